@@ -0,0 +1,105 @@
+//! Token amounts as typed on the command line: whole tokens with an optional
+//! fraction, e.g. `1.5`, converted to base units once the mint's decimals are
+//! known.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Amount {
+    whole: String,
+    fraction: String,
+}
+
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(amount: &str) -> Result<Self, String> {
+        let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+        let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !digits(whole) || !digits(fraction) {
+            return Err(format!("expected a decimal amount, got `{amount}`"));
+        }
+        Ok(Self {
+            whole: whole.to_string(),
+            fraction: fraction.to_string(),
+        })
+    }
+}
+
+impl Amount {
+    /// The amount in base units of a mint with `decimals` decimals. Fails if
+    /// the amount has more decimals than the mint or does not fit a `u64`.
+    pub fn to_base_units(&self, decimals: u8) -> Result<u64> {
+        let decimals = decimals as usize;
+        // Trailing zeros are not precision the mint lacks
+        let fraction = self.fraction.trim_end_matches('0');
+        if fraction.len() > decimals {
+            bail!("{self} has more than {decimals} decimals");
+        }
+        format!("{}{fraction:0<decimals$}", self.whole)
+            .trim_start_matches('0')
+            .parse::<u64>()
+            .or_else(|err| match err.kind() {
+                std::num::IntErrorKind::Empty => Ok(0),
+                _ => Err(anyhow!("{self} does not fit in a u64 of base units")),
+            })
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.fraction.as_str() {
+            "" => write!(f, "{}", self.whole),
+            fraction => write!(f, "{}.{fraction}", self.whole),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn units(amount: &str, decimals: u8) -> Result<u64> {
+        amount.parse::<Amount>().unwrap().to_base_units(decimals)
+    }
+
+    #[test]
+    fn parses_whole_and_fractional_amounts() {
+        assert_eq!(units("1", 6).unwrap(), 1_000_000);
+        assert_eq!(units("1.5", 6).unwrap(), 1_500_000);
+        assert_eq!(units("0.000001", 6).unwrap(), 1);
+        assert_eq!(units(".25", 2).unwrap(), 25);
+        assert_eq!(units("3.", 0).unwrap(), 3);
+        assert_eq!(units("0", 9).unwrap(), 0);
+        assert_eq!(units("0.0", 0).unwrap(), 0);
+        assert_eq!(units("42", 0).unwrap(), 42);
+    }
+
+    #[test]
+    fn ignores_trailing_zeros() {
+        assert_eq!(units("1.500000000", 6).unwrap(), 1_500_000);
+        assert_eq!(units("2.0", 0).unwrap(), 2);
+    }
+
+    #[test]
+    fn rejects_more_decimals_than_the_mint() {
+        assert!(units("1.5", 0).is_err());
+        assert!(units("0.0000001", 6).is_err());
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        assert_eq!(units("18446744073709551615", 0).unwrap(), u64::MAX);
+        assert!(units("18446744073709551616", 0).is_err());
+        assert!(units("18446744073709.551616", 6).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_amounts() {
+        for amount in ["", ".", "-1", "1e6", "1.2.3", "0x10", " 1", "1,5"] {
+            assert!(amount.parse::<Amount>().is_err(), "{amount}");
+        }
+    }
+}
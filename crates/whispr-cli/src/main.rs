@@ -0,0 +1,905 @@
+//! `whispr`: create and operate Whispr pools and trade confidentially from the
+//! command line. Every command prints one JSON object to stdout, an `error`
+//! object if it failed. Token amounts are in whole tokens, e.g. `1.5`, and
+//! converted with the mint's decimals.
+
+mod amount;
+mod rpc;
+
+use std::{process::ExitCode, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use rand::{rngs::OsRng, RngCore};
+use serde_json::{json, Value};
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Signature, Signer},
+};
+use whispr_client::{
+    decode::WhisprEvent,
+    encryption::shared_secret,
+    instructions::{self, EncryptedSwap},
+    pda, Config, Pool, RescueCipher, SwapResult, SwapState, SwapStatus,
+};
+
+use crate::{amount::Amount, rpc::Rpc};
+
+#[derive(Parser)]
+#[command(
+    name = "whispr",
+    version,
+    about = "Operate Whispr pools and trade confidentially"
+)]
+struct Cli {
+    /// Keypair that signs and pays for every transaction
+    #[arg(
+        long,
+        short = 'k',
+        global = true,
+        env = "WHISPR_KEYPAIR",
+        default_value = "~/.config/solana/id.json"
+    )]
+    keypair: String,
+    /// localnet, devnet, testnet, mainnet or an RPC URL
+    #[arg(
+        long,
+        short = 'u',
+        global = true,
+        env = "WHISPR_CLUSTER",
+        default_value = "localnet"
+    )]
+    cluster: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a pool for two mints
+    Init {
+        #[arg(long)]
+        seed: u64,
+        #[arg(long)]
+        mint_x: Pubkey,
+        #[arg(long)]
+        mint_y: Pubkey,
+        /// Swap fee in basis points
+        #[arg(long)]
+        fee: u16,
+        /// Defaults to the signer
+        #[arg(long)]
+        authority: Option<Pubkey>,
+        /// Create the pool without an authority, so it can never be locked
+        #[arg(long, conflicts_with = "authority")]
+        no_authority: bool,
+        /// Seconds after which anyone can cancel a pending confidential swap,
+        /// at least 60
+        #[arg(long)]
+        swap_timeout: i64,
+        #[arg(long)]
+        precision: u8,
+    },
+    /// Show a pool's config and reserves
+    Pool {
+        #[arg(long)]
+        seed: u64,
+    },
+    /// Mint `amount` LP tokens for at most `max_x` and `max_y`
+    Deposit {
+        #[arg(long)]
+        seed: u64,
+        #[arg(long)]
+        amount: Amount,
+        #[arg(long)]
+        max_x: Amount,
+        #[arg(long)]
+        max_y: Amount,
+        /// Unix timestamp after which the deposit fails
+        #[arg(long)]
+        deadline: Option<i64>,
+    },
+    /// Burn `amount` LP tokens for at least `min_x` and `min_y`
+    Withdraw {
+        #[arg(long)]
+        seed: u64,
+        #[arg(long)]
+        amount: Amount,
+        #[arg(long)]
+        min_x: Amount,
+        #[arg(long)]
+        min_y: Amount,
+        #[arg(long)]
+        deadline: Option<i64>,
+    },
+    /// Stop all trading and liquidity changes
+    Lock {
+        #[arg(long)]
+        seed: u64,
+    },
+    Unlock {
+        #[arg(long)]
+        seed: u64,
+    },
+    /// Queue a confidential swap, wait for its callback and decrypt the result
+    Swap {
+        #[arg(long)]
+        seed: u64,
+        /// The side paid in
+        #[arg(long, value_enum)]
+        side: Side,
+        #[arg(long)]
+        amount: Amount,
+        #[arg(long)]
+        min_output: Amount,
+        /// X to escrow, `amount` by default when paying in X. Escrow amounts
+        /// are public: escrowing on both sides, and more than the trade,
+        /// hides its direction and size until it settles, when the transfers
+        /// make both public.
+        #[arg(long)]
+        max_x_in: Option<Amount>,
+        /// Y to escrow, `amount` by default when paying in Y
+        #[arg(long)]
+        max_y_in: Option<Amount>,
+        #[arg(long)]
+        deadline: Option<i64>,
+        /// The MXE's x25519 public key, hex encoded
+        #[arg(long, value_parser = parse_key)]
+        mxe_pubkey: [u8; 32],
+        /// Offset of the Arcium cluster the MXE runs on
+        #[arg(long)]
+        arcium_cluster: u32,
+        /// Seconds to wait for the callback
+        #[arg(long, default_value_t = 120)]
+        timeout: u64,
+    },
+    /// Show a confidential swap's state
+    SwapState {
+        #[arg(long)]
+        seed: u64,
+        #[arg(long)]
+        offset: u64,
+        /// Defaults to the signer
+        #[arg(long)]
+        user: Option<Pubkey>,
+    },
+    /// Refund a confidential swap whose callback never landed
+    CancelSwap {
+        #[arg(long)]
+        seed: u64,
+        #[arg(long)]
+        offset: u64,
+        /// Defaults to the signer
+        #[arg(long)]
+        user: Option<Pubkey>,
+    },
+    /// Reclaim the rent of a settled confidential swap
+    CloseSwap {
+        #[arg(long)]
+        seed: u64,
+        #[arg(long)]
+        offset: u64,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Side {
+    X,
+    Y,
+}
+
+fn main() -> ExitCode {
+    let output = run(Cli::parse());
+    println!("{}", render(&output));
+    match output {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
+    }
+}
+
+fn run(cli: Cli) -> Result<Value> {
+    let keypair = expand_home(&cli.keypair);
+    let signer =
+        read_keypair_file(&keypair).map_err(|err| anyhow!("reading keypair {keypair}: {err}"))?;
+    let rpc = Rpc::new(rpc_url(&cli.cluster), signer);
+    let payer = rpc.signer.pubkey();
+    let decimals = |mint: &Pubkey| rpc.decimals(mint);
+
+    match &cli.command {
+        Command::Init {
+            seed,
+            mint_x,
+            mint_y,
+            ..
+        } => {
+            let pool = rpc.pool_for(*seed, *mint_x, *mint_y)?;
+            let signature = rpc.send(&transaction(&cli.command, &pool, payer, decimals)?)?;
+            Ok(initialized_json(&signature, &pool))
+        }
+        Command::Pool { seed } => {
+            let (pool, config) = rpc.pool(*seed)?;
+            pool_json(&rpc, &pool, &config)
+        }
+        Command::Swap {
+            seed,
+            deadline,
+            mxe_pubkey,
+            arcium_cluster,
+            timeout,
+            ..
+        } => {
+            let (pool, _) = rpc.pool(*seed)?;
+            let amounts = swap_amounts(&cli.command, &pool, decimals)?;
+            confidential_swap(
+                &rpc,
+                &pool,
+                &amounts,
+                *deadline,
+                mxe_pubkey,
+                *arcium_cluster,
+                Duration::from_secs(*timeout),
+            )
+        }
+        Command::SwapState { seed, offset, user } => {
+            let swap_state = pda::swap_state(&pda::config(*seed), &user.unwrap_or(payer), *offset);
+            Ok(swap_json(&swap_state, &rpc.account(&swap_state)?))
+        }
+        command => {
+            let (pool, _) = rpc.pool(command.seed())?;
+            let signature = rpc.send(&transaction(command, &pool, payer, decimals)?)?;
+            Ok(signature_json(&signature))
+        }
+    }
+}
+
+impl Command {
+    fn seed(&self) -> u64 {
+        match self {
+            Command::Init { seed, .. }
+            | Command::Pool { seed }
+            | Command::Deposit { seed, .. }
+            | Command::Withdraw { seed, .. }
+            | Command::Lock { seed }
+            | Command::Unlock { seed }
+            | Command::Swap { seed, .. }
+            | Command::SwapState { seed, .. }
+            | Command::CancelSwap { seed, .. }
+            | Command::CloseSwap { seed, .. } => *seed,
+        }
+    }
+}
+
+/// The instructions of a command that sends one transaction, signed and paid
+/// for by `payer`. `decimals` looks up a mint's decimals to convert amounts.
+fn transaction(
+    command: &Command,
+    pool: &Pool,
+    payer: Pubkey,
+    decimals: impl Fn(&Pubkey) -> Result<u8>,
+) -> Result<Vec<Instruction>> {
+    let units = |amount: &Amount, mint: &Pubkey| amount.to_base_units(decimals(mint)?);
+    Ok(match command {
+        Command::Init {
+            fee,
+            authority,
+            no_authority,
+            swap_timeout,
+            precision,
+            ..
+        } => {
+            let authority = (!no_authority).then(|| authority.unwrap_or(payer));
+            vec![instructions::initialize_amm(
+                pool,
+                payer,
+                *fee,
+                authority,
+                *swap_timeout,
+                *precision,
+            )]
+        }
+        Command::Deposit {
+            amount,
+            max_x,
+            max_y,
+            deadline,
+            ..
+        } => {
+            let mut ixs = instructions::create_native_accounts(pool, payer, payer);
+            ixs.push(instructions::deposit(
+                pool,
+                payer,
+                units(amount, &pool.mint_lp)?,
+                units(max_x, &pool.mint_x)?,
+                units(max_y, &pool.mint_y)?,
+                *deadline,
+            ));
+            ixs
+        }
+        Command::Withdraw {
+            amount,
+            min_x,
+            min_y,
+            deadline,
+            ..
+        } => {
+            let mut ixs = instructions::create_native_accounts(pool, payer, payer);
+            ixs.push(instructions::withdraw(
+                pool,
+                payer,
+                units(amount, &pool.mint_lp)?,
+                units(min_x, &pool.mint_x)?,
+                units(min_y, &pool.mint_y)?,
+                *deadline,
+            ));
+            ixs
+        }
+        Command::Lock { .. } => vec![instructions::lock(pool, payer)],
+        Command::Unlock { .. } => vec![instructions::unlock(pool, payer)],
+        Command::CancelSwap { offset, user, .. } => vec![instructions::cancel_swap(
+            pool,
+            payer,
+            user.unwrap_or(payer),
+            *offset,
+        )],
+        Command::CloseSwap { offset, .. } => vec![instructions::close_swap(pool, payer, *offset)],
+        Command::Pool { .. } | Command::Swap { .. } | Command::SwapState { .. } => {
+            bail!("not a single transaction command")
+        }
+    })
+}
+
+/// A confidential swap's amounts in base units.
+#[derive(Debug, PartialEq, Eq)]
+struct SwapAmounts {
+    is_x: bool,
+    amount: u64,
+    min_output: u64,
+    max_x_in: u64,
+    max_y_in: u64,
+}
+
+/// The amounts of a `swap` command. The input side escrows `amount` unless
+/// told otherwise, the other side nothing.
+fn swap_amounts(
+    command: &Command,
+    pool: &Pool,
+    decimals: impl Fn(&Pubkey) -> Result<u8>,
+) -> Result<SwapAmounts> {
+    let Command::Swap {
+        side,
+        amount,
+        min_output,
+        max_x_in,
+        max_y_in,
+        ..
+    } = command
+    else {
+        bail!("not a swap command")
+    };
+    let units = |amount: &Amount, mint: &Pubkey| amount.to_base_units(decimals(mint)?);
+    let is_x = *side == Side::X;
+    let (mint_in, mint_out) = if is_x {
+        (&pool.mint_x, &pool.mint_y)
+    } else {
+        (&pool.mint_y, &pool.mint_x)
+    };
+    let amount = units(amount, mint_in)?;
+    let escrow = |max_in: &Option<Amount>, mint: &Pubkey, paid_in: bool| match max_in {
+        Some(max_in) => units(max_in, mint),
+        None => Ok(if paid_in { amount } else { 0 }),
+    };
+    Ok(SwapAmounts {
+        is_x,
+        amount,
+        min_output: units(min_output, mint_out)?,
+        max_x_in: escrow(max_x_in, &pool.mint_x, is_x)?,
+        max_y_in: escrow(max_y_in, &pool.mint_y, !is_x)?,
+    })
+}
+
+/// Encrypts the swap under a fresh key and nonce, queues it, waits for the
+/// callback that settles it, decrypts the amounts and closes the swap state.
+fn confidential_swap(
+    rpc: &Rpc,
+    pool: &Pool,
+    amounts: &SwapAmounts,
+    deadline: Option<i64>,
+    mxe_pubkey: &[u8; 32],
+    arcium_cluster: u32,
+    timeout: Duration,
+) -> Result<Value> {
+    let user = rpc.signer.pubkey();
+    let mut private_key = [0u8; 32];
+    OsRng.fill_bytes(&mut private_key);
+    let mut nonce = [0u8; 16];
+    OsRng.fill_bytes(&mut nonce);
+    let computation_offset = OsRng.next_u64();
+
+    let swap = EncryptedSwap::new(
+        &private_key,
+        mxe_pubkey,
+        u128::from_le_bytes(nonce),
+        amounts.is_x,
+        amounts.amount,
+        amounts.min_output,
+    );
+    let mut ixs = instructions::create_native_accounts(pool, user, user);
+    ixs.push(instructions::compute_swap(
+        pool,
+        user,
+        user,
+        pda::arcium::cluster(arcium_cluster),
+        computation_offset,
+        &swap,
+        amounts.max_x_in,
+        amounts.max_y_in,
+        deadline,
+    ));
+    let signature = rpc.send(&ixs)?;
+
+    let swap_state = pda::swap_state(&pool.config, &user, computation_offset);
+    let state = rpc.wait_for_swap(&swap_state, timeout)?;
+    let (callback, event) = rpc.swap_callback(&swap_state, computation_offset)?;
+    let mut output = json!({
+        "signature": signature.to_string(),
+        "callback_signature": callback.to_string(),
+        "swap_state": swap_state.to_string(),
+        "computation_offset": computation_offset,
+        "status": status_name(&state.status),
+    });
+    match event {
+        WhisprEvent::ConfidentialSwapExecutedEvent(event) => {
+            let cipher = RescueCipher::new(&shared_secret(&private_key, mxe_pubkey));
+            let result = SwapResult::decrypt(&cipher, &event)
+                .ok_or_else(|| anyhow!("could not decrypt the swap result"))?;
+            output["deposit_amount"] = json!(result.deposit_amount);
+            output["withdraw_amount"] = json!(result.withdraw_amount);
+        }
+        WhisprEvent::ConfidentialSwapFailedEvent(event) => {
+            output["reason"] = json!(event.reason);
+        }
+        _ => bail!("unexpected callback event {}", event.name()),
+    }
+
+    let close = rpc.send(&[instructions::close_swap(pool, user, computation_offset)])?;
+    output["close_signature"] = json!(close.to_string());
+    Ok(output)
+}
+
+/// Formats a command's output. Every command's stdout goes through here.
+fn render(output: &Result<Value>) -> String {
+    let value = match output {
+        Ok(value) => value.clone(),
+        Err(err) => json!({ "error": format!("{err:#}") }),
+    };
+    serde_json::to_string_pretty(&value).expect("JSON values always serialize")
+}
+
+fn signature_json(signature: &Signature) -> Value {
+    json!({ "signature": signature.to_string() })
+}
+
+fn initialized_json(signature: &Signature, pool: &Pool) -> Value {
+    json!({
+        "signature": signature.to_string(),
+        "config": pool.config.to_string(),
+        "mint_lp": pool.mint_lp.to_string(),
+        "vault_x": pool.vault_x.to_string(),
+        "vault_y": pool.vault_y.to_string(),
+    })
+}
+
+fn pool_json(rpc: &Rpc, pool: &Pool, config: &Config) -> Result<Value> {
+    let balance = |account: &Pubkey| -> Result<String> {
+        Ok(rpc.client.get_token_account_balance(account)?.amount)
+    };
+    Ok(json!({
+        "config": pool.config.to_string(),
+        "seed": config.seed,
+        "authority": config.authority.map(|authority| authority.to_string()),
+        "mint_x": config.mint_x.to_string(),
+        "mint_y": config.mint_y.to_string(),
+        "mint_lp": pool.mint_lp.to_string(),
+        "fee": config.fee,
+        "protocol_fee": config.protocol_fee,
+        "locked": config.locked,
+        "dark": config.dark,
+        "swap_timeout": config.swap_timeout,
+        "reserve_x": balance(&pool.vault_x)?,
+        "reserve_y": balance(&pool.vault_y)?,
+        "lp_supply": rpc.client.get_token_supply(&pool.mint_lp)?.amount,
+    }))
+}
+
+fn swap_json(swap_state: &Pubkey, swap: &SwapState) -> Value {
+    let settled = swap.status == SwapStatus::Executed;
+    json!({
+        "swap_state": swap_state.to_string(),
+        "user": swap.user.to_string(),
+        "config": swap.config.to_string(),
+        "computation_offset": swap.computation_offset,
+        "status": status_name(&swap.status),
+        "created_at": swap.created_at,
+        "deadline": swap.deadline,
+        // Amounts are only written once the callback has settled the swap
+        "deposit_amount": settled.then_some(swap.deposit_amount),
+        "withdraw_amount": settled.then_some(swap.withdraw_amount),
+    })
+}
+
+fn status_name(status: &SwapStatus) -> &'static str {
+    match status {
+        SwapStatus::Initiated => "initiated",
+        SwapStatus::Computing => "computing",
+        SwapStatus::Executed => "executed",
+        SwapStatus::Failed => "failed",
+        SwapStatus::Rejected => "rejected",
+    }
+}
+
+fn rpc_url(cluster: &str) -> String {
+    match cluster {
+        "localnet" => "http://127.0.0.1:8899",
+        "devnet" => "https://api.devnet.solana.com",
+        "testnet" => "https://api.testnet.solana.com",
+        "mainnet" => "https://api.mainnet-beta.solana.com",
+        url => url,
+    }
+    .to_string()
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{home}/{rest}"),
+        _ => path.to_string(),
+    }
+}
+
+fn parse_key(hex: &str) -> Result<[u8; 32], String> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err("expected 32 hex encoded bytes".to_string());
+    }
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|err| err.to_string())?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYER: Pubkey = Pubkey::new_from_array([1; 32]);
+
+    fn pool() -> Pool {
+        Pool::new(
+            7,
+            Pubkey::new_from_array([2; 32]),
+            Pubkey::new_from_array([3; 32]),
+            Pubkey::new_from_array([4; 32]),
+            Pubkey::new_from_array([5; 32]),
+        )
+    }
+
+    // 6 decimals for LP, 9 for X and 2 for Y
+    fn decimals(pool: &Pool) -> impl Fn(&Pubkey) -> Result<u8> + '_ {
+        move |mint: &Pubkey| match *mint {
+            mint if mint == pool.mint_lp => Ok(6),
+            mint if mint == pool.mint_x => Ok(9),
+            mint if mint == pool.mint_y => Ok(2),
+            mint => bail!("unknown mint {mint}"),
+        }
+    }
+
+    fn command(args: &[&str]) -> Command {
+        Cli::try_parse_from([&["whispr"][..], args].concat())
+            .unwrap()
+            .command
+    }
+
+    fn instructions_for(args: &[&str]) -> Result<Vec<Instruction>> {
+        let pool = pool();
+        transaction(&command(args), &pool, PAYER, decimals(&pool))
+    }
+
+    #[test]
+    fn init_authority() {
+        let pool = pool();
+        let (mint_x, mint_y, other) = (
+            pool.mint_x.to_string(),
+            pool.mint_y.to_string(),
+            Pubkey::new_unique(),
+        );
+        let other_arg = other.to_string();
+        let init = [
+            "init",
+            "--seed",
+            "7",
+            "--mint-x",
+            &mint_x,
+            "--mint-y",
+            &mint_y,
+            "--fee",
+            "30",
+            "--swap-timeout",
+            "60",
+            "--precision",
+            "6",
+        ];
+
+        for (extra, authority) in [
+            (&[][..], Some(PAYER)),
+            (&["--authority", &other_arg][..], Some(other)),
+            (&["--no-authority"][..], None),
+        ] {
+            assert_eq!(
+                instructions_for(&[&init[..], extra].concat()).unwrap(),
+                [instructions::initialize_amm(
+                    &pool, PAYER, 30, authority, 60, 6
+                )]
+            );
+        }
+        let conflicting = ["--no-authority", "--authority", &other_arg];
+        assert!(Cli::try_parse_from([&["whispr"][..], &init, &conflicting].concat()).is_err());
+    }
+
+    #[test]
+    fn deposit_and_withdraw_convert_amounts() {
+        let pool = pool();
+        assert_eq!(
+            instructions_for(&[
+                "deposit",
+                "--seed",
+                "7",
+                "--amount",
+                "1.5",
+                "--max-x",
+                "2",
+                "--max-y",
+                "0.25",
+                "--deadline",
+                "100",
+            ])
+            .unwrap(),
+            [instructions::deposit(
+                &pool,
+                PAYER,
+                1_500_000,
+                2_000_000_000,
+                25,
+                Some(100)
+            )]
+        );
+        assert_eq!(
+            instructions_for(&[
+                "withdraw", "--seed", "7", "--amount", "0.000001", "--min-x", "0", "--min-y", "3",
+            ])
+            .unwrap(),
+            [instructions::withdraw(&pool, PAYER, 1, 0, 300, None)]
+        );
+    }
+
+    #[test]
+    fn amounts_beyond_the_mint_decimals_fail() {
+        let err = instructions_for(&[
+            "deposit", "--seed", "7", "--amount", "1", "--max-x", "1", "--max-y", "0.001",
+        ])
+        .unwrap_err();
+        assert_eq!(err.to_string(), "0.001 has more than 2 decimals");
+        assert!(Cli::try_parse_from([
+            "whispr", "deposit", "--seed", "7", "--amount", "1e3", "--max-x", "1", "--max-y", "1",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn native_sides_get_their_wsol_account_first() {
+        let token_program = Pubkey::new_unique();
+        let native_mint = "So11111111111111111111111111111111111111112"
+            .parse()
+            .unwrap();
+        let pool = Pool::new(
+            7,
+            native_mint,
+            Pubkey::new_unique(),
+            token_program,
+            token_program,
+        );
+        let ixs = transaction(
+            &command(&[
+                "withdraw", "--seed", "7", "--amount", "1", "--min-x", "0", "--min-y", "0",
+            ]),
+            &pool,
+            PAYER,
+            |_| Ok(0),
+        )
+        .unwrap();
+        let mut expected = instructions::create_native_accounts(&pool, PAYER, PAYER);
+        expected.push(instructions::withdraw(&pool, PAYER, 1, 0, 0, None));
+        assert_eq!(expected.len(), 2);
+        assert_eq!(ixs, expected);
+    }
+
+    #[test]
+    fn authority_and_swap_commands() {
+        let pool = pool();
+        let user = Pubkey::new_unique().to_string();
+        for (args, expected) in [
+            (
+                vec!["lock", "--seed", "7"],
+                instructions::lock(&pool, PAYER),
+            ),
+            (
+                vec!["unlock", "--seed", "7"],
+                instructions::unlock(&pool, PAYER),
+            ),
+            (
+                vec!["cancel-swap", "--seed", "7", "--offset", "5"],
+                instructions::cancel_swap(&pool, PAYER, PAYER, 5),
+            ),
+            (
+                vec![
+                    "cancel-swap",
+                    "--seed",
+                    "7",
+                    "--offset",
+                    "5",
+                    "--user",
+                    user.as_str(),
+                ],
+                instructions::cancel_swap(&pool, PAYER, user.parse().unwrap(), 5),
+            ),
+            (
+                vec!["close-swap", "--seed", "7", "--offset", "5"],
+                instructions::close_swap(&pool, PAYER, 5),
+            ),
+        ] {
+            assert_eq!(instructions_for(&args).unwrap(), [expected], "{args:?}");
+        }
+        assert!(instructions_for(&["pool", "--seed", "7"]).is_err());
+    }
+
+    fn swap(args: &[&str]) -> Result<SwapAmounts> {
+        let pool = pool();
+        let key = "00".repeat(32);
+        let swap = [
+            "swap",
+            "--seed",
+            "7",
+            "--mxe-pubkey",
+            &key,
+            "--arcium-cluster",
+            "1",
+        ];
+        swap_amounts(
+            &command(&[&swap[..], args].concat()),
+            &pool,
+            decimals(&pool),
+        )
+    }
+
+    #[test]
+    fn swap_escrows_the_input_side_by_default() {
+        assert_eq!(
+            swap(&["--side", "x", "--amount", "1", "--min-output", "0.5"]).unwrap(),
+            SwapAmounts {
+                is_x: true,
+                amount: 1_000_000_000,
+                min_output: 50,
+                max_x_in: 1_000_000_000,
+                max_y_in: 0,
+            }
+        );
+        assert_eq!(
+            swap(&["--side", "y", "--amount", "1", "--min-output", "0.5"]).unwrap(),
+            SwapAmounts {
+                is_x: false,
+                amount: 100,
+                min_output: 500_000_000,
+                max_x_in: 0,
+                max_y_in: 100,
+            }
+        );
+    }
+
+    #[test]
+    fn swap_escrows_what_it_is_told() {
+        assert_eq!(
+            swap(&[
+                "--side",
+                "x",
+                "--amount",
+                "1",
+                "--min-output",
+                "0",
+                "--max-x-in",
+                "3",
+                "--max-y-in",
+                "4",
+            ])
+            .unwrap(),
+            SwapAmounts {
+                is_x: true,
+                amount: 1_000_000_000,
+                min_output: 0,
+                max_x_in: 3_000_000_000,
+                max_y_in: 400,
+            }
+        );
+        assert!(swap(&["--side", "y", "--amount", "0.001", "--min-output", "0"]).is_err());
+    }
+
+    #[test]
+    fn output_shapes() {
+        let signature = Signature::from([9; 64]);
+        assert_eq!(
+            signature_json(&signature),
+            json!({ "signature": signature.to_string() })
+        );
+
+        let pool = pool();
+        assert_eq!(
+            initialized_json(&signature, &pool),
+            json!({
+                "signature": signature.to_string(),
+                "config": pool.config.to_string(),
+                "mint_lp": pool.mint_lp.to_string(),
+                "vault_x": pool.vault_x.to_string(),
+                "vault_y": pool.vault_y.to_string(),
+            })
+        );
+
+        let mut swap = SwapState {
+            user: PAYER,
+            config: pool.config,
+            computation_offset: 5,
+            deposit_amount: 10,
+            withdraw_amount: 20,
+            status: SwapStatus::Computing,
+            created_at: 1,
+            deadline: None,
+            bump: 255,
+        };
+        let address = Pubkey::new_unique();
+        let expected = |status: &str, deposit: Value, withdraw: Value| {
+            json!({
+                "swap_state": address.to_string(),
+                "user": PAYER.to_string(),
+                "config": pool.config.to_string(),
+                "computation_offset": 5,
+                "status": status,
+                "created_at": 1,
+                "deadline": null,
+                "deposit_amount": deposit,
+                "withdraw_amount": withdraw,
+            })
+        };
+        assert_eq!(
+            swap_json(&address, &swap),
+            expected("computing", Value::Null, Value::Null)
+        );
+        swap.status = SwapStatus::Executed;
+        assert_eq!(
+            swap_json(&address, &swap),
+            expected("executed", json!(10), json!(20))
+        );
+    }
+
+    #[test]
+    fn render_prints_one_object() {
+        let rendered = render(&Ok(json!({ "signature": "abc" })));
+        assert_eq!(
+            serde_json::from_str::<Value>(&rendered).unwrap(),
+            json!({ "signature": "abc" })
+        );
+
+        let err = Err(anyhow!("no account").context("fetching mint"));
+        assert_eq!(
+            serde_json::from_str::<Value>(&render(&err)).unwrap(),
+            json!({ "error": "fetching mint: no account" })
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Thin RPC layer: sends instructions signed by the CLI keypair and reads
+//! Whispr accounts and events back.
+
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anchor_lang::AccountDeserialize;
+use anyhow::{anyhow, bail, Context, Result};
+use solana_client::{rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+use solana_transaction_status::UiTransactionEncoding;
+use whispr_client::{
+    decode::{self, WhisprEvent},
+    pda, Config, Pool, SwapState, SwapStatus,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct Rpc {
+    pub client: RpcClient,
+    pub signer: Keypair,
+}
+
+impl Rpc {
+    pub fn new(url: String, signer: Keypair) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(url, CommitmentConfig::confirmed()),
+            signer,
+        }
+    }
+
+    pub fn send(&self, instructions: &[Instruction]) -> Result<Signature> {
+        let blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.signer.pubkey()),
+            &[&self.signer],
+            blockhash,
+        );
+        Ok(self.client.send_and_confirm_transaction(&transaction)?)
+    }
+
+    pub fn account<T: AccountDeserialize>(&self, address: &Pubkey) -> Result<T> {
+        let data = self
+            .client
+            .get_account_data(address)
+            .with_context(|| format!("fetching {address}"))?;
+        decode::account(&data).map_err(|err| anyhow!("decoding {address}: {err}"))
+    }
+
+    /// Loads a pool from its seed, reading each mint's token program from the
+    /// mint's owner.
+    pub fn pool(&self, seed: u64) -> Result<(Pool, Config)> {
+        let config: Config = self.account(&pda::config(seed))?;
+        let pool = self.pool_for(seed, config.mint_x, config.mint_y)?;
+        Ok((pool, config))
+    }
+
+    pub fn decimals(&self, mint: &Pubkey) -> Result<u8> {
+        Ok(self
+            .client
+            .get_token_supply(mint)
+            .with_context(|| format!("fetching mint {mint}"))?
+            .decimals)
+    }
+
+    pub fn pool_for(&self, seed: u64, mint_x: Pubkey, mint_y: Pubkey) -> Result<Pool> {
+        let token_program_x = self.client.get_account(&mint_x)?.owner;
+        let token_program_y = self.client.get_account(&mint_y)?.owner;
+        Ok(Pool::new(
+            seed,
+            mint_x,
+            mint_y,
+            token_program_x,
+            token_program_y,
+        ))
+    }
+
+    /// The Whispr events a confirmed transaction emitted.
+    pub fn events(&self, signature: &Signature) -> Result<Vec<WhisprEvent>> {
+        let transaction = self.client.get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Json),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )?;
+        let logs: Option<Vec<String>> = transaction
+            .transaction
+            .meta
+            .and_then(|meta| meta.log_messages.into());
+        Ok(decode::events_from_logs(&logs.unwrap_or_default()))
+    }
+
+    /// Polls a swap until its callback has landed.
+    pub fn wait_for_swap(&self, swap_state: &Pubkey, timeout: Duration) -> Result<SwapState> {
+        let start = Instant::now();
+        loop {
+            let swap: SwapState = self.account(swap_state)?;
+            if matches!(
+                swap.status,
+                SwapStatus::Executed | SwapStatus::Failed | SwapStatus::Rejected
+            ) {
+                return Ok(swap);
+            }
+            if start.elapsed() >= timeout {
+                bail!(
+                    "no callback for {swap_state} after {}s, run `whispr cancel-swap` once the pool's swap timeout has passed",
+                    timeout.as_secs()
+                );
+            }
+            sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// The callback transaction of a swap and the events it emitted, found
+    /// through the swap state's signature history.
+    pub fn swap_callback(
+        &self,
+        swap_state: &Pubkey,
+        computation_offset: u64,
+    ) -> Result<(Signature, WhisprEvent)> {
+        for status in self.client.get_signatures_for_address(swap_state)? {
+            let signature: Signature = status.signature.parse()?;
+            for event in self.events(&signature)? {
+                let settled = match &event {
+                    WhisprEvent::ConfidentialSwapExecutedEvent(event) => {
+                        event.computation_offset == computation_offset
+                    }
+                    WhisprEvent::ConfidentialSwapFailedEvent(event) => {
+                        event.computation_offset == computation_offset
+                    }
+                    _ => false,
+                };
+                if settled {
+                    return Ok((signature, event));
+                }
+            }
+        }
+        bail!("no callback transaction found for {swap_state}")
+    }
+}
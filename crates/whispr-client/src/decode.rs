@@ -0,0 +1,201 @@
+//! Decoding of program accounts and of the events in transaction logs.
+
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Discriminator};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Decodes a program account such as [`whispr::Config`] or
+/// [`whispr::SwapState`], checking its discriminator.
+pub fn account<T: AccountDeserialize>(data: &[u8]) -> anchor_lang::Result<T> {
+    T::try_deserialize(&mut &data[..])
+}
+
+macro_rules! events {
+    ($($name:ident),* $(,)?) => {
+        /// Every `#[event]` the program emits.
+        pub enum WhisprEvent {
+            $($name(whispr::$name),)*
+        }
+
+        impl WhisprEvent {
+            /// Decodes one event payload, discriminator included. `None` if it is
+            /// not a Whispr event.
+            pub fn decode(data: &[u8]) -> Option<Self> {
+                $(
+                    if let Some(mut payload) = data.strip_prefix(whispr::$name::DISCRIMINATOR) {
+                        return whispr::$name::deserialize(&mut payload).ok().map(Self::$name);
+                    }
+                )*
+                None
+            }
+
+            /// The event's struct name, as it appears in the IDL.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$name(_) => stringify!($name),)*
+                }
+            }
+        }
+    };
+}
+
+events! {
+    InitializeEvent,
+    DepositEvent,
+    WithdrawEvent,
+    SwapEvent,
+    LockEvent,
+    UnlockEvent,
+    SetFeeEvent,
+    SetProtocolFeeEvent,
+    ClaimProtocolFeesEvent,
+    ProposeAuthorityEvent,
+    AcceptAuthorityEvent,
+    RenounceAuthorityEvent,
+    ConfidentialSwapInitiatedEvent,
+    ConfidentialSwapExecutedEvent,
+    ConfidentialSwapCancelledEvent,
+    ConfidentialSwapFailedEvent,
+    ConfidentialLiquidityInitiatedEvent,
+    ConfidentialLiquidityExecutedEvent,
+    ConfidentialLiquidityCancelledEvent,
+    ConfidentialLiquidityFailedEvent,
+    DarkPoolEnabledEvent,
+    DarkPoolDisabledEvent,
+    DarkFundEvent,
+    DarkSwapEvent,
+    DarkWithdrawEvent,
+    DarkPoolFailedEvent,
+}
+
+/// Decodes the events in a transaction's log messages. Only `Program data:`
+/// lines logged while the Whispr program is the innermost invocation count, so
+/// data logged by programs it calls, or that call it, is skipped.
+pub fn events_from_logs<S: AsRef<str>>(logs: &[S]) -> Vec<WhisprEvent> {
+    let program_id = whispr::ID.to_string();
+    // Whether each program on the invocation stack is Whispr
+    let mut stack: Vec<bool> = Vec::new();
+    let mut events = Vec::new();
+
+    for log in logs {
+        let log = log.as_ref();
+        if let Some(data) = log.strip_prefix("Program data: ") {
+            if stack.last() == Some(&true) {
+                if let Some(event) = STANDARD
+                    .decode(data)
+                    .ok()
+                    .and_then(|data| WhisprEvent::decode(&data))
+                {
+                    events.push(event);
+                }
+            }
+        } else if let Some((id, status)) = log
+            .strip_prefix("Program ")
+            .and_then(|rest| rest.split_once(' '))
+        {
+            if status.starts_with("invoke [") {
+                stack.push(id == program_id);
+            } else if status == "success" || status.starts_with("failed") {
+                stack.pop();
+            }
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::{prelude::Pubkey, Event};
+
+    use super::*;
+
+    const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+    const ROUTER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tqUZoK5HPkvqPpkP";
+
+    fn data(event: &impl Event) -> String {
+        format!("Program data: {}", STANDARD.encode(event.data()))
+    }
+
+    fn lock(config: Pubkey) -> whispr::LockEvent {
+        whispr::LockEvent {
+            user: Pubkey::new_unique(),
+            config,
+        }
+    }
+
+    fn configs(events: &[WhisprEvent]) -> Vec<Pubkey> {
+        events
+            .iter()
+            .map(|event| match event {
+                WhisprEvent::LockEvent(event) => event.config,
+                _ => panic!("unexpected {}", event.name()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn top_level_events() {
+        let whispr = whispr::ID.to_string();
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let logs = [
+            format!("Program {whispr} invoke [1]"),
+            "Program log: Instruction: Lock".to_string(),
+            data(&lock(first)),
+            format!("Program {whispr} consumed 4000 of 200000 compute units"),
+            format!("Program {whispr} success"),
+            format!("Program {whispr} invoke [1]"),
+            data(&lock(second)),
+            format!("Program {whispr} success"),
+        ];
+        assert_eq!(configs(&events_from_logs(&logs)), [first, second]);
+    }
+
+    #[test]
+    fn skips_data_logged_by_programs_whispr_calls() {
+        let whispr = whispr::ID.to_string();
+        let (inner, outer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let logs = [
+            format!("Program {whispr} invoke [1]"),
+            format!("Program {TOKEN} invoke [2]"),
+            // Bytes that happen to decode as a Whispr event
+            data(&lock(inner)),
+            format!("Program {TOKEN} success"),
+            data(&lock(outer)),
+            format!("Program {whispr} success"),
+        ];
+        assert_eq!(configs(&events_from_logs(&logs)), [outer]);
+    }
+
+    #[test]
+    fn decodes_whispr_called_through_cpi() {
+        let whispr = whispr::ID.to_string();
+        let (router_data, event) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let logs = [
+            format!("Program {ROUTER} invoke [1]"),
+            data(&lock(router_data)),
+            format!("Program {whispr} invoke [2]"),
+            format!("Program {TOKEN} invoke [3]"),
+            format!("Program {TOKEN} success"),
+            data(&lock(event)),
+            format!("Program {whispr} success"),
+            data(&lock(router_data)),
+            format!("Program {ROUTER} success"),
+        ];
+        assert_eq!(configs(&events_from_logs(&logs)), [event]);
+    }
+
+    #[test]
+    fn failed_invocations_leave_the_stack() {
+        let whispr = whispr::ID.to_string();
+        let event = Pubkey::new_unique();
+        let logs = [
+            format!("Program {whispr} invoke [1]"),
+            format!("Program {TOKEN} invoke [2]"),
+            format!("Program {TOKEN} failed: insufficient funds"),
+            data(&lock(event)),
+            format!("Program {whispr} success"),
+            // Not an event at all
+            "Program data: bm90IGFuIGV2ZW50".to_string(),
+        ];
+        assert_eq!(configs(&events_from_logs(&logs)), [event]);
+    }
+}
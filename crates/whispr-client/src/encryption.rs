@@ -0,0 +1,634 @@
+//! Client-side encryption of confidential instruction inputs.
+//!
+//! A port of `RescueCipher` from `@arcium-hq/client`. The trader derives an
+//! x25519 shared secret with the MXE, hashes it into a key with Rescue-Prime
+//! and encrypts each value in counter mode with the Rescue block cipher over
+//! the Curve25519 base field. Ciphertexts are 32-byte little-endian field
+//! elements, the `[u8; 32]` the program takes as arguments and emits in events.
+
+use num_bigint::BigUint;
+use sha3::{
+    digest::{ExtendableOutput, Update, XofReader},
+    Shake256,
+};
+use x25519_dalek::{x25519, X25519_BASEPOINT_BYTES};
+
+use crate::instructions::{
+    EncryptedDarkWithdraw, EncryptedDeposit, EncryptedSwap, EncryptedWithdraw,
+};
+
+// Values encrypted together share counter blocks of this many field elements
+const BLOCK_SIZE: usize = 5;
+const CIPHER_SECURITY_LEVEL: u32 = 128;
+const HASH_SECURITY_LEVEL: u32 = 256;
+// Rescue-Prime parameters the key derivation hashes with
+const HASH_STATE_SIZE: usize = 12;
+const HASH_CAPACITY: usize = 5;
+
+/// The trader's x25519 public key for `private_key`, passed along with the
+/// ciphertexts so the MXE can derive the same shared secret.
+pub fn public_key(private_key: &[u8; 32]) -> [u8; 32] {
+    x25519(*private_key, X25519_BASEPOINT_BYTES)
+}
+
+/// The x25519 shared secret between the trader and the MXE.
+pub fn shared_secret(private_key: &[u8; 32], mxe_public_key: &[u8; 32]) -> [u8; 32] {
+    x25519(*private_key, *mxe_public_key)
+}
+
+/// Rescue in counter mode, keyed by an x25519 shared secret.
+pub struct RescueCipher {
+    rescue: Rescue,
+}
+
+impl RescueCipher {
+    pub fn new(shared_secret: &[u8; 32]) -> Self {
+        // One-step KDF of NIST SP 800-56C with a single repetition:
+        // H(counter || Z || FixedInfo), where FixedInfo is the key length
+        let key = Rescue::hash(HASH_STATE_SIZE, HASH_CAPACITY).digest(&[
+            BigUint::from(1u8),
+            BigUint::from_bytes_le(shared_secret),
+            BigUint::from(BLOCK_SIZE),
+        ]);
+        Self {
+            rescue: Rescue::cipher(key),
+        }
+    }
+
+    /// Encrypts `plaintext` under `nonce`. Values the circuit reads as one
+    /// struct must be encrypted together, in field order.
+    pub fn encrypt(&self, plaintext: &[u128], nonce: u128) -> Vec<[u8; 32]> {
+        let p = &self.rescue.p;
+        plaintext
+            .iter()
+            .zip(self.keystream(plaintext.len(), nonce))
+            .map(|(value, key)| to_bytes((BigUint::from(*value) + key) % p))
+            .collect()
+    }
+
+    /// Decrypts ciphertexts the MXE encrypted under `nonce`. `None` if a value
+    /// does not fit a `u128`, which means the key or nonce is wrong.
+    pub fn decrypt(&self, ciphertexts: &[[u8; 32]], nonce: u128) -> Option<Vec<u128>> {
+        let p = &self.rescue.p;
+        ciphertexts
+            .iter()
+            .zip(self.keystream(ciphertexts.len(), nonce))
+            .map(|(ciphertext, key)| {
+                let value = (BigUint::from_bytes_le(ciphertext) % p + p - key) % p;
+                u128::try_from(value).ok()
+            })
+            .collect()
+    }
+
+    fn keystream(&self, len: usize, nonce: u128) -> Vec<BigUint> {
+        (0..len.div_ceil(BLOCK_SIZE))
+            .flat_map(|block| {
+                let mut counter = vec![BigUint::default(); BLOCK_SIZE];
+                counter[0] = BigUint::from(nonce);
+                counter[1] = BigUint::from(block);
+                self.rescue.permute(counter)
+            })
+            .take(len)
+            .collect()
+    }
+}
+
+/// A decrypted `SwapResult`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapResult {
+    pub deposit_amount: u64,
+    pub withdraw_amount: u64,
+}
+
+impl SwapResult {
+    /// Decrypts the amounts a `ConfidentialSwapExecutedEvent` settled.
+    pub fn decrypt(
+        cipher: &RescueCipher,
+        event: &whispr::ConfidentialSwapExecutedEvent,
+    ) -> Option<Self> {
+        let amounts =
+            cipher.decrypt(&[event.deposit_amount, event.withdraw_amount], event.nonce)?;
+        Some(Self {
+            deposit_amount: amounts[0].try_into().ok()?,
+            withdraw_amount: amounts[1].try_into().ok()?,
+        })
+    }
+}
+
+impl EncryptedSwap {
+    /// Encrypts `SwapData` for the MXE. `nonce` must never be reused with the
+    /// same key.
+    pub fn new(
+        private_key: &[u8; 32],
+        mxe_public_key: &[u8; 32],
+        nonce: u128,
+        is_x: bool,
+        amount: u64,
+        min_output: u64,
+    ) -> Self {
+        let cipher = RescueCipher::new(&shared_secret(private_key, mxe_public_key));
+        let ciphertexts =
+            cipher.encrypt(&[is_x as u128, amount as u128, min_output as u128], nonce);
+        Self {
+            pub_key: public_key(private_key),
+            nonce,
+            is_x: ciphertexts[0],
+            amount: ciphertexts[1],
+            min_output: ciphertexts[2],
+        }
+    }
+}
+
+impl EncryptedDeposit {
+    /// Encrypts `DepositData` for the MXE. `nonce` must never be reused with
+    /// the same key.
+    pub fn new(
+        private_key: &[u8; 32],
+        mxe_public_key: &[u8; 32],
+        nonce: u128,
+        lp_amount: u64,
+        max_x: u64,
+        max_y: u64,
+    ) -> Self {
+        let cipher = RescueCipher::new(&shared_secret(private_key, mxe_public_key));
+        let ciphertexts = cipher.encrypt(&[lp_amount as u128, max_x as u128, max_y as u128], nonce);
+        Self {
+            pub_key: public_key(private_key),
+            nonce,
+            lp_amount: ciphertexts[0],
+            max_x: ciphertexts[1],
+            max_y: ciphertexts[2],
+        }
+    }
+}
+
+impl EncryptedWithdraw {
+    /// Encrypts `WithdrawData` for the MXE. `nonce` must never be reused with
+    /// the same key.
+    pub fn new(
+        private_key: &[u8; 32],
+        mxe_public_key: &[u8; 32],
+        nonce: u128,
+        lp_amount: u64,
+        min_x: u64,
+        min_y: u64,
+    ) -> Self {
+        let cipher = RescueCipher::new(&shared_secret(private_key, mxe_public_key));
+        let ciphertexts = cipher.encrypt(&[lp_amount as u128, min_x as u128, min_y as u128], nonce);
+        Self {
+            pub_key: public_key(private_key),
+            nonce,
+            lp_amount: ciphertexts[0],
+            min_x: ciphertexts[1],
+            min_y: ciphertexts[2],
+        }
+    }
+}
+
+impl EncryptedDarkWithdraw {
+    /// Encrypts `DarkWithdrawData` for the MXE. `nonce` must never be reused
+    /// with the same key.
+    pub fn new(
+        private_key: &[u8; 32],
+        mxe_public_key: &[u8; 32],
+        nonce: u128,
+        x_amount: u64,
+        y_amount: u64,
+    ) -> Self {
+        let cipher = RescueCipher::new(&shared_secret(private_key, mxe_public_key));
+        let ciphertexts = cipher.encrypt(&[x_amount as u128, y_amount as u128], nonce);
+        Self {
+            pub_key: public_key(private_key),
+            nonce,
+            x_amount: ciphertexts[0],
+            y_amount: ciphertexts[1],
+        }
+    }
+}
+
+fn to_bytes(value: BigUint) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    let le = value.to_bytes_le();
+    bytes[..le.len()].copy_from_slice(&le);
+    bytes
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Cipher,
+    Hash { capacity: usize },
+}
+
+/// The Rescue permutation over F_p, p = 2^255 - 19, either as a keyed block
+/// cipher or as the Rescue-Prime sponge permutation.
+/// See https://eprint.iacr.org/2019/426 and https://eprint.iacr.org/2020/1143.
+struct Rescue {
+    p: BigUint,
+    mode: Mode,
+    m: usize,
+    // The smallest prime not dividing p - 1, and its inverse modulo p - 1
+    alpha: BigUint,
+    alpha_inverse: BigUint,
+    rounds: usize,
+    mds: Vec<Vec<BigUint>>,
+    round_keys: Vec<Vec<BigUint>>,
+}
+
+impl Rescue {
+    fn cipher(key: Vec<BigUint>) -> Self {
+        let mut rescue = Self::new(Mode::Cipher, key.len());
+        let constants = rescue.sample_constants();
+        // The key schedule runs the key itself through the permutation
+        rescue.round_keys = rescue.permutation(&constants, key);
+        rescue
+    }
+
+    fn hash(m: usize, capacity: usize) -> Self {
+        let mut rescue = Self::new(Mode::Hash { capacity }, m);
+        rescue.round_keys = rescue.sample_constants();
+        rescue
+    }
+
+    fn new(mode: Mode, m: usize) -> Self {
+        let p = (BigUint::from(1u8) << 255u32) - 19u8;
+        let p_minus_one = &p - 1u8;
+        let alpha = [2u8, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]
+            .into_iter()
+            .map(BigUint::from)
+            .find(|a| (&p_minus_one % a) != BigUint::default())
+            .expect("no small alpha");
+        // alpha * alpha_inverse = k * (p - 1) + 1 for some k < alpha
+        let alpha_inverse = (0u8..)
+            .map(|k| &p_minus_one * k + 1u8)
+            .find(|n| (n % &alpha) == BigUint::default())
+            .map(|n| n / &alpha)
+            .unwrap();
+        let rounds = rounds(&p, mode, &alpha, m);
+        let mds = (1..=m)
+            .map(|i| {
+                (1..=m)
+                    .map(|j| inverse(&BigUint::from(i + j), &p))
+                    .collect()
+            })
+            .collect();
+        Self {
+            p,
+            mode,
+            m,
+            alpha,
+            alpha_inverse,
+            rounds,
+            mds,
+            round_keys: Vec::new(),
+        }
+    }
+
+    /// Round constants, sampled from SHAKE256 with 16 extra bytes per element
+    /// to keep them statistically close to uniform.
+    fn sample_constants(&self) -> Vec<Vec<BigUint>> {
+        let m = self.m;
+        let mut hasher = Shake256::default();
+        match self.mode {
+            Mode::Cipher => hasher.update(b"encrypt everything, compute anything"),
+            Mode::Hash { capacity } => hasher.update(
+                format!(
+                    "Rescue-XLIX({},{},{},{})",
+                    self.p, m, capacity, HASH_SECURITY_LEVEL
+                )
+                .as_bytes(),
+            ),
+        }
+        let mut reader = hasher.finalize_xof();
+        let mut sample = || {
+            let mut buffer = [0u8; 32 + 16];
+            reader.read(&mut buffer);
+            BigUint::from_bytes_le(&buffer) % &self.p
+        };
+
+        match self.mode {
+            Mode::Cipher => {
+                // An affine recurrence c[r + 1] = M * c[r] + b with invertible M
+                let mut matrix: Vec<Vec<BigUint>> =
+                    (0..m).map(|_| (0..m).map(|_| sample()).collect()).collect();
+                let initial: Vec<BigUint> = (0..m).map(|_| sample()).collect();
+                let affine: Vec<BigUint> = (0..m).map(|_| sample()).collect();
+                while !invertible(&matrix, &self.p) {
+                    matrix = (0..m).map(|_| (0..m).map(|_| sample()).collect()).collect();
+                }
+                let mut constants = vec![initial];
+                for r in 0..2 * self.rounds {
+                    let next = self.add(mat_mul(&matrix, &constants[r], &self.p), &affine);
+                    constants.push(next);
+                }
+                constants
+            }
+            Mode::Hash { .. } => {
+                // A leading zero key makes the permutation Algorithm 3 of
+                // Rescue-Prime, which starts without a key addition
+                let mut constants = vec![vec![BigUint::default(); m]];
+                for _ in 0..2 * self.rounds {
+                    constants.push((0..m).map(|_| sample()).collect());
+                }
+                constants
+            }
+        }
+    }
+
+    /// Every intermediate state of the permutation under `keys`.
+    fn permutation(&self, keys: &[Vec<BigUint>], state: Vec<BigUint>) -> Vec<Vec<BigUint>> {
+        let (even, odd) = match self.mode {
+            Mode::Cipher => (&self.alpha_inverse, &self.alpha),
+            Mode::Hash { .. } => (&self.alpha, &self.alpha_inverse),
+        };
+        let mut states = vec![self.add(state, &keys[0])];
+        for (r, key) in keys[1..].iter().enumerate() {
+            let exponent = if r % 2 == 0 { even } else { odd };
+            let sboxed: Vec<BigUint> = states[r]
+                .iter()
+                .map(|x| x.modpow(exponent, &self.p))
+                .collect();
+            let next = self.add(mat_mul(&self.mds, &sboxed, &self.p), key);
+            states.push(next);
+        }
+        states
+    }
+
+    fn permute(&self, state: Vec<BigUint>) -> Vec<BigUint> {
+        self.permutation(&self.round_keys, state)
+            .pop()
+            .expect("at least one state")
+    }
+
+    /// Rescue-Prime sponge, padded with a single 1 and then zeros to the rate.
+    fn digest(&self, message: &[BigUint]) -> Vec<BigUint> {
+        let Mode::Hash { capacity } = self.mode else {
+            unreachable!("digest on a cipher");
+        };
+        let rate = self.m - capacity;
+        let mut padded = message.to_vec();
+        padded.push(BigUint::from(1u8));
+        padded.resize(padded.len().div_ceil(rate) * rate, BigUint::default());
+
+        let mut state = vec![BigUint::default(); self.m];
+        for chunk in padded.chunks(rate) {
+            let mut block = chunk.to_vec();
+            block.resize(self.m, BigUint::default());
+            state = self.permute(self.add(state, &block));
+        }
+        state.truncate(capacity);
+        state
+    }
+
+    fn add(&self, a: Vec<BigUint>, b: &[BigUint]) -> Vec<BigUint> {
+        a.into_iter()
+            .zip(b)
+            .map(|(x, y)| (x + y) % &self.p)
+            .collect()
+    }
+}
+
+/// Rounds for 128-bit security against the attacks of the Rescue paper, and
+/// for the hash the Gröbner basis bound with a 50% margin.
+fn rounds(p: &BigUint, mode: Mode, alpha: &BigUint, m: usize) -> usize {
+    let alpha = u32::try_from(alpha).expect("small alpha");
+    match mode {
+        Mode::Cipher => {
+            let bits = (2 * CIPHER_SECURITY_LEVEL) as f64;
+            let l0 = (bits / ((m + 1) as f64 * (p.bits() as f64 - f64::from(alpha - 1).log2())))
+                .ceil() as usize;
+            let l1 = if alpha == 3 {
+                ((CIPHER_SECURITY_LEVEL + 2) as f64 / (4 * m) as f64).ceil()
+            } else {
+                ((CIPHER_SECURITY_LEVEL + 3) as f64 / (5.5 * m as f64)).ceil()
+            } as usize;
+            2 * l0.max(l1).max(5)
+        }
+        Mode::Hash { capacity } => {
+            let rate = m - capacity;
+            let dcon =
+                |n: usize| (0.5 * f64::from(alpha - 1) * (m * (n - 1)) as f64 + 2.0) as usize;
+            let v = |n: usize| m * (n - 1) + rate;
+            let target = BigUint::from(1u8) << HASH_SECURITY_LEVEL;
+            let mut l1 = 1;
+            let mut tmp = binomial(v(l1) + dcon(l1), v(l1));
+            while &tmp * &tmp <= target && l1 <= 23 {
+                l1 += 1;
+                tmp = binomial(v(l1) + dcon(l1), v(l1));
+            }
+            (1.5 * l1.max(5) as f64).ceil() as usize
+        }
+    }
+}
+
+fn binomial(n: usize, k: usize) -> BigUint {
+    (0..k).fold(BigUint::from(1u8), |acc, i| acc * (n - i) / (i + 1))
+}
+
+fn inverse(a: &BigUint, p: &BigUint) -> BigUint {
+    a.modpow(&(p - 2u8), p)
+}
+
+fn mat_mul(matrix: &[Vec<BigUint>], vector: &[BigUint], p: &BigUint) -> Vec<BigUint> {
+    matrix
+        .iter()
+        .map(|row| row.iter().zip(vector).map(|(a, b)| a * b).sum::<BigUint>() % p)
+        .collect()
+}
+
+/// Whether `matrix` has a non-zero determinant, by Gaussian elimination.
+fn invertible(matrix: &[Vec<BigUint>], p: &BigUint) -> bool {
+    let zero = BigUint::default();
+    let mut rows = matrix.to_vec();
+    let n = rows.len();
+    for col in 0..n {
+        let Some(pivot) = (col..n).find(|&r| rows[r][col] != zero) else {
+            return false;
+        };
+        rows.swap(col, pivot);
+        let (above, below) = rows.split_at_mut(col + 1);
+        let pivot_row = &above[col];
+        let pivot_inverse = inverse(&pivot_row[col], p);
+        for row in below {
+            let factor = &row[col] * &pivot_inverse % p;
+            for (x, y) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *x = (&*x + p - &factor * y % p) % p;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn hex32(s: &str) -> [u8; 32] {
+        hex(s).try_into().unwrap()
+    }
+
+    // RFC 7748, section 6.1
+    const ALICE_PRIVATE: &str = "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a";
+    const ALICE_PUBLIC: &str = "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a";
+    const BOB_PRIVATE: &str = "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb";
+    const BOB_PUBLIC: &str = "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f";
+    const SHARED: &str = "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742";
+
+    #[test]
+    fn x25519_vectors() {
+        assert_eq!(public_key(&hex32(ALICE_PRIVATE)), hex32(ALICE_PUBLIC));
+        assert_eq!(public_key(&hex32(BOB_PRIVATE)), hex32(BOB_PUBLIC));
+        assert_eq!(
+            shared_secret(&hex32(ALICE_PRIVATE), &hex32(BOB_PUBLIC)),
+            hex32(SHARED)
+        );
+        assert_eq!(
+            shared_secret(&hex32(BOB_PRIVATE), &hex32(ALICE_PUBLIC)),
+            hex32(SHARED)
+        );
+    }
+
+    #[test]
+    fn parameters() {
+        let cipher = Rescue::new(Mode::Cipher, BLOCK_SIZE);
+        assert_eq!(cipher.alpha, BigUint::from(5u8));
+        assert_eq!(
+            &cipher.alpha * &cipher.alpha_inverse % (&cipher.p - 1u8),
+            BigUint::from(1u8)
+        );
+        assert_eq!(cipher.rounds, 10);
+        assert_eq!(
+            Rescue::new(
+                Mode::Hash {
+                    capacity: HASH_CAPACITY
+                },
+                HASH_STATE_SIZE
+            )
+            .rounds,
+            8
+        );
+    }
+
+    // Written by scripts/cipher_vectors.ts from @arcium-hq/client's
+    // RescueCipher, so key derivation, round constants and counter layout are
+    // pinned to the implementation the MXE interoperates with
+    const CIPHER_VECTORS: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/cipher_vectors.json");
+
+    #[test]
+    fn cipher_vectors() {
+        // Only a disagreement with the vectors fails, not their absence
+        let Ok(fixture) = std::fs::read_to_string(CIPHER_VECTORS) else {
+            eprintln!(
+                "skipping: {CIPHER_VECTORS} is missing, generate it with `yarn cipher-vectors`"
+            );
+            return;
+        };
+        let fixture: serde_json::Value = serde_json::from_str(&fixture).unwrap();
+        let shared_secret = hex32(fixture["shared_secret"].as_str().unwrap());
+        assert_eq!(shared_secret, hex32(SHARED));
+
+        let cipher = RescueCipher::new(&shared_secret);
+        let cases = fixture["cases"].as_array().unwrap();
+        assert!(!cases.is_empty());
+        for case in cases {
+            let nonce = hex(case["nonce"].as_str().unwrap()).try_into().unwrap();
+            let nonce = u128::from_le_bytes(nonce);
+            let plaintext: Vec<u128> = case["plaintext"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|value| value.as_str().unwrap().parse().unwrap())
+                .collect();
+            let ciphertexts: Vec<[u8; 32]> = case["ciphertexts"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|value| hex32(value.as_str().unwrap()))
+                .collect();
+
+            assert_eq!(cipher.encrypt(&plaintext, nonce), ciphertexts);
+            assert_eq!(cipher.decrypt(&ciphertexts, nonce), Some(plaintext));
+        }
+    }
+
+    #[test]
+    fn round_trip_across_blocks() {
+        let cipher = RescueCipher::new(&hex32(SHARED));
+        let plaintext: Vec<u128> = (0..12).map(|i| u128::from(u64::MAX) + i).collect();
+        let ciphertexts = cipher.encrypt(&plaintext, 7);
+        assert_eq!(cipher.decrypt(&ciphertexts, 7).as_ref(), Some(&plaintext));
+        assert_ne!(cipher.decrypt(&ciphertexts, 8).as_ref(), Some(&plaintext));
+        // The same value encrypts differently at each counter position
+        let repeated = cipher.encrypt(&[42; 6], 7);
+        assert_ne!(repeated[0], repeated[5]);
+    }
+
+    #[test]
+    fn swap_round_trip() {
+        let swap = EncryptedSwap::new(
+            &hex32(ALICE_PRIVATE),
+            &hex32(BOB_PUBLIC),
+            99,
+            true,
+            500,
+            450,
+        );
+        assert_eq!(swap.pub_key, hex32(ALICE_PUBLIC));
+        // The MXE side derives the same key from the trader's public key
+        let mxe = RescueCipher::new(&shared_secret(&hex32(BOB_PRIVATE), &swap.pub_key));
+        assert_eq!(
+            mxe.decrypt(&[swap.is_x, swap.amount, swap.min_output], swap.nonce),
+            Some(vec![1, 500, 450])
+        );
+
+        let result = mxe.encrypt(&[500, 449], 100);
+        let event = whispr::ConfidentialSwapExecutedEvent {
+            user: Default::default(),
+            config: Default::default(),
+            computation_offset: 0,
+            deposit_amount: result[0],
+            withdraw_amount: result[1],
+            nonce: 100,
+        };
+        let trader = RescueCipher::new(&shared_secret(&hex32(ALICE_PRIVATE), &hex32(BOB_PUBLIC)));
+        assert_eq!(
+            SwapResult::decrypt(&trader, &event),
+            Some(SwapResult {
+                deposit_amount: 500,
+                withdraw_amount: 449
+            })
+        );
+    }
+
+    #[test]
+    fn liquidity_and_dark_round_trip() {
+        let (alice, bob) = (hex32(ALICE_PRIVATE), hex32(BOB_PUBLIC));
+        let mxe = RescueCipher::new(&shared_secret(&hex32(BOB_PRIVATE), &hex32(ALICE_PUBLIC)));
+
+        let deposit = EncryptedDeposit::new(&alice, &bob, 1, 10, 20, 30);
+        assert_eq!(deposit.pub_key, hex32(ALICE_PUBLIC));
+        assert_eq!(
+            mxe.decrypt(&[deposit.lp_amount, deposit.max_x, deposit.max_y], 1),
+            Some(vec![10, 20, 30])
+        );
+
+        let withdraw = EncryptedWithdraw::new(&alice, &bob, 2, 10, 5, 6);
+        assert_eq!(
+            mxe.decrypt(&[withdraw.lp_amount, withdraw.min_x, withdraw.min_y], 2),
+            Some(vec![10, 5, 6])
+        );
+
+        let dark = EncryptedDarkWithdraw::new(&alice, &bob, 3, 7, u64::MAX);
+        assert_eq!(
+            mxe.decrypt(&[dark.x_amount, dark.y_amount], 3),
+            Some(vec![7, u64::MAX.into()])
+        );
+    }
+}
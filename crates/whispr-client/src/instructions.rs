@@ -0,0 +1,1654 @@
+//! Instruction builders, one for each instruction a client sends.
+//!
+//! Confidential swaps, deposits and withdrawals settle inside their
+//! `*_callback` instructions, which only the Arcium cluster invokes, so there
+//! is no separate execute instruction to build: `cancel_swap`,
+//! `cancel_deposit` and `cancel_withdraw` refund one whose callback never
+//! landed, and `close_swap` and `close_liquidity` reclaim the rent of a
+//! settled one.
+
+use anchor_lang::{
+    prelude::Pubkey,
+    solana_program::{
+        instruction::{AccountMeta, Instruction},
+        system_program,
+    },
+    InstructionData, ToAccountMetas,
+};
+use anchor_spl::{
+    associated_token::spl_associated_token_account::instruction::create_associated_token_account_idempotent,
+    token::spl_token::native_mint,
+};
+use arcium_anchor::prelude::{ARCIUM_CLOCK_ACCOUNT_ADDRESS, ARCIUM_FEE_POOL_ACCOUNT_ADDRESS};
+
+use crate::{
+    pda::{self, arcium::ARCIUM_PROGRAM_ID},
+    Pool,
+};
+
+/// Encrypted inputs of a confidential swap, in `SwapData` field order. See
+/// [`EncryptedSwap::new`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncryptedSwap {
+    // The trader's x25519 public key the MXE shares a secret with
+    pub pub_key: [u8; 32],
+    pub nonce: u128,
+    pub is_x: [u8; 32],
+    pub amount: [u8; 32],
+    pub min_output: [u8; 32],
+}
+
+/// Encrypted inputs of a confidential deposit, in `DepositData` field order.
+/// See [`EncryptedDeposit::new`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncryptedDeposit {
+    pub pub_key: [u8; 32],
+    pub nonce: u128,
+    pub lp_amount: [u8; 32],
+    pub max_x: [u8; 32],
+    pub max_y: [u8; 32],
+}
+
+/// Encrypted inputs of a confidential withdrawal, in `WithdrawData` field
+/// order. See [`EncryptedWithdraw::new`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncryptedWithdraw {
+    pub pub_key: [u8; 32],
+    pub nonce: u128,
+    pub lp_amount: [u8; 32],
+    pub min_x: [u8; 32],
+    pub min_y: [u8; 32],
+}
+
+/// Encrypted amounts of a dark pool withdrawal, in `DarkWithdrawData` field
+/// order. See [`EncryptedDarkWithdraw::new`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncryptedDarkWithdraw {
+    pub pub_key: [u8; 32],
+    pub nonce: u128,
+    pub x_amount: [u8; 32],
+    pub y_amount: [u8; 32],
+}
+
+/// Creates the user's wSOL account for each native SOL side of the pool, if it
+/// does not exist. The program only wraps into and unwraps out of an existing
+/// account, and closes it when unwrapping, so prepend these to any instruction
+/// moving the user's tokens. Empty for pools without a native side.
+pub fn create_native_accounts(pool: &Pool, payer: Pubkey, user: Pubkey) -> Vec<Instruction> {
+    [
+        (pool.mint_x, pool.token_program_x),
+        (pool.mint_y, pool.token_program_y),
+    ]
+    .into_iter()
+    .filter(|(mint, _)| *mint == native_mint::ID)
+    .map(|(mint, token_program)| {
+        create_associated_token_account_idempotent(&payer, &user, &mint, &token_program)
+    })
+    .collect()
+}
+
+pub fn initialize_amm(
+    pool: &Pool,
+    admin: Pubkey,
+    fee: u16,
+    authority: Option<Pubkey>,
+    swap_timeout: i64,
+    precision: u8,
+) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::InitializeAmm {
+            admin,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            mint_lp: pool.mint_lp,
+            locked_lp: pool.locked_lp,
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            protocol_x: pool.protocol_x,
+            protocol_y: pool.protocol_y,
+            config: pool.config,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            token_program: anchor_spl::token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::InitializeAmm {
+            seed: pool.seed,
+            fee,
+            authority,
+            swap_timeout,
+            precision,
+        }
+        .data(),
+    }
+}
+
+pub fn deposit(
+    pool: &Pool,
+    user: Pubkey,
+    amount: u64,
+    max_x: u64,
+    max_y: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::Deposit {
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            mint_lp: pool.mint_lp,
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            user_x,
+            user_y,
+            user_lp,
+            locked_lp: pool.locked_lp,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            token_program: anchor_spl::token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::Deposit {
+            amount,
+            max_x,
+            max_y,
+            deadline,
+        }
+        .data(),
+    }
+}
+
+pub fn withdraw(
+    pool: &Pool,
+    user: Pubkey,
+    amount: u64,
+    min_x: u64,
+    min_y: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::Withdraw {
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            mint_lp: pool.mint_lp,
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            user_x,
+            user_y,
+            user_lp,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            token_program: anchor_spl::token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::Withdraw {
+            amount,
+            min_x,
+            min_y,
+            deadline,
+        }
+        .data(),
+    }
+}
+
+pub fn swap(
+    pool: &Pool,
+    user: Pubkey,
+    is_x: bool,
+    amount: u64,
+    min_out: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    let (user_x, user_y, _) = pool.user_tokens(&user);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::Swap {
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            mint_lp: pool.mint_lp,
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            protocol_x: pool.protocol_x,
+            protocol_y: pool.protocol_y,
+            user_x,
+            user_y,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::Swap {
+            is_x,
+            amount,
+            min_out,
+            deadline,
+        }
+        .data(),
+    }
+}
+
+/// Deposits X only when `is_x`, otherwise Y only. The program swaps part of
+/// `amount_in` for the other side before minting at least `min_lp`.
+pub fn deposit_single(
+    pool: &Pool,
+    user: Pubkey,
+    is_x: bool,
+    amount_in: u64,
+    min_lp: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: zap_accounts(pool, user),
+        data: whispr::instruction::DepositSingle {
+            is_x,
+            amount_in,
+            min_lp,
+            deadline,
+        }
+        .data(),
+    }
+}
+
+/// Burns `lp_amount` and pays the whole withdrawal out in X when `is_x`,
+/// otherwise in Y.
+pub fn withdraw_single(
+    pool: &Pool,
+    user: Pubkey,
+    is_x: bool,
+    lp_amount: u64,
+    min_out: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: zap_accounts(pool, user),
+        data: whispr::instruction::WithdrawSingle {
+            is_x,
+            lp_amount,
+            min_out,
+            deadline,
+        }
+        .data(),
+    }
+}
+
+fn zap_accounts(pool: &Pool, user: Pubkey) -> Vec<AccountMeta> {
+    let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+    whispr::accounts::Zap {
+        user,
+        mint_x: pool.mint_x,
+        mint_y: pool.mint_y,
+        config: pool.config,
+        mint_lp: pool.mint_lp,
+        vault_x: pool.vault_x,
+        vault_y: pool.vault_y,
+        protocol_x: pool.protocol_x,
+        protocol_y: pool.protocol_y,
+        user_x,
+        user_y,
+        user_lp,
+        token_program_x: pool.token_program_x,
+        token_program_y: pool.token_program_y,
+        token_program: anchor_spl::token::ID,
+        associated_token_program: anchor_spl::associated_token::ID,
+        system_program: system_program::ID,
+    }
+    .to_account_metas(None)
+}
+
+/// Queues a confidential swap. `cluster` is the MXE's cluster account, see
+/// [`pda::arcium::cluster`]. `max_x_in` and `max_y_in` are escrowed in the
+/// clear, so they publicly bound the size of the trade.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_swap(
+    pool: &Pool,
+    payer: Pubkey,
+    user: Pubkey,
+    cluster: Pubkey,
+    computation_offset: u64,
+    swap: &EncryptedSwap,
+    max_x_in: u64,
+    max_y_in: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    let (user_x, user_y, _) = pool.user_tokens(&user);
+    let swap_state = pda::swap_state(&pool.config, &user, computation_offset);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::ComputeSwap {
+            payer,
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            protocol_x: pool.protocol_x,
+            protocol_y: pool.protocol_y,
+            swap_state,
+            escrow_x: pda::escrow_x(&swap_state),
+            escrow_y: pda::escrow_y(&swap_state),
+            user_x,
+            user_y,
+            mxe_account: pda::arcium::mxe(),
+            mempool_account: pda::arcium::mempool(),
+            executing_pool: pda::arcium::execpool(),
+            computation_account: pda::arcium::computation(computation_offset),
+            comp_def_account: pda::arcium::comp_def(Circuit::ComputeSwap.name()),
+            cluster_account: cluster,
+            pool_account: ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+            clock_account: ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            arcium_program: ARCIUM_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::ComputeSwap {
+            computation_offset,
+            pub_key: swap.pub_key,
+            nonce: swap.nonce,
+            encrypted_is_x: swap.is_x,
+            encrypted_amount: swap.amount,
+            encrypted_min_output: swap.min_output,
+            max_x_in,
+            max_y_in,
+            deadline,
+        }
+        .data(),
+    }
+}
+
+/// Refunds an expired confidential swap. Anyone can send it once the pool's
+/// swap timeout has passed.
+pub fn cancel_swap(
+    pool: &Pool,
+    caller: Pubkey,
+    user: Pubkey,
+    computation_offset: u64,
+) -> Instruction {
+    let (user_x, user_y, _) = pool.user_tokens(&user);
+    let swap_state = pda::swap_state(&pool.config, &user, computation_offset);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::CancelSwap {
+            caller,
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            swap_state,
+            escrow_x: pda::escrow_x(&swap_state),
+            escrow_y: pda::escrow_y(&swap_state),
+            user_x,
+            user_y,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::CancelSwap {}.data(),
+    }
+}
+
+pub fn close_swap(pool: &Pool, user: Pubkey, computation_offset: u64) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::CloseSwap {
+            user,
+            config: pool.config,
+            swap_state: pda::swap_state(&pool.config, &user, computation_offset),
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::CloseSwap {}.data(),
+    }
+}
+
+pub fn lock(pool: &Pool, authority: Pubkey) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: update_accounts(pool, authority),
+        data: whispr::instruction::Lock {}.data(),
+    }
+}
+
+pub fn unlock(pool: &Pool, authority: Pubkey) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: update_accounts(pool, authority),
+        data: whispr::instruction::Unlock {}.data(),
+    }
+}
+
+pub fn set_fee(pool: &Pool, authority: Pubkey, fee: u16) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: update_accounts(pool, authority),
+        data: whispr::instruction::SetFee { fee }.data(),
+    }
+}
+
+pub fn set_protocol_fee(pool: &Pool, authority: Pubkey, protocol_fee: u16) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: update_accounts(pool, authority),
+        data: whispr::instruction::SetProtocolFee { protocol_fee }.data(),
+    }
+}
+
+/// Sends the accrued protocol fees to the authority's associated token
+/// accounts.
+pub fn claim_protocol_fees(pool: &Pool, authority: Pubkey) -> Instruction {
+    let (user_x, user_y, _) = pool.user_tokens(&authority);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::ClaimProtocolFees {
+            user: authority,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            protocol_x: pool.protocol_x,
+            protocol_y: pool.protocol_y,
+            user_x,
+            user_y,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::ClaimProtocolFees {}.data(),
+    }
+}
+
+pub fn propose_authority(pool: &Pool, authority: Pubkey, new_authority: Pubkey) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: update_accounts(pool, authority),
+        data: whispr::instruction::ProposeAuthority { new_authority }.data(),
+    }
+}
+
+/// Sent by the proposed authority.
+pub fn accept_authority(pool: &Pool, new_authority: Pubkey) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: update_accounts(pool, new_authority),
+        data: whispr::instruction::AcceptAuthority {}.data(),
+    }
+}
+
+pub fn renounce_authority(pool: &Pool, authority: Pubkey) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: update_accounts(pool, authority),
+        data: whispr::instruction::RenounceAuthority {}.data(),
+    }
+}
+
+fn update_accounts(pool: &Pool, user: Pubkey) -> Vec<AccountMeta> {
+    whispr::accounts::Update {
+        user,
+        config: pool.config,
+    }
+    .to_account_metas(None)
+}
+
+/// The program's circuits, each initialized once per deployment with
+/// [`init_comp_def`] before the instructions queueing it can be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Circuit {
+    ComputeSwap,
+    ComputeDeposit,
+    ComputeWithdraw,
+    SealReserves,
+    RevealReserves,
+    DarkFund,
+    DarkSwap,
+    DarkWithdraw,
+}
+
+impl Circuit {
+    pub const ALL: [Circuit; 8] = [
+        Circuit::ComputeSwap,
+        Circuit::ComputeDeposit,
+        Circuit::ComputeWithdraw,
+        Circuit::SealReserves,
+        Circuit::RevealReserves,
+        Circuit::DarkFund,
+        Circuit::DarkSwap,
+        Circuit::DarkWithdraw,
+    ];
+
+    /// The circuit's name in `encrypted-ixs`, which its computation
+    /// definition offset is derived from.
+    pub fn name(self) -> &'static str {
+        match self {
+            Circuit::ComputeSwap => "compute_swap",
+            Circuit::ComputeDeposit => "compute_deposit",
+            Circuit::ComputeWithdraw => "compute_withdraw",
+            Circuit::SealReserves => "seal_reserves",
+            Circuit::RevealReserves => "reveal_reserves",
+            Circuit::DarkFund => "dark_fund",
+            Circuit::DarkSwap => "dark_swap",
+            Circuit::DarkWithdraw => "dark_withdraw",
+        }
+    }
+}
+
+pub fn init_comp_def(payer: Pubkey, circuit: Circuit) -> Instruction {
+    use whispr::instruction as ix;
+
+    let data = match circuit {
+        Circuit::ComputeSwap => ix::InitComputeSwapCompDef {}.data(),
+        Circuit::ComputeDeposit => ix::InitComputeDepositCompDef {}.data(),
+        Circuit::ComputeWithdraw => ix::InitComputeWithdrawCompDef {}.data(),
+        Circuit::SealReserves => ix::InitSealReservesCompDef {}.data(),
+        Circuit::RevealReserves => ix::InitRevealReservesCompDef {}.data(),
+        Circuit::DarkFund => ix::InitDarkFundCompDef {}.data(),
+        Circuit::DarkSwap => ix::InitDarkSwapCompDef {}.data(),
+        Circuit::DarkWithdraw => ix::InitDarkWithdrawCompDef {}.data(),
+    };
+    Instruction {
+        program_id: whispr::ID,
+        // Every `Init*CompDef` struct has this same layout
+        accounts: whispr::accounts::InitComputeSwapCompDef {
+            payer,
+            mxe_account: pda::arcium::mxe(),
+            comp_def_account: pda::arcium::comp_def(circuit.name()),
+            arcium_program: ARCIUM_PROGRAM_ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data,
+    }
+}
+
+/// Queues a deposit of `EncryptedDeposit::lp_amount` LP tokens. `max_x_in`
+/// and `max_y_in` are escrowed in the clear, and the settled amounts are
+/// public once the callback lands.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_deposit(
+    pool: &Pool,
+    payer: Pubkey,
+    user: Pubkey,
+    cluster: Pubkey,
+    computation_offset: u64,
+    deposit: &EncryptedDeposit,
+    max_x_in: u64,
+    max_y_in: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+    let liquidity_state = pda::liquidity_state(&pool.config, &user, computation_offset);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::ComputeDeposit {
+            payer,
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            mint_lp: pool.mint_lp,
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            liquidity_state,
+            escrow_x: pda::escrow_x(&liquidity_state),
+            escrow_y: pda::escrow_y(&liquidity_state),
+            user_x,
+            user_y,
+            user_lp,
+            mxe_account: pda::arcium::mxe(),
+            mempool_account: pda::arcium::mempool(),
+            executing_pool: pda::arcium::execpool(),
+            computation_account: pda::arcium::computation(computation_offset),
+            comp_def_account: pda::arcium::comp_def(Circuit::ComputeDeposit.name()),
+            cluster_account: cluster,
+            pool_account: ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+            clock_account: ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            token_program: anchor_spl::token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            arcium_program: ARCIUM_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::ComputeDeposit {
+            computation_offset,
+            pub_key: deposit.pub_key,
+            nonce: deposit.nonce,
+            encrypted_lp_amount: deposit.lp_amount,
+            encrypted_max_x: deposit.max_x,
+            encrypted_max_y: deposit.max_y,
+            max_x_in,
+            max_y_in,
+            deadline,
+        }
+        .data(),
+    }
+}
+
+/// Queues a withdrawal, escrowing `max_lp_in` LP tokens in the clear. The
+/// settled amounts are public once the callback lands.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_withdraw(
+    pool: &Pool,
+    payer: Pubkey,
+    user: Pubkey,
+    cluster: Pubkey,
+    computation_offset: u64,
+    withdraw: &EncryptedWithdraw,
+    max_lp_in: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+    let liquidity_state = pda::liquidity_state(&pool.config, &user, computation_offset);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::ComputeWithdraw {
+            payer,
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            mint_lp: pool.mint_lp,
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            liquidity_state,
+            escrow_lp: pda::escrow_lp(&liquidity_state),
+            user_x,
+            user_y,
+            user_lp,
+            mxe_account: pda::arcium::mxe(),
+            mempool_account: pda::arcium::mempool(),
+            executing_pool: pda::arcium::execpool(),
+            computation_account: pda::arcium::computation(computation_offset),
+            comp_def_account: pda::arcium::comp_def(Circuit::ComputeWithdraw.name()),
+            cluster_account: cluster,
+            pool_account: ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+            clock_account: ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            token_program: anchor_spl::token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            arcium_program: ARCIUM_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::ComputeWithdraw {
+            computation_offset,
+            pub_key: withdraw.pub_key,
+            nonce: withdraw.nonce,
+            encrypted_lp_amount: withdraw.lp_amount,
+            encrypted_min_x: withdraw.min_x,
+            encrypted_min_y: withdraw.min_y,
+            max_lp_in,
+            deadline,
+        }
+        .data(),
+    }
+}
+
+/// Refunds an expired confidential deposit. Anyone can send it once the
+/// pool's swap timeout has passed.
+pub fn cancel_deposit(
+    pool: &Pool,
+    caller: Pubkey,
+    user: Pubkey,
+    computation_offset: u64,
+) -> Instruction {
+    let (user_x, user_y, _) = pool.user_tokens(&user);
+    let liquidity_state = pda::liquidity_state(&pool.config, &user, computation_offset);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::CancelDeposit {
+            caller,
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            liquidity_state,
+            escrow_x: pda::escrow_x(&liquidity_state),
+            escrow_y: pda::escrow_y(&liquidity_state),
+            user_x,
+            user_y,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::CancelDeposit {}.data(),
+    }
+}
+
+/// Refunds an expired confidential withdrawal. Anyone can send it once the
+/// pool's swap timeout has passed.
+pub fn cancel_withdraw(
+    pool: &Pool,
+    caller: Pubkey,
+    user: Pubkey,
+    computation_offset: u64,
+) -> Instruction {
+    let (_, _, user_lp) = pool.user_tokens(&user);
+    let liquidity_state = pda::liquidity_state(&pool.config, &user, computation_offset);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::CancelWithdraw {
+            caller,
+            user,
+            config: pool.config,
+            mint_lp: pool.mint_lp,
+            liquidity_state,
+            escrow_lp: pda::escrow_lp(&liquidity_state),
+            user_lp,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::CancelWithdraw {}.data(),
+    }
+}
+
+pub fn close_liquidity(pool: &Pool, user: Pubkey, computation_offset: u64) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::CloseLiquidity {
+            user,
+            config: pool.config,
+            liquidity_state: pda::liquidity_state(&pool.config, &user, computation_offset),
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::CloseLiquidity {}.data(),
+    }
+}
+
+/// Seals the pool's reserves into its dark pool. `nonce` encrypts the
+/// sealed reserves.
+pub fn enable_dark_pool(
+    pool: &Pool,
+    authority: Pubkey,
+    cluster: Pubkey,
+    computation_offset: u64,
+    nonce: u128,
+) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::EnableDarkPool {
+            payer: authority,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            dark_pool: pda::dark_pool(&pool.config),
+            dark_x: pda::dark_x(&pool.config),
+            dark_y: pda::dark_y(&pool.config),
+            mxe_account: pda::arcium::mxe(),
+            mempool_account: pda::arcium::mempool(),
+            executing_pool: pda::arcium::execpool(),
+            computation_account: pda::arcium::computation(computation_offset),
+            comp_def_account: pda::arcium::comp_def(Circuit::SealReserves.name()),
+            cluster_account: cluster,
+            pool_account: ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+            clock_account: ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            system_program: system_program::ID,
+            arcium_program: ARCIUM_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::EnableDarkPool {
+            computation_offset,
+            nonce,
+        }
+        .data(),
+    }
+}
+
+/// Reopens a pool whose seal never landed. Anyone can send it once the
+/// pending seal is older than the pool's swap timeout.
+pub fn cancel_enable_dark_pool(pool: &Pool, caller: Pubkey) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::CancelEnableDarkPool {
+            caller,
+            config: pool.config,
+            dark_pool: pda::dark_pool(&pool.config),
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::CancelEnableDarkPool {}.data(),
+    }
+}
+
+/// Reveals the dark pool's reserves back into the public vaults. Sent by
+/// the authority, or by anyone once the pool has no authority or has been
+/// dark for the maximum period.
+pub fn disable_dark_pool(
+    pool: &Pool,
+    payer: Pubkey,
+    cluster: Pubkey,
+    computation_offset: u64,
+) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::DisableDarkPool {
+            payer,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            dark_pool: pda::dark_pool(&pool.config),
+            vault_x: pool.vault_x,
+            vault_y: pool.vault_y,
+            dark_x: pda::dark_x(&pool.config),
+            dark_y: pda::dark_y(&pool.config),
+            protocol_x: pool.protocol_x,
+            protocol_y: pool.protocol_y,
+            mxe_account: pda::arcium::mxe(),
+            mempool_account: pda::arcium::mempool(),
+            executing_pool: pda::arcium::execpool(),
+            computation_account: pda::arcium::computation(computation_offset),
+            comp_def_account: pda::arcium::comp_def(Circuit::RevealReserves.name()),
+            cluster_account: cluster,
+            pool_account: ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+            clock_account: ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            system_program: system_program::ID,
+            arcium_program: ARCIUM_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::DisableDarkPool { computation_offset }.data(),
+    }
+}
+
+/// Moves `amount_x` and `amount_y` into the user's encrypted dark pool
+/// balance. The amounts are public, the balance is not.
+pub fn dark_fund(
+    pool: &Pool,
+    payer: Pubkey,
+    user: Pubkey,
+    cluster: Pubkey,
+    computation_offset: u64,
+    amount_x: u64,
+    amount_y: u64,
+) -> Instruction {
+    let (user_x, user_y, _) = pool.user_tokens(&user);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::DarkFund {
+            payer,
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            dark_pool: pda::dark_pool(&pool.config),
+            dark_balance: pda::dark_balance(&pool.config, &user),
+            dark_x: pda::dark_x(&pool.config),
+            dark_y: pda::dark_y(&pool.config),
+            user_x,
+            user_y,
+            mxe_account: pda::arcium::mxe(),
+            mempool_account: pda::arcium::mempool(),
+            executing_pool: pda::arcium::execpool(),
+            computation_account: pda::arcium::computation(computation_offset),
+            comp_def_account: pda::arcium::comp_def(Circuit::DarkFund.name()),
+            cluster_account: cluster,
+            pool_account: ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+            clock_account: ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            arcium_program: ARCIUM_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::DarkFund {
+            computation_offset,
+            amount_x,
+            amount_y,
+        }
+        .data(),
+    }
+}
+
+/// Swaps within the user's dark pool balance. Unlike [`compute_swap`] no
+/// tokens move, and only the trader can decrypt the outcome.
+pub fn dark_swap(
+    pool: &Pool,
+    payer: Pubkey,
+    user: Pubkey,
+    cluster: Pubkey,
+    computation_offset: u64,
+    swap: &EncryptedSwap,
+) -> Instruction {
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::DarkSwap {
+            payer,
+            user,
+            config: pool.config,
+            dark_pool: pda::dark_pool(&pool.config),
+            dark_balance: pda::dark_balance(&pool.config, &user),
+            mxe_account: pda::arcium::mxe(),
+            mempool_account: pda::arcium::mempool(),
+            executing_pool: pda::arcium::execpool(),
+            computation_account: pda::arcium::computation(computation_offset),
+            comp_def_account: pda::arcium::comp_def(Circuit::DarkSwap.name()),
+            cluster_account: cluster,
+            pool_account: ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+            clock_account: ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            system_program: system_program::ID,
+            arcium_program: ARCIUM_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::DarkSwap {
+            computation_offset,
+            pub_key: swap.pub_key,
+            nonce: swap.nonce,
+            encrypted_is_x: swap.is_x,
+            encrypted_amount: swap.amount,
+            encrypted_min_output: swap.min_output,
+        }
+        .data(),
+    }
+}
+
+/// Withdraws from the user's dark pool balance. The withdrawn amounts are
+/// public once the callback transfers them.
+pub fn dark_withdraw(
+    pool: &Pool,
+    payer: Pubkey,
+    user: Pubkey,
+    cluster: Pubkey,
+    computation_offset: u64,
+    withdraw: &EncryptedDarkWithdraw,
+) -> Instruction {
+    let (user_x, user_y, _) = pool.user_tokens(&user);
+    Instruction {
+        program_id: whispr::ID,
+        accounts: whispr::accounts::DarkWithdraw {
+            payer,
+            user,
+            mint_x: pool.mint_x,
+            mint_y: pool.mint_y,
+            config: pool.config,
+            dark_balance: pda::dark_balance(&pool.config, &user),
+            dark_x: pda::dark_x(&pool.config),
+            dark_y: pda::dark_y(&pool.config),
+            user_x,
+            user_y,
+            mxe_account: pda::arcium::mxe(),
+            mempool_account: pda::arcium::mempool(),
+            executing_pool: pda::arcium::execpool(),
+            computation_account: pda::arcium::computation(computation_offset),
+            comp_def_account: pda::arcium::comp_def(Circuit::DarkWithdraw.name()),
+            cluster_account: cluster,
+            pool_account: ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+            clock_account: ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            token_program_x: pool.token_program_x,
+            token_program_y: pool.token_program_y,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            arcium_program: ARCIUM_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: whispr::instruction::DarkWithdraw {
+            computation_offset,
+            pub_key: withdraw.pub_key,
+            nonce: withdraw.nonce,
+            encrypted_x_amount: withdraw.x_amount,
+            encrypted_y_amount: withdraw.y_amount,
+        }
+        .data(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::Discriminator;
+
+    use super::*;
+
+    fn pool(mint_x: Pubkey) -> Pool {
+        Pool::new(
+            7,
+            mint_x,
+            Pubkey::new_unique(),
+            anchor_spl::token::ID,
+            anchor_spl::token_2022::ID,
+        )
+    }
+
+    fn keys(ix: &Instruction) -> Vec<Pubkey> {
+        ix.accounts.iter().map(|meta| meta.pubkey).collect()
+    }
+
+    fn signers(ix: &Instruction) -> Vec<Pubkey> {
+        ix.accounts
+            .iter()
+            .filter(|meta| meta.is_signer)
+            .map(|meta| meta.pubkey)
+            .collect()
+    }
+
+    // The expected account lists below follow the field order of the
+    // program's `#[derive(Accounts)]` structs
+
+    #[test]
+    fn deposit_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let user = Pubkey::new_unique();
+        let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+        let ix = deposit(&pool, user, 1, 2, 3, None);
+
+        assert_eq!(ix.program_id, whispr::ID);
+        assert_eq!(
+            keys(&ix),
+            [
+                user,
+                pool.mint_x,
+                pool.mint_y,
+                pool.config,
+                pool.mint_lp,
+                pool.vault_x,
+                pool.vault_y,
+                user_x,
+                user_y,
+                user_lp,
+                pool.locked_lp,
+                anchor_spl::token::ID,
+                anchor_spl::token_2022::ID,
+                anchor_spl::token::ID,
+                anchor_spl::associated_token::ID,
+                system_program::ID,
+            ]
+        );
+        assert_eq!(signers(&ix), [user]);
+        assert!(ix
+            .data
+            .starts_with(whispr::instruction::Deposit::DISCRIMINATOR));
+    }
+
+    #[test]
+    fn swap_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let user = Pubkey::new_unique();
+        let (user_x, user_y, _) = pool.user_tokens(&user);
+        let ix = swap(&pool, user, true, 1, 0, Some(9));
+
+        assert_eq!(
+            keys(&ix),
+            [
+                user,
+                pool.mint_x,
+                pool.mint_y,
+                pool.config,
+                pool.mint_lp,
+                pool.vault_x,
+                pool.vault_y,
+                pool.protocol_x,
+                pool.protocol_y,
+                user_x,
+                user_y,
+                anchor_spl::token::ID,
+                anchor_spl::token_2022::ID,
+                anchor_spl::associated_token::ID,
+                system_program::ID,
+            ]
+        );
+        assert_eq!(signers(&ix), [user]);
+        assert!(ix
+            .data
+            .starts_with(whispr::instruction::Swap::DISCRIMINATOR));
+    }
+
+    #[test]
+    fn compute_swap_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let (payer, user, cluster) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (user_x, user_y, _) = pool.user_tokens(&user);
+        let offset = 5;
+        let swap_state = pda::swap_state(&pool.config, &user, offset);
+        let encrypted = EncryptedSwap {
+            pub_key: [1; 32],
+            nonce: 2,
+            is_x: [3; 32],
+            amount: [4; 32],
+            min_output: [5; 32],
+        };
+        let ix = compute_swap(&pool, payer, user, cluster, offset, &encrypted, 6, 7, None);
+
+        assert_eq!(
+            keys(&ix),
+            [
+                payer,
+                user,
+                pool.mint_x,
+                pool.mint_y,
+                pool.config,
+                pool.vault_x,
+                pool.vault_y,
+                pool.protocol_x,
+                pool.protocol_y,
+                swap_state,
+                pda::escrow_x(&swap_state),
+                pda::escrow_y(&swap_state),
+                user_x,
+                user_y,
+                pda::arcium::mxe(),
+                pda::arcium::mempool(),
+                pda::arcium::execpool(),
+                pda::arcium::computation(offset),
+                pda::arcium::comp_def("compute_swap"),
+                cluster,
+                ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+                ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+                anchor_spl::token::ID,
+                anchor_spl::token_2022::ID,
+                anchor_spl::associated_token::ID,
+                system_program::ID,
+                ARCIUM_PROGRAM_ID,
+            ]
+        );
+        assert_eq!(signers(&ix), [payer, user]);
+        assert!(ix
+            .data
+            .starts_with(whispr::instruction::ComputeSwap::DISCRIMINATOR));
+    }
+
+    #[test]
+    fn cancel_and_close_swap_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let (caller, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (user_x, user_y, _) = pool.user_tokens(&user);
+        let swap_state = pda::swap_state(&pool.config, &user, 3);
+
+        // Anyone can refund an expired swap, only the caller signs
+        let ix = cancel_swap(&pool, caller, user, 3);
+        assert_eq!(
+            keys(&ix),
+            [
+                caller,
+                user,
+                pool.mint_x,
+                pool.mint_y,
+                pool.config,
+                swap_state,
+                pda::escrow_x(&swap_state),
+                pda::escrow_y(&swap_state),
+                user_x,
+                user_y,
+                anchor_spl::token::ID,
+                anchor_spl::token_2022::ID,
+            ]
+        );
+        assert_eq!(signers(&ix), [caller]);
+
+        let ix = close_swap(&pool, user, 3);
+        assert_eq!(keys(&ix), [user, pool.config, swap_state]);
+        assert_eq!(signers(&ix), [user]);
+    }
+
+    #[test]
+    fn zap_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let user = Pubkey::new_unique();
+        let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+        let expected = [
+            user,
+            pool.mint_x,
+            pool.mint_y,
+            pool.config,
+            pool.mint_lp,
+            pool.vault_x,
+            pool.vault_y,
+            pool.protocol_x,
+            pool.protocol_y,
+            user_x,
+            user_y,
+            user_lp,
+            anchor_spl::token::ID,
+            anchor_spl::token_2022::ID,
+            anchor_spl::token::ID,
+            anchor_spl::associated_token::ID,
+            system_program::ID,
+        ];
+
+        let ix = deposit_single(&pool, user, true, 1, 0, None);
+        assert_eq!(keys(&ix), expected);
+        assert_eq!(signers(&ix), [user]);
+        assert!(ix
+            .data
+            .starts_with(whispr::instruction::DepositSingle::DISCRIMINATOR));
+
+        let ix = withdraw_single(&pool, user, false, 1, 0, None);
+        assert_eq!(keys(&ix), expected);
+        assert!(ix
+            .data
+            .starts_with(whispr::instruction::WithdrawSingle::DISCRIMINATOR));
+    }
+
+    #[test]
+    fn authority_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let (authority, next) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        for (ix, discriminator) in [
+            (
+                set_fee(&pool, authority, 30),
+                whispr::instruction::SetFee::DISCRIMINATOR,
+            ),
+            (
+                set_protocol_fee(&pool, authority, 10),
+                whispr::instruction::SetProtocolFee::DISCRIMINATOR,
+            ),
+            (
+                propose_authority(&pool, authority, next),
+                whispr::instruction::ProposeAuthority::DISCRIMINATOR,
+            ),
+            (
+                renounce_authority(&pool, authority),
+                whispr::instruction::RenounceAuthority::DISCRIMINATOR,
+            ),
+        ] {
+            assert_eq!(keys(&ix), [authority, pool.config]);
+            assert_eq!(signers(&ix), [authority]);
+            assert!(ix.data.starts_with(discriminator));
+        }
+        // The proposed authority accepts
+        let ix = accept_authority(&pool, next);
+        assert_eq!(signers(&ix), [next]);
+
+        let (user_x, user_y, _) = pool.user_tokens(&authority);
+        let ix = claim_protocol_fees(&pool, authority);
+        assert_eq!(
+            keys(&ix),
+            [
+                authority,
+                pool.mint_x,
+                pool.mint_y,
+                pool.config,
+                pool.protocol_x,
+                pool.protocol_y,
+                user_x,
+                user_y,
+                anchor_spl::token::ID,
+                anchor_spl::token_2022::ID,
+            ]
+        );
+        assert_eq!(signers(&ix), [authority]);
+    }
+
+    #[test]
+    fn init_comp_def_accounts() {
+        let payer = Pubkey::new_unique();
+        for (circuit, discriminator) in Circuit::ALL.into_iter().zip([
+            whispr::instruction::InitComputeSwapCompDef::DISCRIMINATOR,
+            whispr::instruction::InitComputeDepositCompDef::DISCRIMINATOR,
+            whispr::instruction::InitComputeWithdrawCompDef::DISCRIMINATOR,
+            whispr::instruction::InitSealReservesCompDef::DISCRIMINATOR,
+            whispr::instruction::InitRevealReservesCompDef::DISCRIMINATOR,
+            whispr::instruction::InitDarkFundCompDef::DISCRIMINATOR,
+            whispr::instruction::InitDarkSwapCompDef::DISCRIMINATOR,
+            whispr::instruction::InitDarkWithdrawCompDef::DISCRIMINATOR,
+        ]) {
+            let ix = init_comp_def(payer, circuit);
+            assert_eq!(
+                keys(&ix),
+                [
+                    payer,
+                    pda::arcium::mxe(),
+                    pda::arcium::comp_def(circuit.name()),
+                    ARCIUM_PROGRAM_ID,
+                    system_program::ID,
+                ]
+            );
+            assert_eq!(signers(&ix), [payer]);
+            assert_eq!(ix.data, discriminator, "{circuit:?}");
+        }
+    }
+
+    #[test]
+    fn compute_liquidity_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let (payer, user, cluster) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+        let offset = 5;
+        let state = pda::liquidity_state(&pool.config, &user, offset);
+        let arcium = |circuit: &str| {
+            [
+                pda::arcium::mxe(),
+                pda::arcium::mempool(),
+                pda::arcium::execpool(),
+                pda::arcium::computation(offset),
+                pda::arcium::comp_def(circuit),
+                cluster,
+                ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+                ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            ]
+        };
+        let programs = [
+            anchor_spl::token::ID,
+            anchor_spl::token_2022::ID,
+            anchor_spl::token::ID,
+            anchor_spl::associated_token::ID,
+            system_program::ID,
+            ARCIUM_PROGRAM_ID,
+        ];
+
+        let deposit = EncryptedDeposit {
+            pub_key: [1; 32],
+            nonce: 2,
+            lp_amount: [3; 32],
+            max_x: [4; 32],
+            max_y: [5; 32],
+        };
+        let ix = compute_deposit(&pool, payer, user, cluster, offset, &deposit, 6, 7, None);
+        let expected: Vec<Pubkey> = [
+            payer,
+            user,
+            pool.mint_x,
+            pool.mint_y,
+            pool.config,
+            pool.mint_lp,
+            pool.vault_x,
+            pool.vault_y,
+            state,
+            pda::escrow_x(&state),
+            pda::escrow_y(&state),
+            user_x,
+            user_y,
+            user_lp,
+        ]
+        .into_iter()
+        .chain(arcium("compute_deposit"))
+        .chain(programs)
+        .collect();
+        assert_eq!(keys(&ix), expected);
+        assert_eq!(signers(&ix), [payer, user]);
+        assert!(ix
+            .data
+            .starts_with(whispr::instruction::ComputeDeposit::DISCRIMINATOR));
+
+        let withdraw = EncryptedWithdraw {
+            pub_key: [1; 32],
+            nonce: 2,
+            lp_amount: [3; 32],
+            min_x: [4; 32],
+            min_y: [5; 32],
+        };
+        let ix = compute_withdraw(&pool, payer, user, cluster, offset, &withdraw, 6, None);
+        let expected: Vec<Pubkey> = [
+            payer,
+            user,
+            pool.mint_x,
+            pool.mint_y,
+            pool.config,
+            pool.mint_lp,
+            pool.vault_x,
+            pool.vault_y,
+            state,
+            pda::escrow_lp(&state),
+            user_x,
+            user_y,
+            user_lp,
+        ]
+        .into_iter()
+        .chain(arcium("compute_withdraw"))
+        .chain(programs)
+        .collect();
+        assert_eq!(keys(&ix), expected);
+        assert_eq!(signers(&ix), [payer, user]);
+        assert!(ix
+            .data
+            .starts_with(whispr::instruction::ComputeWithdraw::DISCRIMINATOR));
+    }
+
+    #[test]
+    fn cancel_and_close_liquidity_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let (caller, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (user_x, user_y, user_lp) = pool.user_tokens(&user);
+        let state = pda::liquidity_state(&pool.config, &user, 3);
+
+        let ix = cancel_deposit(&pool, caller, user, 3);
+        assert_eq!(
+            keys(&ix),
+            [
+                caller,
+                user,
+                pool.mint_x,
+                pool.mint_y,
+                pool.config,
+                state,
+                pda::escrow_x(&state),
+                pda::escrow_y(&state),
+                user_x,
+                user_y,
+                anchor_spl::token::ID,
+                anchor_spl::token_2022::ID,
+            ]
+        );
+        assert_eq!(signers(&ix), [caller]);
+
+        let ix = cancel_withdraw(&pool, caller, user, 3);
+        assert_eq!(
+            keys(&ix),
+            [
+                caller,
+                user,
+                pool.config,
+                pool.mint_lp,
+                state,
+                pda::escrow_lp(&state),
+                user_lp,
+                anchor_spl::token::ID,
+            ]
+        );
+        assert_eq!(signers(&ix), [caller]);
+
+        let ix = close_liquidity(&pool, user, 3);
+        assert_eq!(keys(&ix), [user, pool.config, state]);
+        assert_eq!(signers(&ix), [user]);
+    }
+
+    #[test]
+    fn dark_pool_toggle_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let (payer, cluster) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let dark_pool = pda::dark_pool(&pool.config);
+        let (dark_x, dark_y) = (pda::dark_x(&pool.config), pda::dark_y(&pool.config));
+        let arcium = |circuit: &str| {
+            [
+                pda::arcium::mxe(),
+                pda::arcium::mempool(),
+                pda::arcium::execpool(),
+                pda::arcium::computation(9),
+                pda::arcium::comp_def(circuit),
+                cluster,
+                ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+                ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+                anchor_spl::token::ID,
+                anchor_spl::token_2022::ID,
+                system_program::ID,
+                ARCIUM_PROGRAM_ID,
+            ]
+        };
+
+        let ix = enable_dark_pool(&pool, payer, cluster, 9, 1);
+        let expected: Vec<Pubkey> = [
+            payer,
+            pool.mint_x,
+            pool.mint_y,
+            pool.config,
+            pool.vault_x,
+            pool.vault_y,
+            dark_pool,
+            dark_x,
+            dark_y,
+        ]
+        .into_iter()
+        .chain(arcium("seal_reserves"))
+        .collect();
+        assert_eq!(keys(&ix), expected);
+        assert_eq!(signers(&ix), [payer]);
+
+        let ix = disable_dark_pool(&pool, payer, cluster, 9);
+        let expected: Vec<Pubkey> = [
+            payer,
+            pool.mint_x,
+            pool.mint_y,
+            pool.config,
+            dark_pool,
+            pool.vault_x,
+            pool.vault_y,
+            dark_x,
+            dark_y,
+            pool.protocol_x,
+            pool.protocol_y,
+        ]
+        .into_iter()
+        .chain(arcium("reveal_reserves"))
+        .collect();
+        assert_eq!(keys(&ix), expected);
+        assert_eq!(signers(&ix), [payer]);
+
+        let ix = cancel_enable_dark_pool(&pool, payer);
+        assert_eq!(keys(&ix), [payer, pool.config, dark_pool]);
+        assert_eq!(signers(&ix), [payer]);
+    }
+
+    #[test]
+    fn dark_trading_accounts() {
+        let pool = pool(Pubkey::new_unique());
+        let (payer, user, cluster) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (user_x, user_y, _) = pool.user_tokens(&user);
+        let dark_pool = pda::dark_pool(&pool.config);
+        let dark_balance = pda::dark_balance(&pool.config, &user);
+        let (dark_x, dark_y) = (pda::dark_x(&pool.config), pda::dark_y(&pool.config));
+        let arcium = |circuit: &str| {
+            [
+                pda::arcium::mxe(),
+                pda::arcium::mempool(),
+                pda::arcium::execpool(),
+                pda::arcium::computation(4),
+                pda::arcium::comp_def(circuit),
+                cluster,
+                ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+                ARCIUM_CLOCK_ACCOUNT_ADDRESS,
+            ]
+        };
+        let programs = [
+            anchor_spl::token::ID,
+            anchor_spl::token_2022::ID,
+            anchor_spl::associated_token::ID,
+            system_program::ID,
+            ARCIUM_PROGRAM_ID,
+        ];
+
+        let ix = dark_fund(&pool, payer, user, cluster, 4, 1, 2);
+        let expected: Vec<Pubkey> = [
+            payer,
+            user,
+            pool.mint_x,
+            pool.mint_y,
+            pool.config,
+            dark_pool,
+            dark_balance,
+            dark_x,
+            dark_y,
+            user_x,
+            user_y,
+        ]
+        .into_iter()
+        .chain(arcium("dark_fund"))
+        .chain(programs)
+        .collect();
+        assert_eq!(keys(&ix), expected);
+        assert_eq!(signers(&ix), [payer, user]);
+
+        let swap = EncryptedSwap {
+            pub_key: [1; 32],
+            nonce: 2,
+            is_x: [3; 32],
+            amount: [4; 32],
+            min_output: [5; 32],
+        };
+        let ix = dark_swap(&pool, payer, user, cluster, 4, &swap);
+        let expected: Vec<Pubkey> = [payer, user, pool.config, dark_pool, dark_balance]
+            .into_iter()
+            .chain(arcium("dark_swap"))
+            .chain([system_program::ID, ARCIUM_PROGRAM_ID])
+            .collect();
+        assert_eq!(keys(&ix), expected);
+        assert_eq!(signers(&ix), [payer, user]);
+
+        let withdraw = EncryptedDarkWithdraw {
+            pub_key: [1; 32],
+            nonce: 2,
+            x_amount: [3; 32],
+            y_amount: [4; 32],
+        };
+        let ix = dark_withdraw(&pool, payer, user, cluster, 4, &withdraw);
+        let expected: Vec<Pubkey> = [
+            payer,
+            user,
+            pool.mint_x,
+            pool.mint_y,
+            pool.config,
+            dark_balance,
+            dark_x,
+            dark_y,
+            user_x,
+            user_y,
+        ]
+        .into_iter()
+        .chain(arcium("dark_withdraw"))
+        .chain(programs)
+        .collect();
+        assert_eq!(keys(&ix), expected);
+        assert_eq!(signers(&ix), [payer, user]);
+        assert!(ix
+            .data
+            .starts_with(whispr::instruction::DarkWithdraw::DISCRIMINATOR));
+    }
+
+    #[test]
+    fn native_accounts_only_for_native_sides() {
+        let (payer, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(create_native_accounts(&pool(Pubkey::new_unique()), payer, user).is_empty());
+
+        let pool = pool(native_mint::ID);
+        let ixs = create_native_accounts(&pool, payer, user);
+        assert_eq!(ixs.len(), 1);
+        assert_eq!(ixs[0].program_id, anchor_spl::associated_token::ID);
+        assert!(keys(&ixs[0]).contains(&pool.user_tokens(&user).0));
+    }
+}
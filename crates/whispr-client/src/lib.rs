@@ -0,0 +1,19 @@
+//! Rust client for the Whispr program.
+//!
+//! Builds instructions from the program's own generated `instruction` and
+//! `accounts` types, derives every PDA from a pool's `Config.seed`, encrypts
+//! confidential inputs for the MXE, and decodes program accounts and `#[event]`
+//! logs.
+
+pub mod decode;
+pub mod encryption;
+pub mod instructions;
+pub mod pda;
+pub mod pool;
+
+pub use encryption::{RescueCipher, SwapResult};
+pub use pool::Pool;
+pub use whispr::{
+    Config, DarkBalance, DarkPool, LiquidityState, LiquidityStatus, SwapState, SwapStatus,
+    ID as PROGRAM_ID,
+};
@@ -0,0 +1,289 @@
+//! PDA derivation for Whispr pools and the Arcium accounts its confidential
+//! instructions queue computations with.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use arcium_anchor::prelude::comp_def_offset;
+
+pub fn config(seed: u64) -> Pubkey {
+    Pubkey::find_program_address(&[b"config", &seed.to_le_bytes()], &whispr::ID).0
+}
+
+pub fn mint_lp(config: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"lp", config.as_ref()], &whispr::ID).0
+}
+
+/// Pool vaults, the locked LP and user token accounts are all associated token accounts.
+pub fn associated_token(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, mint, token_program)
+}
+
+pub fn protocol_x(config: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"protocol_x", config.as_ref()], &whispr::ID).0
+}
+
+pub fn protocol_y(config: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"protocol_y", config.as_ref()], &whispr::ID).0
+}
+
+pub fn swap_state(config: &Pubkey, user: &Pubkey, computation_offset: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"swap_state",
+            config.as_ref(),
+            user.as_ref(),
+            &computation_offset.to_le_bytes(),
+        ],
+        &whispr::ID,
+    )
+    .0
+}
+
+pub fn liquidity_state(config: &Pubkey, user: &Pubkey, computation_offset: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"liquidity_state",
+            config.as_ref(),
+            user.as_ref(),
+            &computation_offset.to_le_bytes(),
+        ],
+        &whispr::ID,
+    )
+    .0
+}
+
+/// Escrows are keyed by the swap or liquidity state they belong to.
+pub fn escrow_x(state: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"escrow_x", state.as_ref()], &whispr::ID).0
+}
+
+pub fn escrow_y(state: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"escrow_y", state.as_ref()], &whispr::ID).0
+}
+
+pub fn escrow_lp(state: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"escrow_lp", state.as_ref()], &whispr::ID).0
+}
+
+pub fn dark_pool(config: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"dark_pool", config.as_ref()], &whispr::ID).0
+}
+
+pub fn dark_x(config: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"dark_x", config.as_ref()], &whispr::ID).0
+}
+
+pub fn dark_y(config: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"dark_y", config.as_ref()], &whispr::ID).0
+}
+
+pub fn dark_balance(config: &Pubkey, user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"dark_balance", config.as_ref(), user.as_ref()],
+        &whispr::ID,
+    )
+    .0
+}
+
+/// Arcium accounts, derived under the Arcium program the same way as the
+/// `derive_*_pda!` macros the program checks them with.
+pub mod arcium {
+    use super::*;
+
+    pub use arcium_client::idl::arcium::ID as ARCIUM_PROGRAM_ID;
+
+    pub fn mxe() -> Pubkey {
+        Pubkey::find_program_address(&[b"MXEAccount", whispr::ID.as_ref()], &ARCIUM_PROGRAM_ID).0
+    }
+
+    pub fn mempool() -> Pubkey {
+        Pubkey::find_program_address(&[b"Mempool", whispr::ID.as_ref()], &ARCIUM_PROGRAM_ID).0
+    }
+
+    pub fn execpool() -> Pubkey {
+        Pubkey::find_program_address(&[b"Execpool", whispr::ID.as_ref()], &ARCIUM_PROGRAM_ID).0
+    }
+
+    pub fn computation(computation_offset: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"ComputationAccount",
+                whispr::ID.as_ref(),
+                &computation_offset.to_le_bytes(),
+            ],
+            &ARCIUM_PROGRAM_ID,
+        )
+        .0
+    }
+
+    /// Computation definition of one of the program's circuits, e.g. `"compute_swap"`.
+    pub fn comp_def(circuit: &str) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"ComputationDefinitionAccount",
+                whispr::ID.as_ref(),
+                &comp_def_offset(circuit).to_le_bytes(),
+            ],
+            &ARCIUM_PROGRAM_ID,
+        )
+        .0
+    }
+
+    /// The MXE's cluster, from the cluster offset it was deployed with.
+    pub fn cluster(cluster_offset: u32) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"Cluster", &cluster_offset.to_le_bytes()],
+            &ARCIUM_PROGRAM_ID,
+        )
+        .0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(address: &str) -> Pubkey {
+        address.parse().unwrap()
+    }
+
+    fn user() -> Pubkey {
+        Pubkey::new_from_array([7; 32])
+    }
+
+    const OFFSET: u64 = 0x0102_0304_0506_0708;
+
+    // Expected addresses below were derived independently of this crate,
+    // under the program ID in `declare_id!`
+
+    #[test]
+    fn pool_pdas() {
+        let config = config(42);
+        assert_eq!(config, key("9XwgyJT16vLX2RYT3BWU16speyHJn4vw6szceYPzEFGW"));
+        assert_eq!(
+            mint_lp(&config),
+            key("7bwB5Sm16QaHxwiEw2xWXsUrHxib67FJKJ8JGo1vbVgQ")
+        );
+        assert_eq!(
+            protocol_x(&config),
+            key("2bV8Y1B6jG3DpQ8xs95mvAvUJz7Pone5TDafDHF6zCaz")
+        );
+        assert_eq!(
+            protocol_y(&config),
+            key("9moncENUfHjtwU8LWg1ZZGFczyFDvM9MvPVcQHskepR4")
+        );
+        assert_eq!(
+            dark_pool(&config),
+            key("ofiAzhh7MEZk2FYxXbHCdsHCiD6W3TEbJZkSXjbXWEx")
+        );
+        assert_eq!(
+            dark_x(&config),
+            key("G7PqrUK3MaAwvbkeDuZfDjPNj9FFjBXjkiFq2pZafVUV")
+        );
+        assert_eq!(
+            dark_y(&config),
+            key("3R5NvbaZkjKc7U9dMfUt3mxWjwTW2pzAKVd4axT5KaVC")
+        );
+    }
+
+    #[test]
+    fn user_pdas() {
+        let config = config(42);
+        assert_eq!(
+            dark_balance(&config, &user()),
+            key("6fa7vrwC8ceQ2anBkL9vvxAZWsgBUW1JSBW8Ay1WQTVF")
+        );
+        assert_eq!(
+            swap_state(&config, &user(), OFFSET),
+            key("3RZnroZG7xtKefnBALTDzp9szyyFR9deEWi8TnheGfeg")
+        );
+        assert_eq!(
+            liquidity_state(&config, &user(), OFFSET),
+            key("4k3fe5na9AxCpJUXsgsJCabk9Z3BnPHyjyhHEuHPaNgG")
+        );
+    }
+
+    #[test]
+    fn escrow_pdas() {
+        let config = config(42);
+        let swap = swap_state(&config, &user(), OFFSET);
+        let liquidity = liquidity_state(&config, &user(), OFFSET);
+
+        assert_eq!(
+            escrow_x(&swap),
+            key("E21dqFHmw6XUzDsYDRyx1oyhU5bKm5jwYN5yTdp3GN7t")
+        );
+        assert_eq!(
+            escrow_y(&swap),
+            key("DMsuxNEn9tpzGC2EuoYW12Htvyuruji9Kqi46UckmczB")
+        );
+        assert_eq!(
+            escrow_x(&liquidity),
+            key("EDyGfSAa9rT1R6BQUhctLHCDD6zHrhp2K4pzVRG6SJTH")
+        );
+        assert_eq!(
+            escrow_y(&liquidity),
+            key("E2Hx57KfBtfdEJ8kDwTFQjyNvtfFFchomWj35G45fwEk")
+        );
+        assert_eq!(
+            escrow_lp(&liquidity),
+            key("5qHp6UmyaHhh9NkKNEjnJf6HWBw8gFuqyHH4wsXrBkz5")
+        );
+    }
+
+    mod arcium_pdas {
+        // The macros resolve the program ID and Arcium's seeds at the call
+        // site, like they do inside the program
+        use arcium_anchor::prelude::*;
+        use whispr::{ID, ID_CONST};
+
+        use crate::pda::arcium;
+
+        #[test]
+        fn program_id() {
+            assert_eq!(
+                arcium::ARCIUM_PROGRAM_ID,
+                "BKck65TgoKRokMjQM3datB9oRwJ8rAj2jxPXvHXUvcL6"
+                    .parse::<Pubkey>()
+                    .unwrap()
+            );
+        }
+
+        #[test]
+        fn match_the_program_macros() {
+            assert_eq!(arcium::mxe(), derive_mxe_pda!());
+            assert_eq!(arcium::mempool(), derive_mempool_pda!());
+            assert_eq!(arcium::execpool(), derive_execpool_pda!());
+            for offset in [0u64, 1, super::OFFSET, u64::MAX] {
+                assert_eq!(arcium::computation(offset), derive_comp_pda!(offset));
+            }
+            for circuit in [
+                "compute_swap",
+                "compute_deposit",
+                "compute_withdraw",
+                "seal_reserves",
+                "reveal_reserves",
+                "dark_fund",
+                "dark_swap",
+                "dark_withdraw",
+            ] {
+                assert_eq!(
+                    arcium::comp_def(circuit),
+                    derive_comp_def_pda!(comp_def_offset(circuit)),
+                    "{circuit}"
+                );
+            }
+        }
+
+        #[test]
+        fn cluster() {
+            // `derive_cluster_pda!` reads the offset out of a live MXE account
+            assert_eq!(
+                arcium::cluster(1),
+                "536voMDX7c7FxgQDhciVwQkDHnusWwFTsaHNiEMPMEqo"
+                    .parse::<Pubkey>()
+                    .unwrap()
+            );
+        }
+    }
+}
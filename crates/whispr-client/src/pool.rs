@@ -0,0 +1,57 @@
+use anchor_lang::prelude::Pubkey;
+
+use crate::pda;
+
+/// Every address of one pool, derived from its seed, mints and their token programs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pool {
+    pub seed: u64,
+    pub mint_x: Pubkey,
+    pub mint_y: Pubkey,
+    // SPL Token or Token-2022, whichever owns each mint
+    pub token_program_x: Pubkey,
+    pub token_program_y: Pubkey,
+    pub config: Pubkey,
+    pub mint_lp: Pubkey,
+    pub vault_x: Pubkey,
+    pub vault_y: Pubkey,
+    pub locked_lp: Pubkey,
+    pub protocol_x: Pubkey,
+    pub protocol_y: Pubkey,
+}
+
+impl Pool {
+    pub fn new(
+        seed: u64,
+        mint_x: Pubkey,
+        mint_y: Pubkey,
+        token_program_x: Pubkey,
+        token_program_y: Pubkey,
+    ) -> Self {
+        let config = pda::config(seed);
+        let mint_lp = pda::mint_lp(&config);
+        Self {
+            seed,
+            mint_x,
+            mint_y,
+            token_program_x,
+            token_program_y,
+            config,
+            mint_lp,
+            vault_x: pda::associated_token(&config, &mint_x, &token_program_x),
+            vault_y: pda::associated_token(&config, &mint_y, &token_program_y),
+            locked_lp: pda::associated_token(&config, &mint_lp, &anchor_spl::token::ID),
+            protocol_x: pda::protocol_x(&config),
+            protocol_y: pda::protocol_y(&config),
+        }
+    }
+
+    /// A user's associated token accounts for X, Y and the LP mint.
+    pub fn user_tokens(&self, user: &Pubkey) -> (Pubkey, Pubkey, Pubkey) {
+        (
+            pda::associated_token(user, &self.mint_x, &self.token_program_x),
+            pda::associated_token(user, &self.mint_y, &self.token_program_y),
+            pda::associated_token(user, &self.mint_lp, &anchor_spl::token::ID),
+        )
+    }
+}
@@ -0,0 +1,714 @@
+//! Turns confirmed blocks into store rows: decodes each transaction's Whispr
+//! events and snapshots the vault balances of every pool it touched.
+
+use std::collections::HashMap;
+
+use anchor_lang::Discriminator;
+use anyhow::Result;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedTransaction, EncodedTransactionWithStatusMeta,
+    UiCompiledInstruction, UiConfirmedBlock, UiInnerInstructions, UiInstruction, UiMessage,
+};
+use whispr::instruction::{Deposit, DepositSingle, Withdraw, WithdrawSingle};
+use whispr_client::{
+    decode::{self, WhisprEvent},
+    pda,
+};
+
+use crate::store::{Balances, EventRow, PoolRow, Position, Store};
+
+// The instructions that emit `DepositEvent` and `WithdrawEvent`, which carry no
+// config. Each takes the pool's config as its fourth account.
+const LIQUIDITY_INSTRUCTIONS: [&[u8]; 4] = [
+    Deposit::DISCRIMINATOR,
+    Withdraw::DISCRIMINATOR,
+    DepositSingle::DISCRIMINATOR,
+    WithdrawSingle::DISCRIMINATOR,
+];
+const LIQUIDITY_CONFIG_INDEX: usize = 3;
+
+#[derive(Default)]
+pub struct Stats {
+    pub blocks: u64,
+    pub transactions: u64,
+    pub events: u64,
+}
+
+/// The token accounts whose balances make up a pool's reserves.
+struct Vaults {
+    vault_x: Pubkey,
+    vault_y: Pubkey,
+    dark_x: Pubkey,
+    dark_y: Pubkey,
+}
+
+pub struct Indexer<'a> {
+    store: &'a Store,
+    // Every pool seen so far, by config
+    pools: HashMap<Pubkey, Vaults>,
+    pub stats: Stats,
+}
+
+impl<'a> Indexer<'a> {
+    pub fn new(store: &'a Store) -> Result<Self> {
+        let mut indexer = Self {
+            store,
+            pools: HashMap::new(),
+            stats: Stats::default(),
+        };
+        for pool in store.pools()? {
+            indexer.remember(&pool)?;
+        }
+        Ok(indexer)
+    }
+
+    pub fn add_pool(&mut self, pool: &PoolRow) -> Result<()> {
+        self.store.insert_pool(pool)?;
+        self.remember(pool)
+    }
+
+    fn remember(&mut self, pool: &PoolRow) -> Result<()> {
+        let config: Pubkey = pool.config.parse()?;
+        self.pools.insert(
+            config,
+            Vaults {
+                vault_x: pool.vault_x.parse()?,
+                vault_y: pool.vault_y.parse()?,
+                dark_x: pda::dark_x(&config),
+                dark_y: pda::dark_y(&config),
+            },
+        );
+        Ok(())
+    }
+
+    /// Indexes a block's successful transactions in one store transaction.
+    /// Blocks must be indexed in slot order for the reserve snapshots to carry
+    /// forward correctly.
+    pub fn index_block(&mut self, slot: u64, block: &UiConfirmedBlock) -> Result<()> {
+        let store = self.store;
+        store.atomically(|_| {
+            for (tx_index, transaction) in block.transactions.iter().flatten().enumerate() {
+                let Some(transaction) = Transaction::parse(transaction) else {
+                    continue;
+                };
+                let position = Position {
+                    signature: transaction.signature.clone(),
+                    slot,
+                    tx_index: tx_index as u32,
+                    block_time: block.block_time,
+                };
+                self.index_transaction(&position, &transaction)?;
+            }
+            Ok(())
+        })?;
+        self.stats.blocks += 1;
+        Ok(())
+    }
+
+    fn index_transaction(&mut self, position: &Position, transaction: &Transaction) -> Result<()> {
+        let events = decode::events_from_logs(&transaction.logs);
+        for event in &events {
+            if let WhisprEvent::InitializeEvent(event) = event {
+                self.add_pool(&PoolRow {
+                    config: event.config.to_string(),
+                    mint_x: event.mint_x.to_string(),
+                    mint_y: event.mint_y.to_string(),
+                    mint_lp: event.mint_lp.to_string(),
+                    vault_x: event.vault_x.to_string(),
+                    vault_y: event.vault_y.to_string(),
+                })?;
+            }
+        }
+
+        let touched: Vec<Pubkey> = self
+            .pools
+            .keys()
+            .filter(|config| transaction.account_keys.contains(config))
+            .copied()
+            .collect();
+        if events.is_empty() && touched.is_empty() {
+            return Ok(());
+        }
+
+        let mut liquidity_configs = transaction.liquidity_configs();
+        for (log_index, event) in events.iter().enumerate() {
+            let mut row = row(event);
+            if let WhisprEvent::DepositEvent(_) | WhisprEvent::WithdrawEvent(_) = event {
+                row.config = liquidity_configs.next().map(|config| config.to_string());
+            }
+            self.store.insert_event(position, log_index as u32, &row)?;
+        }
+        for config in &touched {
+            let vaults = &self.pools[config];
+            let balances = Balances {
+                vault_x: transaction.balances.get(&vaults.vault_x).copied(),
+                vault_y: transaction.balances.get(&vaults.vault_y).copied(),
+                dark_x: transaction.balances.get(&vaults.dark_x).copied(),
+                dark_y: transaction.balances.get(&vaults.dark_y).copied(),
+            };
+            if balances != Balances::default() {
+                self.store
+                    .record_reserves(&config.to_string(), position, &balances)?;
+            }
+        }
+
+        self.stats.transactions += 1;
+        self.stats.events += events.len() as u64;
+        Ok(())
+    }
+}
+
+/// What the indexer needs from a transaction fetched with `json` encoding.
+struct Transaction {
+    signature: String,
+    // Static keys followed by the keys loaded from lookup tables
+    account_keys: Vec<Pubkey>,
+    logs: Vec<String>,
+    // Token balances after the transaction, by token account
+    balances: HashMap<Pubkey, u64>,
+    // Every instruction, outer and inner, in the order they executed
+    invocations: Vec<Invocation>,
+}
+
+struct Invocation {
+    program_id: Pubkey,
+    accounts: Vec<Pubkey>,
+    data: Vec<u8>,
+}
+
+impl Transaction {
+    /// `None` for failed transactions, whose events were rolled back.
+    fn parse(transaction: &EncodedTransactionWithStatusMeta) -> Option<Self> {
+        let meta = transaction.meta.as_ref()?;
+        if meta.err.is_some() {
+            return None;
+        }
+        let EncodedTransaction::Json(ui) = &transaction.transaction else {
+            return None;
+        };
+        let UiMessage::Raw(message) = &ui.message else {
+            return None;
+        };
+
+        let mut keys = message.account_keys.clone();
+        if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+            keys.extend(loaded.writable.iter().cloned());
+            keys.extend(loaded.readonly.iter().cloned());
+        }
+        let account_keys = keys
+            .iter()
+            .map(|key| key.parse())
+            .collect::<Result<Vec<Pubkey>, _>>()
+            .ok()?;
+        let logs = match &meta.log_messages {
+            OptionSerializer::Some(logs) => logs.clone(),
+            _ => Vec::new(),
+        };
+        let inner: &[UiInnerInstructions] = match &meta.inner_instructions {
+            OptionSerializer::Some(inner) => inner.as_slice(),
+            _ => &[],
+        };
+        let invocations = invocations(&message.instructions, inner, &account_keys)?;
+        let balances = match &meta.post_token_balances {
+            OptionSerializer::Some(balances) => balances
+                .iter()
+                .filter_map(|balance| {
+                    Some((
+                        *account_keys.get(balance.account_index as usize)?,
+                        balance.ui_token_amount.amount.parse().ok()?,
+                    ))
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        Some(Self {
+            signature: ui.signatures.first()?.clone(),
+            account_keys,
+            logs,
+            balances,
+            invocations,
+        })
+    }
+
+    /// The config of every Whispr deposit and withdrawal, in the order they
+    /// executed, which is the order their events were logged in.
+    fn liquidity_configs(&self) -> impl Iterator<Item = Pubkey> + '_ {
+        self.invocations
+            .iter()
+            .filter(|invocation| {
+                invocation.program_id == whispr::ID
+                    && LIQUIDITY_INSTRUCTIONS
+                        .iter()
+                        .any(|discriminator| invocation.data.starts_with(discriminator))
+            })
+            .filter_map(|invocation| invocation.accounts.get(LIQUIDITY_CONFIG_INDEX).copied())
+    }
+}
+
+/// Resolves compiled instructions against the transaction's account keys, each
+/// outer instruction followed by the instructions it invoked.
+fn invocations(
+    outer: &[UiCompiledInstruction],
+    inner: &[UiInnerInstructions],
+    account_keys: &[Pubkey],
+) -> Option<Vec<Invocation>> {
+    let key = |index: u8| account_keys.get(index as usize).copied();
+    let resolve = |instruction: &UiCompiledInstruction| {
+        Some(Invocation {
+            program_id: key(instruction.program_id_index)?,
+            accounts: instruction
+                .accounts
+                .iter()
+                .map(|index| key(*index))
+                .collect::<Option<_>>()?,
+            data: bs58::decode(&instruction.data).into_vec().ok()?,
+        })
+    };
+
+    let mut invocations = Vec::new();
+    for (index, instruction) in outer.iter().enumerate() {
+        invocations.push(resolve(instruction)?);
+        for invoked in inner.iter().filter(|inner| inner.index as usize == index) {
+            for instruction in &invoked.instructions {
+                // `json` encoding never returns parsed instructions
+                if let UiInstruction::Compiled(instruction) = instruction {
+                    invocations.push(resolve(instruction)?);
+                }
+            }
+        }
+    }
+    Some(invocations)
+}
+
+/// Flattens an event into the columns the queries need. `amount` is the LP
+/// amount of a deposit or withdrawal and the fee of fee events; `x_amount` and
+/// `y_amount` are token amounts, in or out depending on the event.
+fn row(event: &WhisprEvent) -> EventRow {
+    let key = |key: &Pubkey| Some(key.to_string());
+    let mut row = EventRow {
+        name: event.name().to_string(),
+        ..Default::default()
+    };
+    match event {
+        WhisprEvent::InitializeEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.admin);
+            row.amount = Some(event.fee.into());
+        }
+        WhisprEvent::DepositEvent(event) => {
+            row.user = key(&event.user);
+            row.amount = Some(event.amount);
+            row.x_amount = Some(event.x_amount);
+            row.y_amount = Some(event.y_amount);
+        }
+        WhisprEvent::WithdrawEvent(event) => {
+            row.user = key(&event.user);
+            row.amount = Some(event.amount);
+            row.x_amount = Some(event.x_amount);
+            row.y_amount = Some(event.y_amount);
+        }
+        WhisprEvent::SwapEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+            let (x_amount, y_amount) = if event.is_x {
+                (event.deposit_amount, event.withdraw_amount)
+            } else {
+                (event.withdraw_amount, event.deposit_amount)
+            };
+            row.x_amount = Some(x_amount);
+            row.y_amount = Some(y_amount);
+        }
+        WhisprEvent::LockEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::UnlockEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::SetFeeEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+            row.amount = Some(event.fee.into());
+        }
+        WhisprEvent::SetProtocolFeeEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+            row.amount = Some(event.protocol_fee.into());
+        }
+        WhisprEvent::ClaimProtocolFeesEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+            row.x_amount = Some(event.x_amount);
+            row.y_amount = Some(event.y_amount);
+        }
+        WhisprEvent::ProposeAuthorityEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::AcceptAuthorityEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::RenounceAuthorityEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::ConfidentialSwapInitiatedEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::ConfidentialSwapExecutedEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::ConfidentialSwapCancelledEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::ConfidentialSwapFailedEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::ConfidentialLiquidityInitiatedEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::ConfidentialLiquidityExecutedEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::ConfidentialLiquidityCancelledEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::ConfidentialLiquidityFailedEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::DarkPoolEnabledEvent(event) => {
+            row.config = key(&event.config);
+        }
+        WhisprEvent::DarkPoolDisabledEvent(event) => {
+            row.config = key(&event.config);
+            row.x_amount = Some(event.x_amount);
+            row.y_amount = Some(event.y_amount);
+        }
+        WhisprEvent::DarkFundEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+            row.x_amount = Some(event.x_amount);
+            row.y_amount = Some(event.y_amount);
+        }
+        WhisprEvent::DarkSwapEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+        }
+        WhisprEvent::DarkWithdrawEvent(event) => {
+            row.config = key(&event.config);
+            row.user = key(&event.user);
+            row.x_amount = Some(event.x_amount);
+            row.y_amount = Some(event.y_amount);
+        }
+        WhisprEvent::DarkPoolFailedEvent(event) => {
+            row.config = key(&event.config);
+            row.user = event.user.as_ref().and_then(key);
+        }
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::{solana_program::instruction::Instruction, Event};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use whispr_client::{instructions, Pool};
+
+    use super::*;
+
+    const ROUTER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tqUZoK5HPkvqPpkP";
+
+    fn pool(seed: u64) -> Pool {
+        let token_program = Pubkey::new_unique();
+        Pool::new(
+            seed,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            token_program,
+            token_program,
+        )
+    }
+
+    fn pool_row(pool: &Pool) -> PoolRow {
+        PoolRow {
+            config: pool.config.to_string(),
+            mint_x: pool.mint_x.to_string(),
+            mint_y: pool.mint_y.to_string(),
+            mint_lp: pool.mint_lp.to_string(),
+            vault_x: pool.vault_x.to_string(),
+            vault_y: pool.vault_y.to_string(),
+        }
+    }
+
+    fn invocation(instruction: Instruction) -> Invocation {
+        Invocation {
+            program_id: instruction.program_id,
+            accounts: instruction
+                .accounts
+                .iter()
+                .map(|meta| meta.pubkey)
+                .collect(),
+            data: instruction.data,
+        }
+    }
+
+    // Logs of one top level Whispr instruction emitting `events`
+    fn logs(events: &[Vec<u8>]) -> Vec<String> {
+        let whispr = whispr::ID.to_string();
+        let mut logs = vec![format!("Program {whispr} invoke [1]")];
+        for event in events {
+            logs.push(format!("Program data: {}", STANDARD.encode(event)));
+        }
+        logs.push(format!("Program {whispr} success"));
+        logs
+    }
+
+    fn deposit_event(user: Pubkey) -> Vec<u8> {
+        whispr::DepositEvent {
+            user,
+            amount: 10,
+            x_amount: 20,
+            y_amount: 30,
+        }
+        .data()
+    }
+
+    fn withdraw_event(user: Pubkey) -> Vec<u8> {
+        whispr::WithdrawEvent {
+            user,
+            amount: 5,
+            x_amount: 6,
+            y_amount: 7,
+        }
+        .data()
+    }
+
+    fn transaction(
+        signature: &str,
+        invocations: Vec<Invocation>,
+        logs: Vec<String>,
+        balances: HashMap<Pubkey, u64>,
+    ) -> Transaction {
+        let mut account_keys: Vec<Pubkey> = invocations
+            .iter()
+            .flat_map(|invocation| invocation.accounts.iter().copied())
+            .collect();
+        account_keys.extend(balances.keys().copied());
+        Transaction {
+            signature: signature.to_string(),
+            account_keys,
+            logs,
+            balances,
+            invocations,
+        }
+    }
+
+    fn position(signature: &str, slot: u64) -> Position {
+        Position {
+            signature: signature.to_string(),
+            slot,
+            tx_index: 0,
+            block_time: None,
+        }
+    }
+
+    fn configs(store: &Store, pool: &Pool) -> Vec<(String, Option<String>)> {
+        store
+            .events(&pool.config.to_string(), None, 100)
+            .unwrap()
+            .into_iter()
+            .map(|stored| (stored.event.name, stored.event.config))
+            .collect()
+    }
+
+    #[test]
+    fn liquidity_events_take_the_config_of_their_instruction() {
+        let store = Store::open(":memory:").unwrap();
+        let mut indexer = Indexer::new(&store).unwrap();
+        let (first, second) = (pool(1), pool(2));
+        indexer.add_pool(&pool_row(&first)).unwrap();
+        indexer.add_pool(&pool_row(&second)).unwrap();
+        let user = Pubkey::new_unique();
+
+        // One transaction touching both pools, so neither is the only one
+        let transaction = transaction(
+            "a",
+            vec![
+                invocation(instructions::deposit(&first, user, 10, 20, 30, None)),
+                invocation(instructions::withdraw(&second, user, 5, 6, 7, None)),
+                invocation(instructions::lock(&first, user)),
+            ],
+            logs(&[deposit_event(user), withdraw_event(user)]),
+            HashMap::new(),
+        );
+        indexer
+            .index_transaction(&position("a", 1), &transaction)
+            .unwrap();
+
+        let first_config = Some(first.config.to_string());
+        let second_config = Some(second.config.to_string());
+        assert_eq!(
+            configs(&store, &first),
+            [("DepositEvent".to_string(), first_config)]
+        );
+        assert_eq!(
+            configs(&store, &second),
+            [("WithdrawEvent".to_string(), second_config)]
+        );
+    }
+
+    #[test]
+    fn inner_instructions_follow_their_outer_instruction() {
+        let (first, second) = (pool(1), pool(2));
+        let user = Pubkey::new_unique();
+        let router: Pubkey = ROUTER.parse().unwrap();
+        let deposit = instructions::deposit(&first, user, 1, 1, 1, None);
+        let withdraw = instructions::withdraw(&second, user, 1, 1, 1, None);
+
+        let mut account_keys = vec![router];
+        let mut compile = |instruction: &Instruction| {
+            let mut index = |key: Pubkey| match account_keys.iter().position(|k| *k == key) {
+                Some(index) => index as u8,
+                None => {
+                    account_keys.push(key);
+                    account_keys.len() as u8 - 1
+                }
+            };
+            UiCompiledInstruction {
+                program_id_index: index(instruction.program_id),
+                accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|meta| index(meta.pubkey))
+                    .collect(),
+                data: bs58::encode(&instruction.data).into_string(),
+                stack_height: None,
+            }
+        };
+        // A router invoking a deposit, then a top level withdrawal
+        let router_call = UiCompiledInstruction {
+            program_id_index: 0,
+            accounts: vec![],
+            data: String::new(),
+            stack_height: None,
+        };
+        let outer = [router_call, compile(&withdraw)];
+        let inner = [UiInnerInstructions {
+            index: 0,
+            instructions: vec![UiInstruction::Compiled(compile(&deposit))],
+        }];
+
+        let invocations = invocations(&outer, &inner, &account_keys).unwrap();
+        let programs: Vec<Pubkey> = invocations
+            .iter()
+            .map(|invocation| invocation.program_id)
+            .collect();
+        assert_eq!(programs, [router, whispr::ID, whispr::ID]);
+
+        let transaction = Transaction {
+            signature: "a".to_string(),
+            account_keys,
+            logs: vec![],
+            balances: HashMap::new(),
+            invocations,
+        };
+        assert_eq!(
+            transaction.liquidity_configs().collect::<Vec<_>>(),
+            [first.config, second.config]
+        );
+        // Out of range indexes make the transaction unreadable
+        assert!(invocations(&outer, &inner, &[router]).is_none());
+    }
+
+    #[test]
+    fn reserves_keep_dark_vaults_apart() {
+        let store = Store::open(":memory:").unwrap();
+        let mut indexer = Indexer::new(&store).unwrap();
+        let pool = pool(1);
+        indexer.add_pool(&pool_row(&pool)).unwrap();
+        let user = Pubkey::new_unique();
+
+        let transaction = transaction(
+            "a",
+            vec![invocation(instructions::lock(&pool, user))],
+            vec![],
+            HashMap::from([
+                (pool.vault_x, 100),
+                (pool.vault_y, 200),
+                (pda::dark_x(&pool.config), 7),
+            ]),
+        );
+        indexer
+            .index_transaction(&position("a", 1), &transaction)
+            .unwrap();
+
+        let reserves = store.tvl_history(&pool.config.to_string(), 10).unwrap();
+        assert_eq!(reserves.len(), 1);
+        assert_eq!(
+            (
+                reserves[0].reserve_x,
+                reserves[0].reserve_y,
+                reserves[0].dark_x,
+                reserves[0].dark_y
+            ),
+            (100, 200, 7, 0)
+        );
+    }
+
+    #[test]
+    fn replaying_a_transaction_is_a_no_op() {
+        let store = Store::open(":memory:").unwrap();
+        let pool = pool(1);
+        let user = Pubkey::new_unique();
+        let transaction = transaction(
+            "a",
+            vec![invocation(instructions::deposit(
+                &pool, user, 10, 20, 30, None,
+            ))],
+            logs(&[deposit_event(user)]),
+            HashMap::from([(pool.vault_x, 120), (pool.vault_y, 230)]),
+        );
+
+        for _ in 0..2 {
+            // A restarted indexer reloads its pools from the store
+            let mut indexer = Indexer::new(&store).unwrap();
+            indexer.add_pool(&pool_row(&pool)).unwrap();
+            store
+                .atomically(|_| indexer.index_transaction(&position("a", 1), &transaction))
+                .unwrap();
+        }
+
+        let config = pool.config.to_string();
+        assert_eq!(store.events(&config, None, 10).unwrap().len(), 1);
+        assert_eq!(store.tvl_history(&config, 10).unwrap().len(), 1);
+        assert_eq!(store.pools().unwrap(), [pool_row(&pool)]);
+    }
+
+    #[test]
+    fn swap_amounts_follow_the_direction() {
+        let event = |is_x| {
+            WhisprEvent::SwapEvent(whispr::SwapEvent {
+                user: Pubkey::new_unique(),
+                config: Pubkey::new_unique(),
+                is_x,
+                deposit_amount: 10,
+                withdraw_amount: 3,
+            })
+        };
+        let amounts = |row: EventRow| (row.x_amount, row.y_amount);
+        assert_eq!(amounts(row(&event(true))), (Some(10), Some(3)));
+        assert_eq!(amounts(row(&event(false))), (Some(3), Some(10)));
+    }
+}
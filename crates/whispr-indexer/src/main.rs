@@ -0,0 +1,265 @@
+//! `whispr-indexer`: decodes Whispr events from confirmed blocks into a local
+//! SQLite database and answers queries over it.
+//!
+//! Blocks come either from an RPC node or from a block archive: a JSON lines
+//! file of `{"slot": .., "block": ..}` objects, where `block` is a `getBlock`
+//! result in `json` encoding with full transaction details. `index --archive`
+//! writes such a file while it indexes, so a ledger replayed through a local
+//! validator once can be re-indexed offline.
+
+mod ingest;
+mod store;
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+};
+
+use anchor_lang::Discriminator;
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcBlockConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use solana_transaction_status::{TransactionDetails, UiConfirmedBlock, UiTransactionEncoding};
+use whispr_client::{decode, Config, Pool};
+
+use crate::{
+    ingest::Indexer,
+    store::{PoolRow, Position, Store, StoredEvent},
+};
+
+#[derive(Parser)]
+#[command(
+    name = "whispr-indexer",
+    version,
+    about = "Index Whispr events into SQLite and query them"
+)]
+struct Cli {
+    /// SQLite database, created if missing
+    #[arg(long, global = true, env = "WHISPR_DB", default_value = "whispr.db")]
+    db: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Index a slot range from an RPC node
+    Index {
+        #[arg(
+            long,
+            short = 'u',
+            env = "WHISPR_RPC",
+            default_value = "http://127.0.0.1:8899"
+        )]
+        rpc: String,
+        #[arg(long)]
+        from_slot: u64,
+        /// Defaults to the latest confirmed slot
+        #[arg(long)]
+        to_slot: Option<u64>,
+        /// Also append every fetched block to this archive
+        #[arg(long)]
+        archive: Option<String>,
+    },
+    /// Index a block archive
+    Import { archive: String },
+    /// A pool's reserves, and its dark pool vaults, after every transaction
+    /// that moved them
+    Tvl {
+        #[arg(long)]
+        config: Pubkey,
+        /// Latest snapshots to return
+        #[arg(long, default_value_t = 100)]
+        limit: u32,
+    },
+    /// A user's public and confidential deposits and withdrawals
+    LpActivity {
+        #[arg(long)]
+        user: Pubkey,
+        #[arg(long)]
+        config: Option<Pubkey>,
+    },
+    /// A pool's latest events
+    Events {
+        #[arg(long)]
+        config: Pubkey,
+        /// Only events of this kind, e.g. SwapEvent
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, default_value_t = 100)]
+        limit: u32,
+    },
+}
+
+/// One line of a block archive.
+#[derive(Serialize, Deserialize)]
+struct ArchivedBlock {
+    slot: u64,
+    block: UiConfirmedBlock,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let store = Store::open(&cli.db).with_context(|| format!("opening {}", cli.db))?;
+
+    let output = match cli.command {
+        Command::Index {
+            rpc,
+            from_slot,
+            to_slot,
+            archive,
+        } => {
+            let rpc = RpcClient::new_with_commitment(rpc, CommitmentConfig::confirmed());
+            let mut indexer = Indexer::new(&store)?;
+            load_pools(&rpc, &mut indexer)?;
+            let mut archive = archive
+                .map(|path| OpenOptions::new().create(true).append(true).open(path))
+                .transpose()?;
+
+            let to_slot = match to_slot {
+                Some(slot) => slot,
+                None => rpc.get_slot()?,
+            };
+            // Skipped slots have no block
+            for slot in rpc.get_blocks(from_slot, Some(to_slot))? {
+                let block = rpc.get_block_with_config(
+                    slot,
+                    RpcBlockConfig {
+                        encoding: Some(UiTransactionEncoding::Json),
+                        transaction_details: Some(TransactionDetails::Full),
+                        rewards: Some(false),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )?;
+                indexer.index_block(slot, &block)?;
+                if let Some(archive) = archive.as_mut() {
+                    serde_json::to_writer(&mut *archive, &ArchivedBlock { slot, block })?;
+                    archive.write_all(b"\n")?;
+                }
+            }
+            stats_json(&indexer)
+        }
+        Command::Import { archive } => {
+            let mut indexer = Indexer::new(&store)?;
+            let file = File::open(&archive).with_context(|| format!("opening {archive}"))?;
+            for (number, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let archived: ArchivedBlock = serde_json::from_str(&line)
+                    .with_context(|| format!("{archive}:{}", number + 1))?;
+                indexer.index_block(archived.slot, &archived.block)?;
+            }
+            stats_json(&indexer)
+        }
+        Command::Tvl { config, limit } => Value::Array(
+            store
+                .tvl_history(&config.to_string(), limit)?
+                .into_iter()
+                .map(|reserves| {
+                    let mut row = position_json(&reserves.position);
+                    row["reserve_x"] = json!(reserves.reserve_x);
+                    row["reserve_y"] = json!(reserves.reserve_y);
+                    row["dark_x"] = json!(reserves.dark_x);
+                    row["dark_y"] = json!(reserves.dark_y);
+                    row
+                })
+                .collect(),
+        ),
+        Command::LpActivity { user, config } => events_json(
+            store.lp_activity(&user.to_string(), config.map(|c| c.to_string()).as_deref())?,
+        ),
+        Command::Events {
+            config,
+            name,
+            limit,
+        } => events_json(store.events(&config.to_string(), name.as_deref(), limit)?),
+    };
+
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+/// Registers every pool on chain, so their vault balances are snapshotted even
+/// when the range starts after the pool's `InitializeEvent`.
+fn load_pools(rpc: &RpcClient, indexer: &mut Indexer) -> Result<()> {
+    let accounts = rpc.get_program_accounts_with_config(
+        &whispr::ID,
+        RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                0,
+                Config::DISCRIMINATOR,
+            ))]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(CommitmentConfig::confirmed()),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )?;
+    for (address, account) in accounts {
+        let config: Config =
+            decode::account(&account.data).map_err(|err| anyhow!("decoding {address}: {err}"))?;
+        let pool = Pool::new(
+            config.seed,
+            config.mint_x,
+            config.mint_y,
+            rpc.get_account(&config.mint_x)?.owner,
+            rpc.get_account(&config.mint_y)?.owner,
+        );
+        indexer.add_pool(&PoolRow {
+            config: pool.config.to_string(),
+            mint_x: pool.mint_x.to_string(),
+            mint_y: pool.mint_y.to_string(),
+            mint_lp: pool.mint_lp.to_string(),
+            vault_x: pool.vault_x.to_string(),
+            vault_y: pool.vault_y.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+fn stats_json(indexer: &Indexer) -> Value {
+    json!({
+        "blocks": indexer.stats.blocks,
+        "transactions": indexer.stats.transactions,
+        "events": indexer.stats.events,
+    })
+}
+
+fn position_json(position: &Position) -> Value {
+    json!({
+        "signature": position.signature,
+        "slot": position.slot,
+        "block_time": position.block_time,
+    })
+}
+
+fn events_json(events: Vec<StoredEvent>) -> Value {
+    Value::Array(
+        events
+            .into_iter()
+            .map(|StoredEvent { position, event }| {
+                let mut row = position_json(&position);
+                row["name"] = json!(event.name);
+                row["config"] = json!(event.config);
+                row["user"] = json!(event.user);
+                row["amount"] = json!(event.amount);
+                row["x_amount"] = json!(event.x_amount);
+                row["y_amount"] = json!(event.y_amount);
+                row
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,601 @@
+//! SQLite store for indexed events, pools and reserve snapshots.
+//!
+//! Addresses are stored base58 encoded and amounts as integers. Rows are keyed
+//! by transaction signature, so indexing the same block twice is a no-op.
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS pools (
+    config TEXT PRIMARY KEY,
+    mint_x TEXT NOT NULL,
+    mint_y TEXT NOT NULL,
+    mint_lp TEXT NOT NULL,
+    vault_x TEXT NOT NULL,
+    vault_y TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    signature TEXT NOT NULL,
+    log_index INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    tx_index INTEGER NOT NULL,
+    block_time INTEGER,
+    name TEXT NOT NULL,
+    config TEXT,
+    user TEXT,
+    amount INTEGER,
+    x_amount INTEGER,
+    y_amount INTEGER,
+    PRIMARY KEY (signature, log_index)
+);
+CREATE INDEX IF NOT EXISTS events_by_config ON events (config, slot, tx_index);
+CREATE INDEX IF NOT EXISTS events_by_user ON events (user, slot, tx_index);
+CREATE TABLE IF NOT EXISTS reserves (
+    config TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    tx_index INTEGER NOT NULL,
+    block_time INTEGER,
+    vault_x INTEGER NOT NULL,
+    vault_y INTEGER NOT NULL,
+    dark_x INTEGER NOT NULL,
+    dark_y INTEGER NOT NULL,
+    PRIMARY KEY (config, signature)
+);
+CREATE INDEX IF NOT EXISTS reserves_by_slot ON reserves (config, slot, tx_index);
+";
+
+// Events that change a user's LP position
+const LP_EVENTS: &str = "'DepositEvent', 'WithdrawEvent', 'ConfidentialLiquidityInitiatedEvent', \
+    'ConfidentialLiquidityExecutedEvent', 'ConfidentialLiquidityCancelledEvent', \
+    'ConfidentialLiquidityFailedEvent'";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolRow {
+    pub config: String,
+    pub mint_x: String,
+    pub mint_y: String,
+    pub mint_lp: String,
+    pub vault_x: String,
+    pub vault_y: String,
+}
+
+/// Where a transaction sits in the ledger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub signature: String,
+    pub slot: u64,
+    pub tx_index: u32,
+    pub block_time: Option<i64>,
+}
+
+/// One decoded event. Amounts mean what the event's own fields mean; they are
+/// `None` for events that carry none, or only ciphertexts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventRow {
+    pub name: String,
+    pub config: Option<String>,
+    pub user: Option<String>,
+    pub amount: Option<u64>,
+    pub x_amount: Option<u64>,
+    pub y_amount: Option<u64>,
+}
+
+/// Token balances of a pool's vaults after a transaction. `None` for the
+/// accounts the transaction did not touch.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Balances {
+    pub vault_x: Option<u64>,
+    pub vault_y: Option<u64>,
+    pub dark_x: Option<u64>,
+    pub dark_y: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reserves {
+    pub position: Position,
+    // The public vaults the curve trades against
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    // The dark pool vaults, which also hold traders' unspent dark balances,
+    // so they are not part of the reserves
+    pub dark_x: u64,
+    pub dark_y: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredEvent {
+    pub position: Position,
+    pub event: EventRow,
+}
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Runs `f` in one SQLite transaction, so a block is indexed entirely or
+    /// not at all.
+    pub fn atomically<T>(&self, f: impl FnOnce(&Self) -> Result<T>) -> Result<T> {
+        self.conn.execute_batch("BEGIN")?;
+        match f(self) {
+            Ok(value) => {
+                self.conn.execute_batch("COMMIT")?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.conn.execute_batch("ROLLBACK")?;
+                Err(err)
+            }
+        }
+    }
+
+    pub fn insert_pool(&self, pool: &PoolRow) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO pools (config, mint_x, mint_y, mint_lp, vault_x, vault_y)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                pool.config,
+                pool.mint_x,
+                pool.mint_y,
+                pool.mint_lp,
+                pool.vault_x,
+                pool.vault_y
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn pools(&self) -> Result<Vec<PoolRow>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT config, mint_x, mint_y, mint_lp, vault_x, vault_y FROM pools")?;
+        let rows = statement.query_map([], |row| {
+            Ok(PoolRow {
+                config: row.get(0)?,
+                mint_x: row.get(1)?,
+                mint_y: row.get(2)?,
+                mint_lp: row.get(3)?,
+                vault_x: row.get(4)?,
+                vault_y: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    pub fn insert_event(
+        &self,
+        position: &Position,
+        log_index: u32,
+        event: &EventRow,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO events
+             (signature, log_index, slot, tx_index, block_time, name, config, user, amount, x_amount, y_amount)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                position.signature,
+                log_index,
+                position.slot,
+                position.tx_index,
+                position.block_time,
+                event.name,
+                event.config,
+                event.user,
+                event.amount,
+                event.x_amount,
+                event.y_amount,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records a pool's reserves after a transaction, carrying the balances it
+    /// did not touch forward from the snapshot before it in the ledger.
+    /// Recording a transaction again is a no-op, but later snapshots are not
+    /// revisited, so new transactions must be recorded in ledger order.
+    pub fn record_reserves(
+        &self,
+        config: &str,
+        position: &Position,
+        balances: &Balances,
+    ) -> Result<()> {
+        let previous = self
+            .conn
+            .query_row(
+                "SELECT vault_x, vault_y, dark_x, dark_y FROM reserves
+                 WHERE config = ?1 AND (slot < ?2 OR (slot = ?2 AND tx_index < ?3))
+                 ORDER BY slot DESC, tx_index DESC LIMIT 1",
+                params![config, position.slot, position.tx_index],
+                |row| Ok([row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?]),
+            )
+            .optional()?
+            .unwrap_or([0u64; 4]);
+        self.conn.execute(
+            "INSERT OR IGNORE INTO reserves
+             (config, signature, slot, tx_index, block_time, vault_x, vault_y, dark_x, dark_y)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                config,
+                position.signature,
+                position.slot,
+                position.tx_index,
+                position.block_time,
+                balances.vault_x.unwrap_or(previous[0]),
+                balances.vault_y.unwrap_or(previous[1]),
+                balances.dark_x.unwrap_or(previous[2]),
+                balances.dark_y.unwrap_or(previous[3]),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The pool's reserves after every transaction that moved them, oldest
+    /// first, limited to the latest `limit`.
+    pub fn tvl_history(&self, config: &str, limit: u32) -> Result<Vec<Reserves>> {
+        let mut statement = self.conn.prepare(
+            "SELECT * FROM (
+                 SELECT signature, slot, tx_index, block_time, vault_x, vault_y, dark_x, dark_y
+                 FROM reserves WHERE config = ?1
+                 ORDER BY slot DESC, tx_index DESC LIMIT ?2
+             ) ORDER BY slot, tx_index",
+        )?;
+        let rows = statement.query_map(params![config, limit], |row| {
+            Ok(Reserves {
+                position: Position {
+                    signature: row.get(0)?,
+                    slot: row.get(1)?,
+                    tx_index: row.get(2)?,
+                    block_time: row.get(3)?,
+                },
+                reserve_x: row.get(4)?,
+                reserve_y: row.get(5)?,
+                dark_x: row.get(6)?,
+                dark_y: row.get(7)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// A user's deposits and withdrawals, public and confidential, oldest first.
+    pub fn lp_activity(&self, user: &str, config: Option<&str>) -> Result<Vec<StoredEvent>> {
+        self.query_events(
+            &format!(
+                "WHERE user = ?1 AND (?2 IS NULL OR config = ?2) AND name IN ({LP_EVENTS})
+                 ORDER BY slot, tx_index, log_index"
+            ),
+            params![user, config],
+        )
+    }
+
+    /// A pool's events, optionally of one kind, limited to the latest `limit`
+    /// and returned oldest first.
+    pub fn events(&self, config: &str, name: Option<&str>, limit: u32) -> Result<Vec<StoredEvent>> {
+        let mut events = self.query_events(
+            "WHERE config = ?1 AND (?2 IS NULL OR name = ?2)
+             ORDER BY slot DESC, tx_index DESC, log_index DESC LIMIT ?3",
+            params![config, name, limit],
+        )?;
+        events.reverse();
+        Ok(events)
+    }
+
+    fn query_events(
+        &self,
+        filter: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<StoredEvent>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT signature, slot, tx_index, block_time, name, config, user, amount, x_amount, y_amount
+             FROM events {filter}"
+        ))?;
+        let rows = statement.query_map(params, |row| {
+            Ok(StoredEvent {
+                position: Position {
+                    signature: row.get(0)?,
+                    slot: row.get(1)?,
+                    tx_index: row.get(2)?,
+                    block_time: row.get(3)?,
+                },
+                event: EventRow {
+                    name: row.get(4)?,
+                    config: row.get(5)?,
+                    user: row.get(6)?,
+                    amount: row.get(7)?,
+                    x_amount: row.get(8)?,
+                    y_amount: row.get(9)?,
+                },
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> Store {
+        Store::open(":memory:").unwrap()
+    }
+
+    fn position(signature: &str, slot: u64, tx_index: u32) -> Position {
+        Position {
+            signature: signature.to_string(),
+            slot,
+            tx_index,
+            block_time: Some(slot as i64),
+        }
+    }
+
+    fn event(name: &str, config: &str, user: &str) -> EventRow {
+        EventRow {
+            name: name.to_string(),
+            config: Some(config.to_string()),
+            user: Some(user.to_string()),
+            amount: Some(1),
+            x_amount: Some(2),
+            y_amount: None,
+        }
+    }
+
+    fn balances(vault_x: Option<u64>, vault_y: Option<u64>, dark_x: Option<u64>) -> Balances {
+        Balances {
+            vault_x,
+            vault_y,
+            dark_x,
+            dark_y: None,
+        }
+    }
+
+    // (vault_x, vault_y, dark_x, dark_y) of every snapshot, oldest first
+    fn snapshots(store: &Store, config: &str) -> Vec<(u64, u64, u64, u64)> {
+        store
+            .tvl_history(config, 100)
+            .unwrap()
+            .into_iter()
+            .map(|reserves| {
+                (
+                    reserves.reserve_x,
+                    reserves.reserve_y,
+                    reserves.dark_x,
+                    reserves.dark_y,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pools_round_trip() {
+        let store = store();
+        let pool = PoolRow {
+            config: "config".to_string(),
+            mint_x: "mint_x".to_string(),
+            mint_y: "mint_y".to_string(),
+            mint_lp: "mint_lp".to_string(),
+            vault_x: "vault_x".to_string(),
+            vault_y: "vault_y".to_string(),
+        };
+        store.insert_pool(&pool).unwrap();
+        store.insert_pool(&pool).unwrap();
+        assert_eq!(store.pools().unwrap(), [pool]);
+    }
+
+    #[test]
+    fn events_round_trip_oldest_first() {
+        let store = store();
+        let lock = event("LockEvent", "pool", "alice");
+        let swap = event("SwapEvent", "pool", "bob");
+        store.insert_event(&position("b", 2, 0), 0, &swap).unwrap();
+        store.insert_event(&position("a", 1, 0), 0, &lock).unwrap();
+        store
+            .insert_event(
+                &position("c", 3, 0),
+                0,
+                &event("LockEvent", "other", "alice"),
+            )
+            .unwrap();
+
+        let events = store.events("pool", None, 10).unwrap();
+        assert_eq!(
+            events,
+            [
+                StoredEvent {
+                    position: position("a", 1, 0),
+                    event: lock.clone(),
+                },
+                StoredEvent {
+                    position: position("b", 2, 0),
+                    event: swap,
+                },
+            ]
+        );
+        // The latest `limit`, still oldest first
+        assert_eq!(
+            store.events("pool", None, 1).unwrap()[0].position.signature,
+            "b"
+        );
+        assert_eq!(
+            store.events("pool", Some("LockEvent"), 10).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn lp_activity_filters_liquidity_events() {
+        let store = store();
+        for (index, name) in ["DepositEvent", "SwapEvent", "WithdrawEvent"]
+            .into_iter()
+            .enumerate()
+        {
+            store
+                .insert_event(
+                    &position("a", 1, 0),
+                    index as u32,
+                    &event(name, "pool", "alice"),
+                )
+                .unwrap();
+        }
+        store
+            .insert_event(
+                &position("b", 2, 0),
+                0,
+                &event("DepositEvent", "other", "alice"),
+            )
+            .unwrap();
+
+        let names = |events: Vec<StoredEvent>| -> Vec<String> {
+            events.into_iter().map(|stored| stored.event.name).collect()
+        };
+        assert_eq!(
+            names(store.lp_activity("alice", Some("pool")).unwrap()),
+            ["DepositEvent", "WithdrawEvent"]
+        );
+        assert_eq!(store.lp_activity("alice", None).unwrap().len(), 3);
+        assert!(store.lp_activity("bob", None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reserves_carry_untouched_balances_forward() {
+        let store = store();
+        store
+            .record_reserves(
+                "pool",
+                &position("a", 1, 0),
+                &balances(Some(100), Some(200), None),
+            )
+            .unwrap();
+        store
+            .record_reserves(
+                "pool",
+                &position("b", 1, 1),
+                &balances(Some(150), None, None),
+            )
+            .unwrap();
+        store
+            .record_reserves(
+                "pool",
+                &position("c", 2, 0),
+                &balances(None, None, Some(40)),
+            )
+            .unwrap();
+        store
+            .record_reserves(
+                "other",
+                &position("c", 2, 0),
+                &balances(Some(1), Some(1), None),
+            )
+            .unwrap();
+
+        // Dark vault balances stay out of the reserves
+        assert_eq!(
+            snapshots(&store, "pool"),
+            [(100, 200, 0, 0), (150, 200, 0, 0), (150, 200, 40, 0)]
+        );
+        let latest = store.tvl_history("pool", 1).unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].position, position("c", 2, 0));
+    }
+
+    #[test]
+    fn replaying_is_a_no_op() {
+        let store = store();
+        let lock = event("LockEvent", "pool", "alice");
+        for _ in 0..2 {
+            store.insert_event(&position("a", 1, 0), 0, &lock).unwrap();
+            store
+                .record_reserves(
+                    "pool",
+                    &position("a", 1, 0),
+                    &balances(Some(100), Some(200), None),
+                )
+                .unwrap();
+            store
+                .record_reserves(
+                    "pool",
+                    &position("b", 2, 0),
+                    &balances(Some(120), None, None),
+                )
+                .unwrap();
+        }
+        // Replaying an earlier transaction after a later one changes neither
+        store
+            .record_reserves(
+                "pool",
+                &position("a", 1, 0),
+                &balances(Some(999), None, None),
+            )
+            .unwrap();
+
+        assert_eq!(store.events("pool", None, 10).unwrap().len(), 1);
+        assert_eq!(
+            snapshots(&store, "pool"),
+            [(100, 200, 0, 0), (120, 200, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn backfilled_snapshots_carry_from_the_one_before_them() {
+        let store = store();
+        store
+            .record_reserves(
+                "pool",
+                &position("a", 1, 0),
+                &balances(Some(100), Some(200), None),
+            )
+            .unwrap();
+        store
+            .record_reserves(
+                "pool",
+                &position("c", 3, 0),
+                &balances(Some(300), Some(400), None),
+            )
+            .unwrap();
+        // A transaction from a block that was missed and indexed afterwards
+        store
+            .record_reserves(
+                "pool",
+                &position("b", 2, 0),
+                &balances(Some(110), None, None),
+            )
+            .unwrap();
+
+        assert_eq!(
+            snapshots(&store, "pool"),
+            [(100, 200, 0, 0), (110, 200, 0, 0), (300, 400, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn failed_blocks_roll_back() {
+        let store = store();
+        let result: Result<()> = store.atomically(|store| {
+            store.insert_event(
+                &position("a", 1, 0),
+                0,
+                &event("LockEvent", "pool", "alice"),
+            )?;
+            anyhow::bail!("fetching the next transaction")
+        });
+        assert!(result.is_err());
+        assert!(store.events("pool", None, 10).unwrap().is_empty());
+
+        store
+            .atomically(|store| {
+                store.insert_event(
+                    &position("a", 1, 0),
+                    0,
+                    &event("LockEvent", "pool", "alice"),
+                )
+            })
+            .unwrap();
+        assert_eq!(store.events("pool", None, 10).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,706 @@
+//! Keeper for Whispr confidential computations.
+//!
+//! Callbacks settle atomically, so there is nothing left to execute once one
+//! lands. What can go wrong is that it never lands, leaving something stuck
+//! until someone cancels it after the pool's swap timeout:
+//!
+//! - a swap's escrowed tokens, refunded by `cancel_swap`
+//! - a deposit's or withdrawal's escrowed tokens, refunded by `cancel_deposit`
+//!   or `cancel_withdraw`
+//! - a pool whose dark pool seal was queued, closed to public trading until
+//!   `cancel_enable_dark_pool` reopens it
+//!
+//! The keeper follows the program's logs to track each of them and cancels it
+//! as soon as it expires. Anything queued while it was not listening is picked
+//! up from a scan of the program's accounts at startup and after every
+//! reconnect.
+//!
+//! Other dark pool computations need no crank: the next one queued reclaims
+//! an expired slot, and funds of a `dark_fund` that never landed are credited
+//! by the user's next one. Settled swaps and liquidity states are left for
+//! their user to close, as `close_swap` and `close_liquidity` need their
+//! signature.
+
+mod metrics;
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{atomic::Ordering, mpsc::RecvTimeoutError, Arc},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anchor_lang::Discriminator;
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use log::{info, warn};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{
+        RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcTransactionLogsConfig,
+        RpcTransactionLogsFilter,
+    },
+    rpc_filter::{Memcmp, RpcFilterType},
+    rpc_response::RpcLogsResponse,
+};
+use solana_pubsub_client::pubsub_client::PubsubClient;
+use solana_sdk::{
+    account::from_account,
+    clock::Clock,
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature, Signer},
+    sysvar,
+    transaction::Transaction,
+};
+use whispr_client::{
+    decode::{self, WhisprEvent},
+    instructions, pda, Config, DarkPool, LiquidityState, LiquidityStatus, Pool, SwapState,
+    SwapStatus,
+};
+
+use crate::metrics::Metrics;
+
+#[derive(Parser)]
+#[command(
+    name = "whispr-keeper",
+    version,
+    about = "Cancel Whispr confidential computations whose callback never lands"
+)]
+struct Args {
+    /// Keypair that pays for the cancel transactions
+    #[arg(
+        long,
+        short = 'k',
+        env = "WHISPR_KEYPAIR",
+        default_value = "~/.config/solana/id.json"
+    )]
+    keypair: String,
+    #[arg(
+        long,
+        short = 'u',
+        env = "WHISPR_RPC",
+        default_value = "http://127.0.0.1:8899"
+    )]
+    rpc: String,
+    /// Websocket endpoint, the RPC URL on the next port by default
+    #[arg(long, env = "WHISPR_WS")]
+    ws: Option<String>,
+    /// Where the Prometheus metrics are served
+    #[arg(long, env = "WHISPR_METRICS", default_value = "127.0.0.1:9464")]
+    metrics: SocketAddr,
+    /// Seconds between checks for expired computations
+    #[arg(long, default_value_t = 5)]
+    interval: u64,
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+    let keypair = expand_home(&args.keypair);
+    let signer =
+        read_keypair_file(&keypair).map_err(|err| anyhow!("reading keypair {keypair}: {err}"))?;
+    let metrics = Arc::new(Metrics::default());
+    metrics::serve(args.metrics, metrics.clone())
+        .with_context(|| format!("serving metrics on {}", args.metrics))?;
+
+    let ws = args.ws.unwrap_or_else(|| ws_url(&args.rpc));
+    let interval = Duration::from_secs(args.interval);
+    let mut keeper = Keeper {
+        rpc: RpcClient::new_with_commitment(args.rpc, CommitmentConfig::confirmed()),
+        signer,
+        pools: HashMap::new(),
+        pending: HashMap::new(),
+        metrics,
+    };
+
+    loop {
+        let (_subscription, logs) = match PubsubClient::logs_subscribe(
+            &ws,
+            RpcTransactionLogsFilter::Mentions(vec![whispr::ID.to_string()]),
+            RpcTransactionLogsConfig {
+                commitment: Some(CommitmentConfig::confirmed()),
+            },
+        ) {
+            Ok(subscription) => subscription,
+            Err(err) => {
+                warn!("subscribing to {ws}: {err}");
+                sleep(interval);
+                continue;
+            }
+        };
+        // Scan after subscribing so that nothing falls between the two
+        for discriminator in [
+            SwapState::DISCRIMINATOR,
+            LiquidityState::DISCRIMINATOR,
+            DarkPool::DISCRIMINATOR,
+        ] {
+            if let Err(err) = keeper.scan(discriminator) {
+                warn!("scanning program accounts: {err:#}");
+            }
+        }
+
+        let mut checked = Instant::now();
+        loop {
+            match logs.recv_timeout(interval) {
+                Ok(response) => keeper.handle(&response.value),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if checked.elapsed() >= interval {
+                if let Err(err) = keeper.cancel_expired() {
+                    warn!("cancelling expired computations: {err:#}");
+                }
+                checked = Instant::now();
+            }
+        }
+        warn!("log subscription to {ws} closed, reconnecting");
+        Metrics::inc(&keeper.metrics.reconnects);
+    }
+}
+
+/// What a tracked computation holds up, which decides how it is cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Swap,
+    Deposit,
+    Withdraw,
+    Seal,
+}
+
+impl Kind {
+    /// Discriminator of the account the computation is tracked by.
+    fn account(self) -> &'static [u8] {
+        match self {
+            Kind::Swap => SwapState::DISCRIMINATOR,
+            Kind::Deposit | Kind::Withdraw => LiquidityState::DISCRIMINATOR,
+            Kind::Seal => DarkPool::DISCRIMINATOR,
+        }
+    }
+}
+
+/// A computation waiting for its callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Pending {
+    kind: Kind,
+    config: Pubkey,
+    // Default for seals, which belong to the pool and are cancelled by
+    // config alone
+    user: Pubkey,
+    computation_offset: u64,
+    // When the computation was queued, the start of its timeout
+    created_at: i64,
+}
+
+/// The computation a `SwapState`, `LiquidityState` or `DarkPool` account is
+/// waiting for, if any. Other accounts are never waiting.
+fn waiting_for(data: &[u8]) -> anchor_lang::Result<Option<Pending>> {
+    if data.starts_with(SwapState::DISCRIMINATOR) {
+        let swap: SwapState = decode::account(data)?;
+        Ok(
+            matches!(swap.status, SwapStatus::Initiated | SwapStatus::Computing).then_some(
+                Pending {
+                    kind: Kind::Swap,
+                    config: swap.config,
+                    user: swap.user,
+                    computation_offset: swap.computation_offset,
+                    created_at: swap.created_at,
+                },
+            ),
+        )
+    } else if data.starts_with(LiquidityState::DISCRIMINATOR) {
+        let state: LiquidityState = decode::account(data)?;
+        Ok(matches!(
+            state.status,
+            LiquidityStatus::Initiated | LiquidityStatus::Computing
+        )
+        .then_some(Pending {
+            kind: match state.is_deposit {
+                true => Kind::Deposit,
+                false => Kind::Withdraw,
+            },
+            config: state.config,
+            user: state.user,
+            computation_offset: state.computation_offset,
+            created_at: state.created_at,
+        }))
+    } else if data.starts_with(DarkPool::DISCRIMINATOR) {
+        let dark_pool: DarkPool = decode::account(data)?;
+        // The slot of an unsealed pool is only ever held by its seal
+        Ok(dark_pool
+            .pending
+            .filter(|_| !dark_pool.sealed)
+            .map(|held| Pending {
+                kind: Kind::Seal,
+                config: dark_pool.config,
+                user: Pubkey::default(),
+                computation_offset: held.computation_offset,
+                created_at: held.since,
+            }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The tracked computations anyone may cancel at `now`: those whose
+/// `created_at` plus their pool's swap timeout has passed, as the cancel
+/// instructions check it. Those of pools missing from `swap_timeouts` are left
+/// alone.
+fn expired(
+    pending: &HashMap<Pubkey, Pending>,
+    swap_timeouts: &HashMap<Pubkey, i64>,
+    now: i64,
+) -> Vec<Pubkey> {
+    let mut expired: Vec<Pubkey> = pending
+        .iter()
+        .filter(|(_, pending)| {
+            swap_timeouts
+                .get(&pending.config)
+                .is_some_and(|timeout| now >= pending.created_at.saturating_add(*timeout))
+        })
+        .map(|(address, _)| *address)
+        .collect();
+    // Oldest first, so a backlog drains in the order it built up
+    expired.sort_by_key(|address| (pending[address].created_at, *address));
+    expired
+}
+
+struct Keeper {
+    rpc: RpcClient,
+    signer: Keypair,
+    // Pools by config with their swap timeout, loaded the first time one of
+    // their computations shows up. Only `initialize_amm` sets the timeout,
+    // so it never needs reloading.
+    pools: HashMap<Pubkey, (Pool, i64)>,
+    // By the address of the account tracking each computation
+    pending: HashMap<Pubkey, Pending>,
+    metrics: Arc<Metrics>,
+}
+
+impl Keeper {
+    fn pool(&mut self, config: &Pubkey) -> Result<&Pool> {
+        if !self.pools.contains_key(config) {
+            let account: Config = decode::account(&self.rpc.get_account_data(config)?)
+                .map_err(|err| anyhow!("decoding {config}: {err}"))?;
+            let pool = Pool::new(
+                account.seed,
+                account.mint_x,
+                account.mint_y,
+                self.rpc.get_account(&account.mint_x)?.owner,
+                self.rpc.get_account(&account.mint_y)?.owner,
+            );
+            self.pools.insert(*config, (pool, account.swap_timeout));
+        }
+        Ok(&self.pools[config].0)
+    }
+
+    /// The computation `address` is still waiting for, if any.
+    fn fetch_pending(&self, address: &Pubkey) -> Result<Option<Pending>> {
+        let Some(account) = self
+            .rpc
+            .get_account_with_commitment(address, CommitmentConfig::confirmed())?
+            .value
+        else {
+            return Ok(None);
+        };
+        waiting_for(&account.data).map_err(|err| anyhow!("decoding {address}: {err}"))
+    }
+
+    /// Loads the pool, which cancelling the computation needs, and tracks it.
+    fn track(&mut self, address: Pubkey, pending: Pending) -> Result<()> {
+        self.pool(&pending.config)?;
+        self.pending.insert(address, pending);
+        self.count_pending();
+        Ok(())
+    }
+
+    fn untrack(&mut self, address: &Pubkey) {
+        self.pending.remove(address);
+        self.count_pending();
+    }
+
+    /// Re-reads `address` and tracks or untracks it by what it is waiting for.
+    fn refresh(&mut self, address: Pubkey) {
+        match self.fetch_pending(&address) {
+            Ok(Some(pending)) => {
+                if let Err(err) = self.track(address, pending) {
+                    warn!("tracking {address}: {err:#}");
+                }
+            }
+            Ok(None) => self.untrack(&address),
+            Err(err) => warn!("fetching {address}: {err:#}"),
+        }
+    }
+
+    fn count_pending(&self) {
+        let count = |kinds: &[Kind]| {
+            self.pending
+                .values()
+                .filter(|pending| kinds.contains(&pending.kind))
+                .count() as u64
+        };
+        let metrics = &self.metrics;
+        for (counters, kinds) in [
+            (&metrics.swaps, &[Kind::Swap][..]),
+            (&metrics.liquidity, &[Kind::Deposit, Kind::Withdraw]),
+            (&metrics.seals, &[Kind::Seal]),
+        ] {
+            counters.pending.store(count(kinds), Ordering::Relaxed);
+        }
+    }
+
+    /// Replaces the computations tracked by accounts with `discriminator`
+    /// with every such account still waiting for its callback. One whose pool
+    /// cannot be loaded is skipped, or kept as it was if it was already
+    /// tracked.
+    fn scan(&mut self, discriminator: &[u8]) -> Result<()> {
+        let accounts = self.rpc.get_program_accounts_with_config(
+            &whispr::ID,
+            RpcProgramAccountsConfig {
+                filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    0,
+                    discriminator,
+                ))]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )?;
+
+        let (mut previous, others): (HashMap<_, _>, HashMap<_, _>) =
+            std::mem::take(&mut self.pending)
+                .into_iter()
+                .partition(|(_, pending)| pending.kind.account() == discriminator);
+        self.pending = others;
+        for (address, account) in accounts {
+            let pending = match waiting_for(&account.data) {
+                Ok(Some(pending)) => pending,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!("decoding {address}: {err}");
+                    continue;
+                }
+            };
+            if let Err(err) = self.track(address, pending) {
+                warn!("tracking {address}: {err:#}");
+                if let Some(pending) = previous.remove(&address) {
+                    self.pending.insert(address, pending);
+                }
+            }
+        }
+        self.count_pending();
+        info!("tracking {} pending computations", self.pending.len());
+        Ok(())
+    }
+
+    fn handle(&mut self, logs: &RpcLogsResponse) {
+        if logs.err.is_some() {
+            return;
+        }
+        // `enable_dark_pool` emits nothing, and its logs do not say which
+        // pool it sealed
+        if logs
+            .logs
+            .iter()
+            .any(|log| log == "Program log: Instruction: EnableDarkPool")
+        {
+            Metrics::inc(&self.metrics.seals.initiated);
+            if let Err(err) = self.scan(DarkPool::DISCRIMINATOR) {
+                warn!("scanning dark pools: {err:#}");
+            }
+        }
+        let seal_expired = whispr::ErrorCode::DarkSealExpired.to_string();
+
+        for event in decode::events_from_logs(&logs.logs) {
+            let (address, counter) = match &event {
+                WhisprEvent::ConfidentialSwapInitiatedEvent(event) => (
+                    pda::swap_state(&event.config, &event.user, event.computation_offset),
+                    &self.metrics.swaps.initiated,
+                ),
+                WhisprEvent::ConfidentialSwapExecutedEvent(event) => (
+                    pda::swap_state(&event.config, &event.user, event.computation_offset),
+                    &self.metrics.swaps.executed,
+                ),
+                WhisprEvent::ConfidentialSwapFailedEvent(event) => (
+                    pda::swap_state(&event.config, &event.user, event.computation_offset),
+                    &self.metrics.swaps.failed,
+                ),
+                WhisprEvent::ConfidentialSwapCancelledEvent(event) => (
+                    pda::swap_state(&event.config, &event.user, event.computation_offset),
+                    &self.metrics.swaps.cancelled,
+                ),
+                WhisprEvent::ConfidentialLiquidityInitiatedEvent(event) => (
+                    pda::liquidity_state(&event.config, &event.user, event.computation_offset),
+                    &self.metrics.liquidity.initiated,
+                ),
+                WhisprEvent::ConfidentialLiquidityExecutedEvent(event) => (
+                    pda::liquidity_state(&event.config, &event.user, event.computation_offset),
+                    &self.metrics.liquidity.executed,
+                ),
+                WhisprEvent::ConfidentialLiquidityFailedEvent(event) => (
+                    pda::liquidity_state(&event.config, &event.user, event.computation_offset),
+                    &self.metrics.liquidity.failed,
+                ),
+                WhisprEvent::ConfidentialLiquidityCancelledEvent(event) => (
+                    pda::liquidity_state(&event.config, &event.user, event.computation_offset),
+                    &self.metrics.liquidity.cancelled,
+                ),
+                WhisprEvent::DarkPoolEnabledEvent(event) => {
+                    (pda::dark_pool(&event.config), &self.metrics.seals.executed)
+                }
+                // Failures of dark trades name their user
+                WhisprEvent::DarkPoolFailedEvent(event) if event.user.is_none() => (
+                    pda::dark_pool(&event.config),
+                    match event.reason == seal_expired {
+                        true => &self.metrics.seals.cancelled,
+                        false => &self.metrics.seals.failed,
+                    },
+                ),
+                _ => continue,
+            };
+            Metrics::inc(counter);
+            info!("{address} {}", event.name());
+            // A stale seal callback fails while a newer seal still waits, so
+            // what the account holds now decides
+            self.refresh(address);
+        }
+    }
+
+    /// Cancels every tracked computation past its pool's swap timeout, by
+    /// the cluster's clock.
+    fn cancel_expired(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let swap_timeouts: HashMap<Pubkey, i64> = self
+            .pools
+            .iter()
+            .map(|(config, (_, swap_timeout))| (*config, *swap_timeout))
+            .collect();
+        let clock: Clock = from_account(&self.rpc.get_account(&sysvar::clock::ID)?)
+            .context("decoding the clock sysvar")?;
+
+        for address in expired(&self.pending, &swap_timeouts, clock.unix_timestamp) {
+            let pending = self.pending[&address];
+            let pool = *self.pool(&pending.config)?;
+            let caller = self.signer.pubkey();
+            let (user, offset) = (pending.user, pending.computation_offset);
+            let instruction = match pending.kind {
+                Kind::Swap => instructions::cancel_swap(&pool, caller, user, offset),
+                Kind::Deposit => instructions::cancel_deposit(&pool, caller, user, offset),
+                Kind::Withdraw => instructions::cancel_withdraw(&pool, caller, user, offset),
+                Kind::Seal => instructions::cancel_enable_dark_pool(&pool, caller),
+            };
+            match self.send(instruction) {
+                Ok(signature) => {
+                    info!("cancelled {address} in {signature}");
+                    self.untrack(&address);
+                }
+                Err(err) => {
+                    Metrics::inc(&self.metrics.cancel_errors);
+                    warn!("cancelling {address}: {err:#}");
+                    // Its callback may have landed while the logs were missed
+                    if let Ok(None) = self.fetch_pending(&address) {
+                        self.untrack(&address);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, instruction: Instruction) -> Result<Signature> {
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.signer.pubkey()),
+            &[&self.signer],
+            self.rpc.get_latest_blockhash()?,
+        );
+        Ok(self.rpc.send_and_confirm_transaction(&transaction)?)
+    }
+}
+
+/// The Solana CLI's convention: a ws scheme and the RPC port plus one.
+fn ws_url(rpc: &str) -> String {
+    let url = rpc.replacen("http", "ws", 1);
+    if let Some((host, port)) = url.rsplit_once(':') {
+        if let Ok(port) = port.trim_end_matches('/').parse::<u16>() {
+            return format!("{host}:{}", port + 1);
+        }
+    }
+    url
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{home}/{rest}"),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::AccountSerialize;
+    use whispr::PendingComputation;
+
+    use super::*;
+
+    fn pending(config: Pubkey, created_at: i64) -> Pending {
+        Pending {
+            kind: Kind::Swap,
+            config,
+            user: Pubkey::new_unique(),
+            computation_offset: 0,
+            created_at,
+        }
+    }
+
+    #[test]
+    fn expires_once_the_timeout_has_passed() {
+        let config = Pubkey::new_unique();
+        let swap_state = Pubkey::new_unique();
+        let pending = HashMap::from([(swap_state, pending(config, 100))]);
+        let swap_timeouts = HashMap::from([(config, 60)]);
+
+        assert!(expired(&pending, &swap_timeouts, 159).is_empty());
+        // cancel_swap accepts the exact second the timeout ends
+        assert_eq!(expired(&pending, &swap_timeouts, 160), [swap_state]);
+        assert_eq!(expired(&pending, &swap_timeouts, 1_000), [swap_state]);
+    }
+
+    #[test]
+    fn leaves_pools_without_a_timeout_alone() {
+        let (known, unknown) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pending = HashMap::from([(a, pending(known, 0)), (b, pending(unknown, 0))]);
+
+        assert_eq!(
+            expired(&pending, &HashMap::from([(known, 60)]), i64::MAX),
+            [a]
+        );
+    }
+
+    #[test]
+    fn oldest_first_and_saturating() {
+        let config = Pubkey::new_unique();
+        let swaps: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let pending = HashMap::from([
+            (swaps[0], pending(config, 30)),
+            (swaps[1], pending(config, 10)),
+            (swaps[2], pending(config, 20)),
+            // Never expires rather than wrapping around
+            (Pubkey::new_unique(), pending(config, i64::MAX - 1)),
+        ]);
+
+        assert_eq!(
+            expired(&pending, &HashMap::from([(config, 60)]), i64::MAX - 1),
+            [swaps[1], swaps[2], swaps[0]]
+        );
+    }
+
+    fn data(account: &impl AccountSerialize) -> Vec<u8> {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn swaps_wait_until_settled() {
+        let mut swap = SwapState {
+            user: Pubkey::new_unique(),
+            config: Pubkey::new_unique(),
+            computation_offset: 3,
+            deposit_amount: 0,
+            withdraw_amount: 0,
+            status: SwapStatus::Computing,
+            created_at: 100,
+            deadline: None,
+            bump: 255,
+        };
+        assert_eq!(
+            waiting_for(&data(&swap)).unwrap(),
+            Some(Pending {
+                kind: Kind::Swap,
+                config: swap.config,
+                user: swap.user,
+                computation_offset: 3,
+                created_at: 100,
+            })
+        );
+        for status in [
+            SwapStatus::Executed,
+            SwapStatus::Failed,
+            SwapStatus::Rejected,
+        ] {
+            swap.status = status;
+            assert_eq!(waiting_for(&data(&swap)).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn liquidity_is_cancelled_by_direction() {
+        let mut state = LiquidityState {
+            user: Pubkey::new_unique(),
+            config: Pubkey::new_unique(),
+            computation_offset: 4,
+            is_deposit: true,
+            status: LiquidityStatus::Initiated,
+            created_at: 50,
+            deadline: Some(60),
+            bump: 254,
+        };
+        let kind = |state: &LiquidityState| waiting_for(&data(state)).unwrap().map(|p| p.kind);
+
+        assert_eq!(kind(&state), Some(Kind::Deposit));
+        state.is_deposit = false;
+        assert_eq!(kind(&state), Some(Kind::Withdraw));
+        for status in [
+            LiquidityStatus::Executed,
+            LiquidityStatus::Failed,
+            LiquidityStatus::Rejected,
+        ] {
+            state.status = status;
+            assert_eq!(kind(&state), None);
+        }
+    }
+
+    #[test]
+    fn only_unsealed_dark_pools_wait_for_their_seal() {
+        let mut dark_pool = DarkPool {
+            bump: 253,
+            reserves: [[0; 32]; 4],
+            nonce: 0,
+            config: Pubkey::new_unique(),
+            sealed: false,
+            pending: Some(PendingComputation {
+                computation_offset: 9,
+                since: 70,
+            }),
+            toggled_at: 0,
+        };
+        let seal = waiting_for(&data(&dark_pool)).unwrap().unwrap();
+        assert_eq!(
+            (
+                seal.kind,
+                seal.config,
+                seal.computation_offset,
+                seal.created_at
+            ),
+            (Kind::Seal, dark_pool.config, 9, 70)
+        );
+
+        // Dark trades and the reveal hold the slot of a sealed pool, and
+        // expire without a crank
+        dark_pool.sealed = true;
+        assert_eq!(waiting_for(&data(&dark_pool)).unwrap(), None);
+        dark_pool.sealed = false;
+        dark_pool.pending = None;
+        assert_eq!(waiting_for(&data(&dark_pool)).unwrap(), None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Keeper counters, served in the Prometheus text format.
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+/// Counters of one kind of tracked computation.
+#[derive(Default)]
+pub struct Counters {
+    pub pending: AtomicU64,
+    pub initiated: AtomicU64,
+    pub executed: AtomicU64,
+    pub failed: AtomicU64,
+    // Cancelled by this keeper, or by anyone else while it was watching
+    pub cancelled: AtomicU64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    pub swaps: Counters,
+    // Confidential deposits and withdrawals
+    pub liquidity: Counters,
+    // Dark pool seals, cancelled with `cancel_enable_dark_pool`
+    pub seals: Counters,
+    pub cancel_errors: AtomicU64,
+    pub reconnects: AtomicU64,
+}
+
+impl Metrics {
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self) -> String {
+        let kinds = [
+            ("swap", &self.swaps),
+            ("liquidity", &self.liquidity),
+            ("seal", &self.seals),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, counter) in [
+            (
+                "whispr_keeper_pending",
+                "gauge",
+                "Confidential computations waiting for their callback",
+                (|counters| &counters.pending) as fn(&Counters) -> &AtomicU64,
+            ),
+            (
+                "whispr_keeper_initiated_total",
+                "counter",
+                "Confidential computations queued",
+                |counters| &counters.initiated,
+            ),
+            (
+                "whispr_keeper_executed_total",
+                "counter",
+                "Confidential computations settled by their callback",
+                |counters| &counters.executed,
+            ),
+            (
+                "whispr_keeper_failed_total",
+                "counter",
+                "Confidential computations aborted or rejected by their callback",
+                |counters| &counters.failed,
+            ),
+            (
+                "whispr_keeper_cancelled_total",
+                "counter",
+                "Expired confidential computations cancelled and refunded",
+                |counters| &counters.cancelled,
+            ),
+        ] {
+            out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+            for (label, counters) in kinds {
+                out.push_str(&format!(
+                    "{name}{{kind=\"{label}\"}} {}\n",
+                    counter(counters).load(Ordering::Relaxed)
+                ));
+            }
+        }
+        for (name, help, value) in [
+            (
+                "whispr_keeper_cancel_errors_total",
+                "Cancel transactions that failed",
+                &self.cancel_errors,
+            ),
+            (
+                "whispr_keeper_reconnects_total",
+                "Log subscriptions reopened",
+                &self.reconnects,
+            ),
+        ] {
+            out.push_str(&format!(
+                "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}\n",
+                value.load(Ordering::Relaxed)
+            ));
+        }
+        out
+    }
+}
+
+/// Answers every request on `addr` with the current metrics.
+pub fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            // The request itself is irrelevant, drain what was sent with it
+            let _ = stream.read(&mut [0u8; 1024]);
+            let body = metrics.render();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    Ok(())
+}
@@ -143,9 +143,12 @@ use arcis_imports::*;
 mod circuits {
     use arcis_imports::*;
 
-    // Simple struct with just amount
-    pub struct SwapAmount {
+    // Swap direction, size and limit. The limit stays private to the trader,
+    // the direction and size only until the swap settles.
+    pub struct SwapData {
+        is_x: bool,
         amount: u64,
+        min_output: u64,
     }
 
     // Return struct with both amounts
@@ -157,33 +160,514 @@ mod circuits {
 
     #[instruction]
     pub fn compute_swap(
-        swap_amount_ctxt: Enc<Shared, SwapAmount>,
+        swap_ctxt: Enc<Shared, SwapData>,
         vault_x_amount: u64,
         vault_y_amount: u64,
-        lp_supply: u64,
         fee: u16,
-    ) -> Enc<Shared, SwapResult> {
-        // Return revealed struct
-        let swap_amount = swap_amount_ctxt.to_arcis();
-        let amount = swap_amount.amount;
+        protocol_fee: u16,
+        escrow_x_amount: u64,
+        escrow_y_amount: u64,
+        transfer_fee_x: u16,
+        max_transfer_fee_x: u64,
+        transfer_fee_y: u16,
+        max_transfer_fee_y: u64,
+    ) -> (Enc<Shared, SwapResult>, u64, u64, u64, u64, u64, u64) {
+        let swap = swap_ctxt.to_arcis();
+        let amount = swap.amount;
+
+        // is_x = true swaps X for Y, otherwise Y for X
+        let (reserve_in, reserve_out) = if swap.is_x {
+            (vault_x_amount, vault_y_amount)
+        } else {
+            (vault_y_amount, vault_x_amount)
+        };
 
-        // Implement constant product AMM formula: x * y = k
-        let k = vault_x_amount * vault_y_amount;
+        // Widen to u128 so x * y = k cannot overflow for realistic reserves
+        let reserve_in = reserve_in as u128;
+        let reserve_out = reserve_out as u128;
+        let k = reserve_in * reserve_out;
+
+        // Token-2022 transfer fees of the input and output mints, zero for
+        // SPL Token mints
+        let (transfer_fee_in, max_transfer_fee_in, transfer_fee_out, max_transfer_fee_out) =
+            if swap.is_x {
+                (
+                    transfer_fee_x,
+                    max_transfer_fee_x,
+                    transfer_fee_y,
+                    max_transfer_fee_y,
+                )
+            } else {
+                (
+                    transfer_fee_y,
+                    max_transfer_fee_y,
+                    transfer_fee_x,
+                    max_transfer_fee_x,
+                )
+            };
+
+        // Only what reaches the vault after the escrow transfer is priced
+        let withheld_in = (amount as u128 * transfer_fee_in as u128 + 9999) / 10000;
+        let withheld_in = if withheld_in > max_transfer_fee_in as u128 {
+            max_transfer_fee_in as u128
+        } else {
+            withheld_in
+        };
+        let received = amount as u128 - withheld_in;
+
+        // Fee is taken on the input like ConstantProduct::swap, and rounded
+        // up in the pool's favour
+        let fee_amount = (received * fee as u128 + 9999) / 10000;
+        let amount_in = received - fee_amount;
+
+        // Round the remaining reserve up so the trader never receives the
+        // rounding dust, guarding the division against an empty pool
+        let new_in = reserve_in + amount_in;
+        let divisor = if new_in == 0 { 1 } else { new_in };
+        let new_out = k / divisor;
+        let new_out = if new_out * divisor < k {
+            new_out + 1
+        } else {
+            new_out
+        };
+        let final_out = if new_out >= reserve_out {
+            0
+        } else {
+            (reserve_out - new_out) as u64
+        };
 
-        // Always swapping X for Y
-        let new_x = vault_x_amount + amount;
-        let new_y = k / new_x;
-        let y_out = vault_y_amount - new_y;
+        // Validate slippage protection on what the trader actually receives,
+        // and that the escrow covers the input
+        let escrowed = if swap.is_x {
+            escrow_x_amount
+        } else {
+            escrow_y_amount
+        };
+        let withheld_out = (final_out as u128 * transfer_fee_out as u128 + 9999) / 10000;
+        let withheld_out = if withheld_out > max_transfer_fee_out as u128 {
+            max_transfer_fee_out as u128
+        } else {
+            withheld_out
+        };
+        let slippage_ok =
+            final_out as u128 - withheld_out >= swap.min_output as u128 && amount <= escrowed;
+        let deposit_amount = if slippage_ok { amount } else { 0 };
+        let withdraw_amount = if slippage_ok { final_out } else { 0 };
+
+        // The protocol's share of the fee, kept out of the pool reserves
+        let protocol_amount = if slippage_ok {
+            (fee_amount * protocol_fee as u128 / 10000) as u64
+        } else {
+            0
+        };
 
-        // Apply fee
-        let fee_amount = (y_out * fee as u64) / 10000;
-        let final_y_out = y_out - fee_amount;
+        // Per-mint amounts for the callback to settle. Exactly one of each
+        // pair is nonzero, so they reveal the direction along with the size.
+        let (x_in, y_in) = if swap.is_x {
+            (deposit_amount, 0)
+        } else {
+            (0, deposit_amount)
+        };
+        let (x_out, y_out) = if swap.is_x {
+            (0, withdraw_amount)
+        } else {
+            (withdraw_amount, 0)
+        };
+        let (protocol_x, protocol_y) = if swap.is_x {
+            (protocol_amount, 0)
+        } else {
+            (0, protocol_amount)
+        };
 
         let result = SwapResult {
-            deposit_amount: amount,
-            withdraw_amount: final_y_out,
+            deposit_amount,
+            withdraw_amount,
+        };
+
+        // The settlement is revealed to the program so that the callback
+        // can only move what the MPC computed. A violated limit only shows
+        // up as a zero settlement, the limit itself is never revealed.
+        (
+            swap_ctxt.owner.from_arcis(result),
+            x_in.reveal(),
+            y_in.reveal(),
+            x_out.reveal(),
+            y_out.reveal(),
+            protocol_x.reveal(),
+            protocol_y.reveal(),
+        )
+    }
+
+    // LP mint size and deposit limits. The limits stay private to the LP, the
+    // size only until the deposit settles.
+    pub struct DepositData {
+        lp_amount: u64,
+        max_x: u64,
+        max_y: u64,
+    }
+
+    // LP burn size and withdrawal limits. The limits stay private to the LP,
+    // the size only until the withdrawal settles.
+    pub struct WithdrawData {
+        lp_amount: u64,
+        min_x: u64,
+        min_y: u64,
+    }
+
+    // Settled LP and token amounts of a deposit or withdrawal
+    #[derive(Debug, Clone)]
+    pub struct LiquidityResult {
+        pub lp_amount: u64,
+        pub x_amount: u64,
+        pub y_amount: u64,
+    }
+
+    #[instruction]
+    pub fn compute_deposit(
+        deposit_ctxt: Enc<Shared, DepositData>,
+        vault_x_amount: u64,
+        vault_y_amount: u64,
+        lp_supply: u64,
+        escrow_x_amount: u64,
+        escrow_y_amount: u64,
+        transfer_fee_x: u16,
+        max_transfer_fee_x: u64,
+        transfer_fee_y: u16,
+        max_transfer_fee_y: u64,
+    ) -> (Enc<Shared, LiquidityResult>, u64, u64, u64) {
+        let deposit = deposit_ctxt.to_arcis();
+        let lp_amount = deposit.lp_amount as u128;
+        let supply = if lp_supply == 0 { 1 } else { lp_supply as u128 };
+
+        // Amounts the vaults must receive for the LP, rounded up in the
+        // pool's favour
+        let x = (vault_x_amount as u128 * lp_amount + supply - 1) / supply;
+        let y = (vault_y_amount as u128 * lp_amount + supply - 1) / supply;
+
+        // Grossed up by the Token-2022 transfer fee withheld on the way from
+        // the escrow into the vault, zero for SPL Token mints
+        let divisor_x = if transfer_fee_x >= 10000 {
+            1
+        } else {
+            10000 - transfer_fee_x as u128
+        };
+        let gross_x = if transfer_fee_x == 0 {
+            x
+        } else {
+            (x * 10000 + divisor_x - 1) / divisor_x
+        };
+        let capped_x = x + max_transfer_fee_x as u128;
+        let gross_x = if gross_x > capped_x {
+            capped_x
+        } else {
+            gross_x
+        };
+
+        let divisor_y = if transfer_fee_y >= 10000 {
+            1
+        } else {
+            10000 - transfer_fee_y as u128
+        };
+        let gross_y = if transfer_fee_y == 0 {
+            y
+        } else {
+            (y * 10000 + divisor_y - 1) / divisor_y
+        };
+        let capped_y = y + max_transfer_fee_y as u128;
+        let gross_y = if gross_y > capped_y {
+            capped_y
+        } else {
+            gross_y
+        };
+
+        // Validate the LP's limits and that the escrows cover the deposit
+        let deposit_ok = lp_supply != 0
+            && lp_amount != 0
+            && gross_x <= deposit.max_x as u128
+            && gross_y <= deposit.max_y as u128
+            && gross_x <= escrow_x_amount as u128
+            && gross_y <= escrow_y_amount as u128;
+        let lp_amount = if deposit_ok { deposit.lp_amount } else { 0 };
+        let x_amount = if deposit_ok { gross_x as u64 } else { 0 };
+        let y_amount = if deposit_ok { gross_y as u64 } else { 0 };
+
+        let result = LiquidityResult {
+            lp_amount,
+            x_amount,
+            y_amount,
+        };
+
+        // Revealed so the callback can mint and transfer exactly this, which
+        // makes the LP and token amounts public in the settlement's balance
+        // changes. The program does not store them, and the LP's limits are
+        // never revealed.
+        (
+            deposit_ctxt.owner.from_arcis(result),
+            lp_amount.reveal(),
+            x_amount.reveal(),
+            y_amount.reveal(),
+        )
+    }
+
+    #[instruction]
+    pub fn compute_withdraw(
+        withdraw_ctxt: Enc<Shared, WithdrawData>,
+        vault_x_amount: u64,
+        vault_y_amount: u64,
+        lp_supply: u64,
+        escrow_lp_amount: u64,
+        transfer_fee_x: u16,
+        max_transfer_fee_x: u64,
+        transfer_fee_y: u16,
+        max_transfer_fee_y: u64,
+    ) -> (Enc<Shared, LiquidityResult>, u64, u64, u64) {
+        let withdraw = withdraw_ctxt.to_arcis();
+        let lp_amount = withdraw.lp_amount as u128;
+        let supply = if lp_supply == 0 { 1 } else { lp_supply as u128 };
+
+        // The LP's share of each reserve, rounded down in the pool's favour
+        let x = vault_x_amount as u128 * lp_amount / supply;
+        let y = vault_y_amount as u128 * lp_amount / supply;
+
+        // The limits apply to what arrives after any Token-2022 transfer fee
+        let withheld_x = (x * transfer_fee_x as u128 + 9999) / 10000;
+        let withheld_x = if withheld_x > max_transfer_fee_x as u128 {
+            max_transfer_fee_x as u128
+        } else {
+            withheld_x
+        };
+        let withheld_y = (y * transfer_fee_y as u128 + 9999) / 10000;
+        let withheld_y = if withheld_y > max_transfer_fee_y as u128 {
+            max_transfer_fee_y as u128
+        } else {
+            withheld_y
+        };
+
+        // Validate the LP's limits and that the escrow covers the burn
+        let withdraw_ok = lp_supply != 0
+            && lp_amount != 0
+            && withdraw.lp_amount <= escrow_lp_amount
+            && x - withheld_x >= withdraw.min_x as u128
+            && y - withheld_y >= withdraw.min_y as u128;
+        let lp_amount = if withdraw_ok { withdraw.lp_amount } else { 0 };
+        let x_amount = if withdraw_ok { x as u64 } else { 0 };
+        let y_amount = if withdraw_ok { y as u64 } else { 0 };
+
+        let result = LiquidityResult {
+            lp_amount,
+            x_amount,
+            y_amount,
+        };
+
+        // Revealed for settlement like compute_deposit
+        (
+            withdraw_ctxt.owner.from_arcis(result),
+            lp_amount.reveal(),
+            x_amount.reveal(),
+            y_amount.reveal(),
+        )
+    }
+
+    // Reserves of a dark pool, only ever held encrypted under the MXE, and the
+    // protocol fees accrued while dark, which are not part of the reserves
+    pub struct DarkReserves {
+        x: u64,
+        y: u64,
+        protocol_x: u64,
+        protocol_y: u64,
+    }
+
+    // A trader's share of a dark pool's aggregated vault
+    pub struct DarkBalance {
+        x: u64,
+        y: u64,
+    }
+
+    // Amounts a trader asks to take out of their dark balance
+    pub struct DarkWithdrawData {
+        x: u64,
+        y: u64,
+    }
+
+    // Outcome of a dark swap and the balance left after it, visible to the
+    // trader only
+    #[derive(Debug, Clone)]
+    pub struct DarkSwapResult {
+        pub deposit_amount: u64,
+        pub withdraw_amount: u64,
+        pub balance_x: u64,
+        pub balance_y: u64,
+    }
+
+    #[instruction]
+    pub fn seal_reserves(mxe: Mxe, reserve_x: u64, reserve_y: u64) -> Enc<Mxe, DarkReserves> {
+        mxe.from_arcis(DarkReserves {
+            x: reserve_x,
+            y: reserve_y,
+            protocol_x: 0,
+            protocol_y: 0,
+        })
+    }
+
+    #[instruction]
+    pub fn reveal_reserves(reserves_ctxt: Enc<Mxe, DarkReserves>) -> (u64, u64, u64, u64) {
+        let reserves = reserves_ctxt.to_arcis();
+        (
+            reserves.x.reveal(),
+            reserves.y.reveal(),
+            reserves.protocol_x.reveal(),
+            reserves.protocol_y.reveal(),
+        )
+    }
+
+    #[instruction]
+    pub fn dark_fund(
+        balance_ctxt: Enc<Mxe, DarkBalance>,
+        is_new: bool,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Enc<Mxe, DarkBalance> {
+        let balance = balance_ctxt.to_arcis();
+
+        // A new balance account holds no ciphertext yet, start from zero
+        let (x, y) = if is_new {
+            (0, 0)
+        } else {
+            (balance.x, balance.y)
+        };
+
+        balance_ctxt.owner.from_arcis(DarkBalance {
+            x: x + amount_x,
+            y: y + amount_y,
+        })
+    }
+
+    #[instruction]
+    pub fn dark_swap(
+        swap_ctxt: Enc<Shared, SwapData>,
+        reserves_ctxt: Enc<Mxe, DarkReserves>,
+        balance_ctxt: Enc<Mxe, DarkBalance>,
+        fee: u16,
+        protocol_fee: u16,
+    ) -> (
+        Enc<Mxe, DarkReserves>,
+        Enc<Mxe, DarkBalance>,
+        Enc<Shared, DarkSwapResult>,
+    ) {
+        let swap = swap_ctxt.to_arcis();
+        let reserves = reserves_ctxt.to_arcis();
+        let balance = balance_ctxt.to_arcis();
+        let amount = swap.amount;
+
+        // is_x = true swaps X for Y, otherwise Y for X
+        let (reserve_in, reserve_out, balance_in) = if swap.is_x {
+            (reserves.x, reserves.y, balance.x)
+        } else {
+            (reserves.y, reserves.x, balance.y)
+        };
+
+        // Same pricing as compute_swap, with no transfer fee since no tokens
+        // move: the fee is taken on the input and rounded up in the pool's
+        // favour, and the remaining reserve is rounded up
+        let reserve_in_wide = reserve_in as u128;
+        let reserve_out_wide = reserve_out as u128;
+        let k = reserve_in_wide * reserve_out_wide;
+        let fee_amount = (amount as u128 * fee as u128 + 9999) / 10000;
+        let new_in = reserve_in_wide + amount as u128 - fee_amount;
+        let divisor = if new_in == 0 { 1 } else { new_in };
+        let new_out = k / divisor;
+        let new_out = if new_out * divisor < k {
+            new_out + 1
+        } else {
+            new_out
+        };
+        let final_out = if new_out >= reserve_out_wide {
+            0
+        } else {
+            (reserve_out_wide - new_out) as u64
+        };
+
+        // The trade only happens if the balance covers it and the limit holds,
+        // otherwise every ciphertext is re-encrypted unchanged
+        let swap_ok =
+            amount != 0 && amount <= balance_in && final_out != 0 && final_out >= swap.min_output;
+        let deposit_amount = if swap_ok { amount } else { 0 };
+        let withdraw_amount = if swap_ok { final_out } else { 0 };
+
+        // The protocol's share of the fee, rounded like compute_swap. It stays
+        // encrypted with the reserves and is paid out when they are revealed.
+        let protocol_amount = if swap_ok {
+            (fee_amount * protocol_fee as u128 / 10000) as u64
+        } else {
+            0
+        };
+
+        // The rest of the input stays in the pool, the fee accrues to the LPs
+        let (new_reserves, new_balance) = if swap.is_x {
+            (
+                DarkReserves {
+                    x: reserves.x + deposit_amount - protocol_amount,
+                    y: reserves.y - withdraw_amount,
+                    protocol_x: reserves.protocol_x + protocol_amount,
+                    protocol_y: reserves.protocol_y,
+                },
+                DarkBalance {
+                    x: balance.x - deposit_amount,
+                    y: balance.y + withdraw_amount,
+                },
+            )
+        } else {
+            (
+                DarkReserves {
+                    x: reserves.x - withdraw_amount,
+                    y: reserves.y + deposit_amount - protocol_amount,
+                    protocol_x: reserves.protocol_x,
+                    protocol_y: reserves.protocol_y + protocol_amount,
+                },
+                DarkBalance {
+                    x: balance.x + withdraw_amount,
+                    y: balance.y - deposit_amount,
+                },
+            )
+        };
+
+        let result = DarkSwapResult {
+            deposit_amount,
+            withdraw_amount,
+            balance_x: new_balance.x,
+            balance_y: new_balance.y,
+        };
+
+        // Nothing is revealed, not even whether the swap went through
+        (
+            reserves_ctxt.owner.from_arcis(new_reserves),
+            balance_ctxt.owner.from_arcis(new_balance),
+            swap_ctxt.owner.from_arcis(result),
+        )
+    }
+
+    #[instruction]
+    pub fn dark_withdraw(
+        withdraw_ctxt: Enc<Shared, DarkWithdrawData>,
+        balance_ctxt: Enc<Mxe, DarkBalance>,
+    ) -> (Enc<Mxe, DarkBalance>, u64, u64) {
+        let withdraw = withdraw_ctxt.to_arcis();
+        let balance = balance_ctxt.to_arcis();
+
+        let withdraw_ok = withdraw.x <= balance.x && withdraw.y <= balance.y;
+        let x_amount = if withdraw_ok { withdraw.x } else { 0 };
+        let y_amount = if withdraw_ok { withdraw.y } else { 0 };
+
+        let new_balance = DarkBalance {
+            x: balance.x - x_amount,
+            y: balance.y - y_amount,
         };
 
-        swap_amount_ctxt.owner.from_arcis(result)
+        // The payout leaves the aggregated vault publicly, so it is revealed
+        (
+            balance_ctxt.owner.from_arcis(new_balance),
+            x_amount.reveal(),
+            y_amount.reveal(),
+        )
     }
 }
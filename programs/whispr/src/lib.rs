@@ -1,23 +1,74 @@
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, system_program};
 use anchor_spl::{
     associated_token::{spl_associated_token_account, AssociatedToken},
-    token::{
-        burn, mint_to, spl_token, transfer, Burn, Mint, MintTo, Token, TokenAccount, Transfer,
+    token::{spl_token, Token},
+    token_2022::spl_token_2022::{
+        self,
+        extension::{
+            transfer_fee::{TransferFeeAmount, TransferFeeConfig},
+            BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+        },
+    },
+    token_2022_extensions::transfer_fee::{
+        harvest_withheld_tokens_to_mint, HarvestWithheldTokensToMint,
+    },
+    token_interface::{
+        burn, close_account, mint_to, sync_native, transfer_checked, Burn, CloseAccount, Mint,
+        MintTo, SyncNative, TokenAccount, TokenInterface, TransferChecked,
     },
 };
 use arcium_anchor::prelude::*;
-use constant_product_curve::ConstantProduct;
+use arcium_client::idl::arcium::types::CallbackAccount;
+use constant_product_curve::{ConstantProduct, LiquidityPair};
 use spl_associated_token_account::id as ASSOCIATED_TOKEN_PROGRAM_ID;
 use spl_token::ID as TOKEN_PROGRAM_ID;
 
 const COMP_DEF_OFFSET_COMPUTE_SWAP: u32 = comp_def_offset("compute_swap");
+const COMP_DEF_OFFSET_COMPUTE_DEPOSIT: u32 = comp_def_offset("compute_deposit");
+const COMP_DEF_OFFSET_COMPUTE_WITHDRAW: u32 = comp_def_offset("compute_withdraw");
+const COMP_DEF_OFFSET_SEAL_RESERVES: u32 = comp_def_offset("seal_reserves");
+const COMP_DEF_OFFSET_REVEAL_RESERVES: u32 = comp_def_offset("reveal_reserves");
+const COMP_DEF_OFFSET_DARK_FUND: u32 = comp_def_offset("dark_fund");
+const COMP_DEF_OFFSET_DARK_SWAP: u32 = comp_def_offset("dark_swap");
+const COMP_DEF_OFFSET_DARK_WITHDRAW: u32 = comp_def_offset("dark_withdraw");
+
+// MXE ciphertexts in DarkPool and DarkBalance, read by the circuits straight
+// from the account data, right after the discriminator and bump
+const DARK_CIPHERTEXT_OFFSET: u32 = 8 + 1;
+const DARK_RESERVES_LEN: u32 = 32 * 4;
+const DARK_BALANCE_LEN: u32 = 32 * 2;
+
+// Longest a pool stays dark before anyone may bring it back to public, in
+// seconds. LP withdrawals are closed while dark, this bounds how long.
+const MAX_DARK_PERIOD: i64 = 7 * 24 * 60 * 60;
+
+// Shortest time a pool stays public between two dark periods, in seconds, so
+// LPs always get a window to withdraw
+const DARK_COOLDOWN: i64 = 24 * 60 * 60;
+
+// LP permanently locked by the first deposit, guards against share inflation
+const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+// Hard cap on the pool fee, in basis points
+const MAX_FEE: u16 = 1_000;
+
+// The swap circuits take the fee out of the input, which underflows above 100%
+const _: () = assert!(MAX_FEE <= 10_000);
+
+// Hard cap on the protocol's share of the pool fee, in basis points of the fee
+const MAX_PROTOCOL_FEE: u16 = 5_000;
+
+// Shortest swap timeout, in seconds. An MPC computation takes several slots
+// to finalize, so anything shorter would let swaps be cancelled under it.
+const MIN_SWAP_TIMEOUT: i64 = 60;
+
+// Upper bound on the fixed point precision of the curve's LP ratio math
+const MAX_PRECISION: u8 = 12;
 
 declare_id!("AmZXddBcEnTS6T4k8TxDsDx3R5wE16qji67Lwh192a3M");
 
 #[arcium_program]
 pub mod whispr {
-    use arcium_client::idl::arcium::types::CallbackAccount;
-
     use super::*;
 
     // ========================= AMM FUNCTIONALITY =========================
@@ -26,16 +77,36 @@ pub mod whispr {
         seed: u64,
         fee: u16,
         authority: Option<Pubkey>,
+        swap_timeout: i64,
+        precision: u8,
     ) -> Result<()> {
+        require!(fee <= MAX_FEE, ErrorCode::InvalidFee);
+        require!(
+            swap_timeout >= MIN_SWAP_TIMEOUT,
+            ErrorCode::InvalidSwapTimeout
+        );
+        require!(
+            precision > 0 && precision <= MAX_PRECISION,
+            ErrorCode::InvalidPrecision
+        );
+        check_mint_extensions(&ctx.accounts.mint_x)?;
+        check_mint_extensions(&ctx.accounts.mint_y)?;
+
         ctx.accounts.config.set_inner(Config {
             seed,
             authority,
+            pending_authority: None,
             mint_x: ctx.accounts.mint_x.key(),
             mint_y: ctx.accounts.mint_y.key(),
             fee,
+            protocol_fee: 0,
             locked: false,
+            swap_timeout,
+            lp_decimals: ctx.accounts.mint_lp.decimals,
+            precision,
             config_bump: ctx.bumps.config,
             lp_bump: ctx.bumps.mint_lp,
+            dark: false,
         });
 
         emit!(InitializeEvent {
@@ -51,53 +122,105 @@ pub mod whispr {
         Ok(())
     }
 
-    pub fn deposit(ctx: Context<Deposit>, amount: u64, max_x: u64, max_y: u64) -> Result<()> {
+    pub fn deposit(
+        ctx: Context<Deposit>,
+        amount: u64,
+        max_x: u64,
+        max_y: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
         require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
         require!(amount != 0, ErrorCode::InvalidAmount);
+        check_deadline(deadline)?;
 
-        let (x, y) = match ctx.accounts.mint_lp.supply == 0
-            && ctx.accounts.vault_x.amount == 0
-            && ctx.accounts.vault_y.amount == 0
-        {
-            true => (max_x, max_y),
+        // Token-2022 mints may withhold a fee on each transfer, the vaults must
+        // receive the curve amounts so the user pays them grossed up
+        let fee_x = MintTransferFee::of(&ctx.accounts.mint_x)?;
+        let fee_y = MintTransferFee::of(&ctx.accounts.mint_y)?;
+
+        // On the first deposit `amount` is the minimum LP the caller accepts,
+        // the LP minted is the geometric mean of the deposit less the lock
+        let first_deposit = ctx.accounts.mint_lp.supply == 0;
+        let (x, y, lp) = match first_deposit {
+            true => {
+                let received_x = max_x - fee_x.on(max_x);
+                let received_y = max_y - fee_y.on(max_y);
+                let liquidity = isqrt(received_x as u128 * received_y as u128) as u64;
+                require!(
+                    liquidity > MINIMUM_LIQUIDITY,
+                    ErrorCode::InsufficientLiquidity
+                );
+                let lp = liquidity - MINIMUM_LIQUIDITY;
+                require!(lp >= amount, ErrorCode::SlippageExceded);
+                (max_x, max_y, lp)
+            }
             false => {
                 let amounts = ConstantProduct::xy_deposit_amounts_from_l(
                     ctx.accounts.vault_x.amount,
                     ctx.accounts.vault_y.amount,
                     ctx.accounts.mint_lp.supply,
                     amount,
-                    6,
+                    ctx.accounts.config.precision.into(),
+                )
+                .map_err(|_| ErrorCode::InvalidAmount)?;
+                (
+                    fee_x.gross_up(amounts.x)?,
+                    fee_y.gross_up(amounts.y)?,
+                    amount,
                 )
-                .unwrap();
-                (amounts.x, amounts.y)
             }
         };
 
         require!(x <= max_x && y <= max_y, ErrorCode::SlippageExceded);
 
+        // A native SOL side is wrapped from the user's lamports as needed
+        if ctx.accounts.mint_x.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                ctx.accounts.token_program_x.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.user_x,
+                x,
+            )?;
+        }
+        if ctx.accounts.mint_y.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                ctx.accounts.token_program_y.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.user_y,
+                y,
+            )?;
+        }
+
         // Transfer tokens
-        transfer(
+        transfer_checked(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_program_x.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.user_x.to_account_info(),
+                    mint: ctx.accounts.mint_x.to_account_info(),
                     to: ctx.accounts.vault_x.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
                 },
             ),
             x,
+            ctx.accounts.mint_x.decimals,
         )?;
 
-        transfer(
+        transfer_checked(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_program_y.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.user_y.to_account_info(),
+                    mint: ctx.accounts.mint_y.to_account_info(),
                     to: ctx.accounts.vault_y.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
                 },
             ),
             y,
+            ctx.accounts.mint_y.decimals,
         )?;
 
         // Mint LP tokens
@@ -108,6 +231,21 @@ pub mod whispr {
         ];
         let signer_seeds = &[&seeds[..]];
 
+        if first_deposit {
+            mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    MintTo {
+                        mint: ctx.accounts.mint_lp.to_account_info(),
+                        to: ctx.accounts.locked_lp.to_account_info(),
+                        authority: ctx.accounts.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                MINIMUM_LIQUIDITY,
+            )?;
+        }
+
         mint_to(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
@@ -118,33 +256,44 @@ pub mod whispr {
                 },
                 signer_seeds,
             ),
-            amount,
+            lp,
         )?;
 
         emit!(DepositEvent {
             user: ctx.accounts.user.key(),
-            amount,
+            amount: lp,
             x_amount: x,
             y_amount: y,
         });
         Ok(())
     }
 
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64, min_x: u64, min_y: u64) -> Result<()> {
+    pub fn withdraw(
+        ctx: Context<Withdraw>,
+        amount: u64,
+        min_x: u64,
+        min_y: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
         require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
         require!(amount != 0, ErrorCode::InvalidAmount);
+        check_deadline(deadline)?;
 
         let amounts = ConstantProduct::xy_withdraw_amounts_from_l(
             ctx.accounts.vault_x.amount,
             ctx.accounts.vault_y.amount,
             ctx.accounts.mint_lp.supply,
             amount,
-            6,
+            ctx.accounts.config.precision.into(),
         )
         .map_err(|_| ErrorCode::InvalidAmount)?;
 
+        // The user receives the curve amounts less any Token-2022 transfer fee
+        let received_x = amounts.x - MintTransferFee::of(&ctx.accounts.mint_x)?.on(amounts.x);
+        let received_y = amounts.y - MintTransferFee::of(&ctx.accounts.mint_y)?.on(amounts.y);
         require!(
-            amounts.x >= min_x && amounts.y >= min_y,
+            received_x >= min_x && received_y >= min_y,
             ErrorCode::SlippageExceded
         );
 
@@ -156,30 +305,34 @@ pub mod whispr {
         let signer_seeds = &[&seeds[..]];
 
         // Withdraw tokens
-        transfer(
+        transfer_checked(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_program_x.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.vault_x.to_account_info(),
+                    mint: ctx.accounts.mint_x.to_account_info(),
                     to: ctx.accounts.user_x.to_account_info(),
                     authority: ctx.accounts.config.to_account_info(),
                 },
                 signer_seeds,
             ),
             amounts.x,
+            ctx.accounts.mint_x.decimals,
         )?;
 
-        transfer(
+        transfer_checked(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_program_y.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.vault_y.to_account_info(),
+                    mint: ctx.accounts.mint_y.to_account_info(),
                     to: ctx.accounts.user_y.to_account_info(),
                     authority: ctx.accounts.config.to_account_info(),
                 },
                 signer_seeds,
             ),
             amounts.y,
+            ctx.accounts.mint_y.decimals,
         )?;
 
         // Burn LP tokens
@@ -195,6 +348,22 @@ pub mod whispr {
             amount,
         )?;
 
+        // A native SOL side is unwrapped back into the user's lamports
+        if ctx.accounts.mint_x.key() == spl_token::native_mint::ID {
+            unwrap_sol(
+                ctx.accounts.token_program_x.to_account_info(),
+                &ctx.accounts.user_x,
+                ctx.accounts.user.to_account_info(),
+            )?;
+        }
+        if ctx.accounts.mint_y.key() == spl_token::native_mint::ID {
+            unwrap_sol(
+                ctx.accounts.token_program_y.to_account_info(),
+                &ctx.accounts.user_y,
+                ctx.accounts.user.to_account_info(),
+            )?;
+        }
+
         emit!(WithdrawEvent {
             user: ctx.accounts.user.key(),
             amount,
@@ -204,194 +373,266 @@ pub mod whispr {
         Ok(())
     }
 
-    pub fn lock(ctx: Context<Update>) -> Result<()> {
+    pub fn swap(
+        ctx: Context<Swap>,
+        is_x: bool,
+        amount: u64,
+        min_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
+        require!(amount != 0, ErrorCode::InvalidAmount);
+        check_deadline(deadline)?;
+
+        // is_x = true deposits X and withdraws Y, otherwise the reverse
+        let (mint_in, mint_out) = match is_x {
+            true => (&ctx.accounts.mint_x, &ctx.accounts.mint_y),
+            false => (&ctx.accounts.mint_y, &ctx.accounts.mint_x),
+        };
+        let (program_in, program_out) = match is_x {
+            true => (&ctx.accounts.token_program_x, &ctx.accounts.token_program_y),
+            false => (&ctx.accounts.token_program_y, &ctx.accounts.token_program_x),
+        };
+
+        // Price only what reaches the vault after any Token-2022 transfer fee
+        let fee_in = MintTransferFee::of(mint_in)?;
+        let fee_out = MintTransferFee::of(mint_out)?;
+        let received = amount - fee_in.on(amount);
+
+        let mut curve = ConstantProduct::init(
+            ctx.accounts.vault_x.amount,
+            ctx.accounts.vault_y.amount,
+            ctx.accounts.mint_lp.supply,
+            ctx.accounts.config.fee,
+            None,
+        )
+        .map_err(|_| ErrorCode::InvalidAmount)?;
+
+        let pair = match is_x {
+            true => LiquidityPair::X,
+            false => LiquidityPair::Y,
+        };
+        let res = curve
+            .swap(pair, received, 0)
+            .map_err(|_| ErrorCode::InvalidAmount)?;
+
         require!(
-            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
-            ErrorCode::InvalidAuthority
+            res.deposit != 0 && res.withdraw != 0,
+            ErrorCode::InvalidAmount
         );
-        ctx.accounts.config.locked = true;
-        emit!(LockEvent {
-            user: ctx.accounts.user.key(),
-            config: ctx.accounts.config.key(),
-        });
-        Ok(())
-    }
-
-    pub fn unlock(ctx: Context<Update>) -> Result<()> {
         require!(
-            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
-            ErrorCode::InvalidAuthority
+            res.withdraw - fee_out.on(res.withdraw) >= min_out,
+            ErrorCode::SlippageExceded
         );
-        ctx.accounts.config.locked = false;
-        emit!(UnlockEvent {
-            user: ctx.accounts.user.key(),
-            config: ctx.accounts.config.key(),
-        });
-        Ok(())
-    }
 
-    // ========================= CONFIDENTIAL SWAP =========================
-    pub fn init_compute_swap_comp_def(ctx: Context<InitComputeSwapCompDef>) -> Result<()> {
-        init_comp_def(ctx.accounts, true, 0, None, None)?;
-        Ok(())
-    }
+        // The protocol's share of the fee is kept out of the pool reserves
+        let protocol_amount = protocol_fee_amount(&ctx.accounts.config, res.deposit);
 
-    pub fn compute_swap(
-        ctx: Context<ComputeSwap>,
-        computation_offset: u64,
-        pub_key: [u8; 32],
-        nonce: u128,
-        // encrypted_is_x: [u8; 32],      // Encrypted bool (as u8)
-        encrypted_amount: [u8; 32], // Encrypted u64
-                                    // encrypted_min_output: [u8; 32], // Encrypted u64
-    ) -> Result<()> {
-        require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        // Native SOL input is wrapped from the user's lamports as needed
+        if mint_in.key() == spl_token::native_mint::ID {
+            let user_token_in = match is_x {
+                true => &ctx.accounts.user_x,
+                false => &ctx.accounts.user_y,
+            };
+            wrap_sol(
+                program_in.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                user_token_in,
+                amount,
+            )?;
+        }
 
-        // Initialize swap state
-        let clock = Clock::get()?;
-        ctx.accounts.swap_state.user = ctx.accounts.user.key();
-        ctx.accounts.swap_state.config = ctx.accounts.config.key();
-        ctx.accounts.swap_state.computation_offset = computation_offset;
-        // ctx.accounts.swap_state.is_x = false;
-        ctx.accounts.swap_state.amount = 0;
-        ctx.accounts.swap_state.min_output = 0;
-        ctx.accounts.swap_state.status = SwapStatus::Initiated;
-        ctx.accounts.swap_state.created_at = clock.unix_timestamp;
+        let (user_in, vault_in, protocol_in, vault_out, user_out) = match is_x {
+            true => (
+                ctx.accounts.user_x.to_account_info(),
+                ctx.accounts.vault_x.to_account_info(),
+                ctx.accounts.protocol_x.to_account_info(),
+                ctx.accounts.vault_y.to_account_info(),
+                ctx.accounts.user_y.to_account_info(),
+            ),
+            false => (
+                ctx.accounts.user_y.to_account_info(),
+                ctx.accounts.vault_y.to_account_info(),
+                ctx.accounts.protocol_y.to_account_info(),
+                ctx.accounts.vault_x.to_account_info(),
+                ctx.accounts.user_x.to_account_info(),
+            ),
+        };
 
-        // Pass three encrypted values separately
-        let args = vec![
-            Argument::ArcisPubkey(pub_key),
-            Argument::PlaintextU128(nonce),
-            Argument::EncryptedU64(encrypted_amount), // amount
-            //  Argument::EncryptedU64(encrypted_min_output), // min_output
-            Argument::PlaintextU64(ctx.accounts.vault_x.amount),
-            Argument::PlaintextU64(ctx.accounts.vault_y.amount),
-            Argument::PlaintextU64(ctx.accounts.mint_lp.supply),
-            Argument::PlaintextU16(ctx.accounts.config.fee),
+        transfer_checked(
+            CpiContext::new(
+                program_in.to_account_info(),
+                TransferChecked {
+                    from: user_in,
+                    mint: mint_in.to_account_info(),
+                    to: vault_in.clone(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount,
+            mint_in.decimals,
+        )?;
+
+        let seeds = &[
+            &b"config"[..],
+            &ctx.accounts.config.seed.to_le_bytes(),
+            &[ctx.accounts.config.config_bump],
         ];
+        let signer_seeds = &[&seeds[..]];
 
-        queue_computation(
-            ctx.accounts,
-            computation_offset,
-            args,
-            vec![
-                CallbackAccount {
-                    pubkey: ctx.accounts.user.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.mint_x.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.mint_y.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.mint_lp.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.config.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.swap_state.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.vault_x.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.vault_x.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.user_x.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.user_y.key(),
-                    is_writable: true,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.token_program.key(),
-                    is_writable: false,
-                },
-                CallbackAccount {
-                    pubkey: ctx.accounts.associated_token_program.key(),
-                    is_writable: false,
+        if protocol_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    program_in.to_account_info(),
+                    TransferChecked {
+                        from: vault_in,
+                        mint: mint_in.to_account_info(),
+                        to: protocol_in,
+                        authority: ctx.accounts.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                protocol_amount,
+                mint_in.decimals,
+            )?;
+        }
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                program_out.to_account_info(),
+                TransferChecked {
+                    from: vault_out,
+                    mint: mint_out.to_account_info(),
+                    to: user_out,
+                    authority: ctx.accounts.config.to_account_info(),
                 },
-            ],
-            None,
+                signer_seeds,
+            ),
+            res.withdraw,
+            mint_out.decimals,
         )?;
 
-        ctx.accounts.swap_state.status = SwapStatus::Computing;
+        // and native SOL output is unwrapped back into lamports
+        if mint_out.key() == spl_token::native_mint::ID {
+            let user_token_out = match is_x {
+                true => &ctx.accounts.user_y,
+                false => &ctx.accounts.user_x,
+            };
+            unwrap_sol(
+                program_out.to_account_info(),
+                user_token_out,
+                ctx.accounts.user.to_account_info(),
+            )?;
+        }
 
-        emit!(ConfidentialSwapInitiatedEvent {
+        emit!(SwapEvent {
             user: ctx.accounts.user.key(),
             config: ctx.accounts.config.key(),
-            computation_offset,
+            is_x,
+            deposit_amount: amount,
+            withdraw_amount: res.withdraw,
         });
-
         Ok(())
     }
 
-    #[arcium_callback(encrypted_ix = "compute_swap")]
-    pub fn compute_swap_callback(
-        ctx: Context<ComputeSwapCallback>,
-        output: ComputationOutputs<ComputeSwapOutput>,
+    pub fn deposit_single(
+        ctx: Context<Zap>,
+        is_x: bool,
+        amount_in: u64,
+        min_lp: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
-        // Extract results from MPC computation
-        let swap_result = match output {
-            ComputationOutputs::Success(ComputeSwapOutput { field_0 }) => field_0,
-            _ => return Err(ErrorCode::AbortedComputation.into()),
+        require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
+        require!(amount_in != 0, ErrorCode::InvalidAmount);
+        check_deadline(deadline)?;
+        require!(
+            ctx.accounts.mint_lp.supply != 0,
+            ErrorCode::InsufficientLiquidity
+        );
+
+        // is_x = true deposits X only, otherwise Y only
+        let (mint_in, program_in) = match is_x {
+            true => (&ctx.accounts.mint_x, &ctx.accounts.token_program_x),
+            false => (&ctx.accounts.mint_y, &ctx.accounts.token_program_y),
+        };
+        let (reserve_in, reserve_out) = match is_x {
+            true => (ctx.accounts.vault_x.amount, ctx.accounts.vault_y.amount),
+            false => (ctx.accounts.vault_y.amount, ctx.accounts.vault_x.amount),
         };
 
-        // Decrypt the results
-        // The circuit returns SwapResult
-        let deposit_amount = swap_result.ciphertexts[0];
-        // u64::from_le_bytes(swap_result.ciphertexts[0][..8].try_into().unwrap());
+        // Swap just enough of what reaches the vault that the rest matches the
+        // post-swap pool ratio, the fee is only charged on the swapped part
+        let received = amount_in - MintTransferFee::of(mint_in)?.on(amount_in);
+        let swap_amount = zap_swap_amount(reserve_in, received, ctx.accounts.config.fee)?;
 
-        let withdraw_amount = swap_result.ciphertexts[1];
-        //u64::from_le_bytes(swap_result.ciphertexts[1][..8].try_into().unwrap());
+        let mut curve = ConstantProduct::init(
+            ctx.accounts.vault_x.amount,
+            ctx.accounts.vault_y.amount,
+            ctx.accounts.mint_lp.supply,
+            ctx.accounts.config.fee,
+            None,
+        )
+        .map_err(|_| ErrorCode::InvalidAmount)?;
 
-        emit!(ConfidentialSwapExecutedEvent {
-            user: ctx.accounts.user.key(),
-            config: ctx.accounts.config.key(),
-            computation_offset: ctx.accounts.swap_state.computation_offset,
-            deposit_amount,
-            withdraw_amount,
-            nonce: swap_result.nonce,
-        });
+        let pair = match is_x {
+            true => LiquidityPair::X,
+            false => LiquidityPair::Y,
+        };
+        let res = curve
+            .swap(pair, swap_amount, 0)
+            .map_err(|_| ErrorCode::InvalidAmount)?;
+        let protocol_amount = protocol_fee_amount(&ctx.accounts.config, res.deposit);
 
-        Ok(())
-    }
+        // LP for the unswapped remainder and the swap output, priced against
+        // the reserves after the swap and rounded down
+        let supply = ctx.accounts.mint_lp.supply as u128;
+        let lp_in = (received - res.deposit) as u128 * supply
+            / (reserve_in as u128 + (res.deposit - protocol_amount) as u128);
+        let lp_out = res.withdraw as u128 * supply / (reserve_out - res.withdraw) as u128;
+        let lp = u64::try_from(lp_in.min(lp_out)).map_err(|_| ErrorCode::MathOverflow)?;
+        require!(lp != 0 && lp >= min_lp, ErrorCode::SlippageExceded);
 
-    pub fn execute_swap(ctx: Context<ExecuteSwap>, _deposit: u64, _withdraw: u64) -> Result<()> {
-        let deposit_amount = _deposit;
-        let withdraw_amount = _withdraw;
+        let (user_token_in, vault_in, protocol_in) = match is_x {
+            true => (
+                &ctx.accounts.user_x,
+                ctx.accounts.vault_x.to_account_info(),
+                ctx.accounts.protocol_x.to_account_info(),
+            ),
+            false => (
+                &ctx.accounts.user_y,
+                ctx.accounts.vault_y.to_account_info(),
+                ctx.accounts.protocol_y.to_account_info(),
+            ),
+        };
 
-        require!(
-            deposit_amount > 0 && withdraw_amount > 0,
-            ErrorCode::InvalidAmount
-        );
+        // Native SOL input is wrapped from the user's lamports as needed
+        if mint_in.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                program_in.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                user_token_in,
+                amount_in,
+            )?;
+        }
 
-        // NOW user signs and can authorize transfers
-        transfer(
+        transfer_checked(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.user_x.to_account_info(),
-                    to: ctx.accounts.vault_x.to_account_info(),
+                program_in.to_account_info(),
+                TransferChecked {
+                    from: user_token_in.to_account_info(),
+                    mint: mint_in.to_account_info(),
+                    to: vault_in.clone(),
                     authority: ctx.accounts.user.to_account_info(),
                 },
             ),
-            deposit_amount,
+            amount_in,
+            mint_in.decimals,
         )?;
 
-        // Config authority transfers Y tokens to user
         let seeds = &[
             &b"config"[..],
             &ctx.accounts.config.seed.to_le_bytes(),
@@ -399,254 +640,4806 @@ pub mod whispr {
         ];
         let signer_seeds = &[&seeds[..]];
 
-        transfer(
+        if protocol_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    program_in.to_account_info(),
+                    TransferChecked {
+                        from: vault_in,
+                        mint: mint_in.to_account_info(),
+                        to: protocol_in,
+                        authority: ctx.accounts.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                protocol_amount,
+                mint_in.decimals,
+            )?;
+        }
+
+        mint_to(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.vault_y.to_account_info(),
-                    to: ctx.accounts.user_y.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.mint_lp.to_account_info(),
+                    to: ctx.accounts.user_lp.to_account_info(),
                     authority: ctx.accounts.config.to_account_info(),
                 },
                 signer_seeds,
             ),
-            withdraw_amount,
+            lp,
         )?;
 
-        // Mark as executed
-        ctx.accounts.swap_state.status = SwapStatus::Executed;
-
-        // emit!(ConfidentialSwapExecutedEvent {
-        //     user: ctx.accounts.user.key(),
-        //     config: ctx.accounts.config.key(),
-        //     computation_offset: ctx.accounts.swap_state.computation_offset,
-        //     deposit_amount,
-        //     withdraw_amount,
-        // });
-
+        let (x_amount, y_amount) = match is_x {
+            true => (amount_in, 0),
+            false => (0, amount_in),
+        };
+        emit!(DepositEvent {
+            user: ctx.accounts.user.key(),
+            amount: lp,
+            x_amount,
+            y_amount,
+        });
         Ok(())
     }
-}
 
-// ========================= STATE =========================
+    pub fn withdraw_single(
+        ctx: Context<Zap>,
+        is_x: bool,
+        lp_amount: u64,
+        min_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
+        require!(lp_amount != 0, ErrorCode::InvalidAmount);
+        check_deadline(deadline)?;
 
-#[account]
-pub struct Config {
-    pub seed: u64,
-    pub authority: Option<Pubkey>,
-    pub mint_x: Pubkey,
-    pub mint_y: Pubkey,
-    pub fee: u16,
-    pub locked: bool,
-    pub config_bump: u8,
-    pub lp_bump: u8,
-}
+        let amounts = ConstantProduct::xy_withdraw_amounts_from_l(
+            ctx.accounts.vault_x.amount,
+            ctx.accounts.vault_y.amount,
+            ctx.accounts.mint_lp.supply,
+            lp_amount,
+            ctx.accounts.config.precision.into(),
+        )
+        .map_err(|_| ErrorCode::InvalidAmount)?;
 
-impl Space for Config {
-    const INIT_SPACE: usize = 8 + 8 + 32 + 1 + 32 * 2 + 2 + 1 + 1 * 2;
-}
+        // The other side's share is swapped back into the pool against the
+        // reserves left after the withdrawal, paying the fee on that part only
+        let mut curve = ConstantProduct::init(
+            ctx.accounts.vault_x.amount - amounts.x,
+            ctx.accounts.vault_y.amount - amounts.y,
+            ctx.accounts.mint_lp.supply - lp_amount,
+            ctx.accounts.config.fee,
+            None,
+        )
+        .map_err(|_| ErrorCode::InvalidAmount)?;
 
-#[account]
-pub struct SwapState {
-    pub user: Pubkey,
-    pub config: Pubkey,
-    pub computation_offset: u64,
-    //  pub is_x: bool,
-    pub amount: u64,
-    pub min_output: u64,
-    pub status: SwapStatus,
-    pub created_at: i64,
-}
+        // is_x = true withdraws X only, otherwise Y only
+        let (pair, kept, swapped) = match is_x {
+            true => (LiquidityPair::Y, amounts.x, amounts.y),
+            false => (LiquidityPair::X, amounts.y, amounts.x),
+        };
+        let res = curve
+            .swap(pair, swapped, 0)
+            .map_err(|_| ErrorCode::InvalidAmount)?;
+        let protocol_amount = protocol_fee_amount(&ctx.accounts.config, res.deposit);
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
-pub enum SwapStatus {
-    Initiated,
-    Computing,
-    Computed,
-    Executed,
-    Failed,
-}
+        let (mint_out, mint_swapped) = match is_x {
+            true => (&ctx.accounts.mint_x, &ctx.accounts.mint_y),
+            false => (&ctx.accounts.mint_y, &ctx.accounts.mint_x),
+        };
+        let (program_out, program_swapped) = match is_x {
+            true => (&ctx.accounts.token_program_x, &ctx.accounts.token_program_y),
+            false => (&ctx.accounts.token_program_y, &ctx.accounts.token_program_x),
+        };
 
-impl Space for SwapState {
-    const INIT_SPACE: usize = 8 + 32 + 32 + 8 + 8 + 8 + 1 + 8;
-}
+        // The user receives the total less any Token-2022 transfer fee
+        let amount_out = kept + res.withdraw;
+        let received = amount_out - MintTransferFee::of(mint_out)?.on(amount_out);
+        require!(received >= min_out, ErrorCode::SlippageExceded);
 
-// ========================= AMM ACCOUNTS =========================
+        let (vault_out, user_out, vault_swapped, protocol_swapped) = match is_x {
+            true => (
+                ctx.accounts.vault_x.to_account_info(),
+                ctx.accounts.user_x.to_account_info(),
+                ctx.accounts.vault_y.to_account_info(),
+                ctx.accounts.protocol_y.to_account_info(),
+            ),
+            false => (
+                ctx.accounts.vault_y.to_account_info(),
+                ctx.accounts.user_y.to_account_info(),
+                ctx.accounts.vault_x.to_account_info(),
+                ctx.accounts.protocol_x.to_account_info(),
+            ),
+        };
 
-#[derive(Accounts)]
-#[instruction(seed: u64)]
-pub struct InitializeAmm<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-    pub mint_y: Account<'info, Mint>,
-    #[account(
-        init,
-        payer = admin,
-        seeds = [b"lp", config.key().as_ref()],
-        bump,
-        mint::decimals = 6,
-        mint::authority = config
-    )]
-    pub mint_lp: Account<'info, Mint>,
-    #[account(
-        init,
-        payer = admin,
-        associated_token::mint = mint_x,
+        let seeds = &[
+            &b"config"[..],
+            &ctx.accounts.config.seed.to_le_bytes(),
+            &[ctx.accounts.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        if protocol_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    program_swapped.to_account_info(),
+                    TransferChecked {
+                        from: vault_swapped,
+                        mint: mint_swapped.to_account_info(),
+                        to: protocol_swapped,
+                        authority: ctx.accounts.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                protocol_amount,
+                mint_swapped.decimals,
+            )?;
+        }
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                program_out.to_account_info(),
+                TransferChecked {
+                    from: vault_out,
+                    mint: mint_out.to_account_info(),
+                    to: user_out,
+                    authority: ctx.accounts.config.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount_out,
+            mint_out.decimals,
+        )?;
+
+        burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.mint_lp.to_account_info(),
+                    from: ctx.accounts.user_lp.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+
+        // Native SOL output is unwrapped back into lamports
+        if mint_out.key() == spl_token::native_mint::ID {
+            let user_token_out = match is_x {
+                true => &ctx.accounts.user_x,
+                false => &ctx.accounts.user_y,
+            };
+            unwrap_sol(
+                program_out.to_account_info(),
+                user_token_out,
+                ctx.accounts.user.to_account_info(),
+            )?;
+        }
+
+        let (x_amount, y_amount) = match is_x {
+            true => (amount_out, 0),
+            false => (0, amount_out),
+        };
+        emit!(WithdrawEvent {
+            user: ctx.accounts.user.key(),
+            amount: lp_amount,
+            x_amount,
+            y_amount,
+        });
+        Ok(())
+    }
+
+    pub fn lock(ctx: Context<Update>) -> Result<()> {
+        require!(
+            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
+            ErrorCode::InvalidAuthority
+        );
+        ctx.accounts.config.locked = true;
+        emit!(LockEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+        });
+        Ok(())
+    }
+
+    pub fn unlock(ctx: Context<Update>) -> Result<()> {
+        require!(
+            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
+            ErrorCode::InvalidAuthority
+        );
+        ctx.accounts.config.locked = false;
+        emit!(UnlockEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+        });
+        Ok(())
+    }
+
+    pub fn set_fee(ctx: Context<Update>, fee: u16) -> Result<()> {
+        require!(
+            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
+            ErrorCode::InvalidAuthority
+        );
+        require!(fee <= MAX_FEE, ErrorCode::InvalidFee);
+        ctx.accounts.config.fee = fee;
+        emit!(SetFeeEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            fee,
+        });
+        Ok(())
+    }
+
+    pub fn set_protocol_fee(ctx: Context<Update>, protocol_fee: u16) -> Result<()> {
+        require!(
+            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
+            ErrorCode::InvalidAuthority
+        );
+        require!(protocol_fee <= MAX_PROTOCOL_FEE, ErrorCode::InvalidFee);
+        ctx.accounts.config.protocol_fee = protocol_fee;
+        emit!(SetProtocolFeeEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            protocol_fee,
+        });
+        Ok(())
+    }
+
+    pub fn claim_protocol_fees(ctx: Context<ClaimProtocolFees>) -> Result<()> {
+        require!(
+            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
+            ErrorCode::InvalidAuthority
+        );
+
+        let x_amount = ctx.accounts.protocol_x.amount;
+        let y_amount = ctx.accounts.protocol_y.amount;
+
+        let seeds = &[
+            &b"config"[..],
+            &ctx.accounts.config.seed.to_le_bytes(),
+            &[ctx.accounts.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        if x_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program_x.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.protocol_x.to_account_info(),
+                        mint: ctx.accounts.mint_x.to_account_info(),
+                        to: ctx.accounts.user_x.to_account_info(),
+                        authority: ctx.accounts.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                x_amount,
+                ctx.accounts.mint_x.decimals,
+            )?;
+        }
+
+        if y_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program_y.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.protocol_y.to_account_info(),
+                        mint: ctx.accounts.mint_y.to_account_info(),
+                        to: ctx.accounts.user_y.to_account_info(),
+                        authority: ctx.accounts.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                y_amount,
+                ctx.accounts.mint_y.decimals,
+            )?;
+        }
+
+        emit!(ClaimProtocolFeesEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            x_amount,
+            y_amount,
+        });
+        Ok(())
+    }
+
+    pub fn propose_authority(ctx: Context<Update>, new_authority: Pubkey) -> Result<()> {
+        require!(
+            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
+            ErrorCode::InvalidAuthority
+        );
+        ctx.accounts.config.pending_authority = Some(new_authority);
+        emit!(ProposeAuthorityEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            new_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<Update>) -> Result<()> {
+        require!(
+            ctx.accounts.config.pending_authority == Some(ctx.accounts.user.key()),
+            ErrorCode::InvalidAuthority
+        );
+        ctx.accounts.config.authority = Some(ctx.accounts.user.key());
+        ctx.accounts.config.pending_authority = None;
+        emit!(AcceptAuthorityEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+        });
+        Ok(())
+    }
+
+    pub fn renounce_authority(ctx: Context<Update>) -> Result<()> {
+        require!(
+            ctx.accounts.config.authority == Some(ctx.accounts.user.key()),
+            ErrorCode::InvalidAuthority
+        );
+        // Nobody could claim protocol fees accrued after this, so they have
+        // to be switched off first. Claim what has accrued before renouncing.
+        require!(
+            ctx.accounts.config.protocol_fee == 0,
+            ErrorCode::ProtocolFeeActive
+        );
+        // A dark pool whose sealing never completes can only be retried by
+        // the authority
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
+        ctx.accounts.config.authority = None;
+        ctx.accounts.config.pending_authority = None;
+        emit!(RenounceAuthorityEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+        });
+        Ok(())
+    }
+
+    // ========================= CONFIDENTIAL SWAP =========================
+    pub fn init_compute_swap_comp_def(ctx: Context<InitComputeSwapCompDef>) -> Result<()> {
+        init_comp_def(ctx.accounts, true, 0, None, None)?;
+        Ok(())
+    }
+
+    pub fn compute_swap(
+        ctx: Context<ComputeSwap>,
+        computation_offset: u64,
+        pub_key: [u8; 32],
+        nonce: u128,
+        encrypted_is_x: [u8; 32],       // Encrypted bool
+        encrypted_amount: [u8; 32],     // Encrypted u64
+        encrypted_min_output: [u8; 32], // Encrypted u64
+        // Plaintext escrow amounts. They are public and bound the trade size
+        // from above, so traders wanting to hide the size over-escrow.
+        max_x_in: u64,
+        max_y_in: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
+        require!(max_x_in != 0 || max_y_in != 0, ErrorCode::InvalidAmount);
+        check_deadline(deadline)?;
+
+        // Initialize swap state
+        let clock = Clock::get()?;
+        ctx.accounts.swap_state.user = ctx.accounts.user.key();
+        ctx.accounts.swap_state.config = ctx.accounts.config.key();
+        ctx.accounts.swap_state.computation_offset = computation_offset;
+        ctx.accounts.swap_state.deposit_amount = 0;
+        ctx.accounts.swap_state.withdraw_amount = 0;
+        ctx.accounts.swap_state.status = SwapStatus::Initiated;
+        ctx.accounts.swap_state.created_at = clock.unix_timestamp;
+        ctx.accounts.swap_state.deadline = deadline;
+        ctx.accounts.swap_state.bump = ctx.bumps.swap_state;
+
+        // A native SOL side is wrapped from the user's lamports as needed
+        if ctx.accounts.mint_x.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                ctx.accounts.token_program_x.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.user_x,
+                max_x_in,
+            )?;
+        }
+        if ctx.accounts.mint_y.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                ctx.accounts.token_program_y.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.user_y,
+                max_y_in,
+            )?;
+        }
+
+        // Lock the trader's maximum input in escrow so the callback can settle
+        // without a second signature. Escrow amounts are public: the direction
+        // and size stay hidden while the swap is queued only if the escrows do
+        // not give them away, and both become public when it settles.
+        if max_x_in > 0 {
+            transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program_x.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.user_x.to_account_info(),
+                        mint: ctx.accounts.mint_x.to_account_info(),
+                        to: ctx.accounts.escrow_x.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                max_x_in,
+                ctx.accounts.mint_x.decimals,
+            )?;
+        }
+
+        if max_y_in > 0 {
+            transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program_y.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.user_y.to_account_info(),
+                        mint: ctx.accounts.mint_y.to_account_info(),
+                        to: ctx.accounts.escrow_y.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                max_y_in,
+                ctx.accounts.mint_y.decimals,
+            )?;
+        }
+
+        // What the escrows actually hold, after any Token-2022 transfer fee
+        ctx.accounts.escrow_x.reload()?;
+        ctx.accounts.escrow_y.reload()?;
+        let fee_x = MintTransferFee::of(&ctx.accounts.mint_x)?;
+        let fee_y = MintTransferFee::of(&ctx.accounts.mint_y)?;
+
+        // Pass three encrypted values separately
+        let args = vec![
+            Argument::ArcisPubkey(pub_key),
+            Argument::PlaintextU128(nonce),
+            Argument::EncryptedBool(encrypted_is_x),  // is_x
+            Argument::EncryptedU64(encrypted_amount), // amount
+            Argument::EncryptedU64(encrypted_min_output), // min_output
+            Argument::PlaintextU64(ctx.accounts.vault_x.amount),
+            Argument::PlaintextU64(ctx.accounts.vault_y.amount),
+            Argument::PlaintextU16(ctx.accounts.config.fee),
+            Argument::PlaintextU16(ctx.accounts.config.protocol_fee),
+            Argument::PlaintextU64(ctx.accounts.escrow_x.amount),
+            Argument::PlaintextU64(ctx.accounts.escrow_y.amount),
+            Argument::PlaintextU16(fee_x.basis_points),
+            Argument::PlaintextU64(fee_x.maximum_fee),
+            Argument::PlaintextU16(fee_y.basis_points),
+            Argument::PlaintextU64(fee_y.maximum_fee),
+        ];
+
+        let callback_accounts = ctx.accounts.callback_accounts();
+        queue_computation(
+            ctx.accounts,
+            computation_offset,
+            args,
+            callback_accounts,
+            None,
+        )?;
+
+        ctx.accounts.swap_state.status = SwapStatus::Computing;
+
+        emit!(ConfidentialSwapInitiatedEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset,
+        });
+
+        Ok(())
+    }
+
+    #[arcium_callback(encrypted_ix = "compute_swap")]
+    pub fn compute_swap_callback(
+        ctx: Context<ComputeSwapCallback>,
+        output: ComputationOutputs<ComputeSwapOutput>,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.swap_state.status == SwapStatus::Computing,
+            ErrorCode::InvalidSwapStatus
+        );
+
+        // Extract results from MPC computation
+        let (swap_result, x_in, y_in, x_out, y_out, protocol_x, protocol_y) = match output {
+            ComputationOutputs::Success(ComputeSwapOutput {
+                field_0:
+                    ComputeSwapTupleStruct0 {
+                        field_0: swap_result,
+                        field_1: x_in,
+                        field_2: y_in,
+                        field_3: x_out,
+                        field_4: y_out,
+                        field_5: protocol_x,
+                        field_6: protocol_y,
+                    },
+            }) => (
+                swap_result,
+                x_in,
+                y_in,
+                x_out,
+                y_out,
+                protocol_x,
+                protocol_y,
+            ),
+            _ => {
+                // Persist the failure instead of reverting, so the swap can be cleaned up
+                ctx.accounts
+                    .reject(SwapStatus::Failed, ErrorCode::AbortedComputation)?;
+                return Ok(());
+            }
+        };
+
+        // The deadline also binds settlement, a late callback is refunded
+        if check_deadline(ctx.accounts.swap_state.deadline).is_err() {
+            ctx.accounts
+                .reject(SwapStatus::Rejected, ErrorCode::DeadlineExceeded)?;
+            return Ok(());
+        }
+
+        // The public vaults were emptied into the dark pool since queueing
+        if ctx.accounts.config.dark {
+            ctx.accounts
+                .reject(SwapStatus::Rejected, ErrorCode::DarkPoolActive)?;
+            return Ok(());
+        }
+
+        // A zero settlement means the trader's encrypted min_output was not met
+        // or the escrow did not cover the amount
+        let deposit_amount = x_in.saturating_add(y_in);
+        let withdraw_amount = x_out.saturating_add(y_out);
+        if deposit_amount == 0 || withdraw_amount == 0 {
+            ctx.accounts
+                .reject(SwapStatus::Rejected, ErrorCode::SlippageExceded)?;
+            return Ok(());
+        }
+
+        // The quote was computed against the reserves at queue time, only
+        // what reaches the vaults after any transfer fee counts towards k
+        let received_x = x_in - MintTransferFee::of(&ctx.accounts.mint_x)?.on(x_in);
+        let received_y = y_in - MintTransferFee::of(&ctx.accounts.mint_y)?.on(y_in);
+        if received_x < protocol_x
+            || received_y < protocol_y
+            || !ctx.accounts.preserves_k(
+                received_x - protocol_x,
+                received_y - protocol_y,
+                x_out,
+                y_out,
+            )
+        {
+            ctx.accounts
+                .reject(SwapStatus::Rejected, ErrorCode::StaleReserves)?;
+            return Ok(());
+        }
+
+        // Both legs run the same code whatever the direction, the unused one
+        // with zero amounts. The transfers themselves show the direction.
+        ctx.accounts.settle(true, x_in, x_out, protocol_x)?;
+        ctx.accounts.settle(false, y_in, y_out, protocol_y)?;
+        ctx.accounts.release_escrows(x_in, y_in)?;
+
+        ctx.accounts.swap_state.deposit_amount = deposit_amount;
+        ctx.accounts.swap_state.withdraw_amount = withdraw_amount;
+        ctx.accounts.swap_state.status = SwapStatus::Executed;
+
+        emit!(ConfidentialSwapExecutedEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset: ctx.accounts.swap_state.computation_offset,
+            deposit_amount: swap_result.ciphertexts[0],
+            withdraw_amount: swap_result.ciphertexts[1],
+            nonce: swap_result.nonce,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_swap(ctx: Context<CancelSwap>) -> Result<()> {
+        require!(
+            matches!(
+                ctx.accounts.swap_state.status,
+                SwapStatus::Initiated | SwapStatus::Computing
+            ),
+            ErrorCode::InvalidSwapStatus
+        );
+
+        // Anyone can crank a stale swap once the pool timeout has passed
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp
+                >= ctx
+                    .accounts
+                    .swap_state
+                    .created_at
+                    .saturating_add(ctx.accounts.config.swap_timeout),
+            ErrorCode::SwapNotExpired
+        );
+
+        // Hand the escrow back, rent is refunded to the user by the close constraints
+        let seeds = &[
+            &b"config"[..],
+            &ctx.accounts.config.seed.to_le_bytes(),
+            &[ctx.accounts.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        release_escrow(
+            ctx.accounts.token_program_x.to_account_info(),
+            &ctx.accounts.mint_x,
+            &ctx.accounts.escrow_x,
+            0,
+            ctx.accounts.user_x.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.accounts.config.to_account_info(),
+            signer_seeds,
+        )?;
+        release_escrow(
+            ctx.accounts.token_program_y.to_account_info(),
+            &ctx.accounts.mint_y,
+            &ctx.accounts.escrow_y,
+            0,
+            ctx.accounts.user_y.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.accounts.config.to_account_info(),
+            signer_seeds,
+        )?;
+
+        emit!(ConfidentialSwapCancelledEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset: ctx.accounts.swap_state.computation_offset,
+        });
+        Ok(())
+    }
+
+    pub fn close_swap(ctx: Context<CloseSwap>) -> Result<()> {
+        require!(
+            matches!(
+                ctx.accounts.swap_state.status,
+                SwapStatus::Executed | SwapStatus::Failed | SwapStatus::Rejected
+            ),
+            ErrorCode::InvalidSwapStatus
+        );
+        Ok(())
+    }
+
+    // ========================= CONFIDENTIAL LIQUIDITY =========================
+    pub fn init_compute_deposit_comp_def(ctx: Context<InitComputeDepositCompDef>) -> Result<()> {
+        init_comp_def(ctx.accounts, true, 0, None, None)?;
+        Ok(())
+    }
+
+    pub fn init_compute_withdraw_comp_def(ctx: Context<InitComputeWithdrawCompDef>) -> Result<()> {
+        init_comp_def(ctx.accounts, true, 0, None, None)?;
+        Ok(())
+    }
+
+    /// Queues a deposit whose LP amount and limits are encrypted. Only the
+    /// limits stay confidential: the callback mints and transfers the settled
+    /// LP, x and y amounts in the clear, so they are public once it lands.
+    pub fn compute_deposit(
+        ctx: Context<ComputeDeposit>,
+        computation_offset: u64,
+        pub_key: [u8; 32],
+        nonce: u128,
+        encrypted_lp_amount: [u8; 32], // Encrypted u64
+        encrypted_max_x: [u8; 32],     // Encrypted u64
+        encrypted_max_y: [u8; 32],     // Encrypted u64
+        max_x_in: u64,
+        max_y_in: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
+        require!(max_x_in != 0 && max_y_in != 0, ErrorCode::InvalidAmount);
+        check_deadline(deadline)?;
+        // The first deposit sets the price publicly, it goes through `deposit`
+        require!(
+            ctx.accounts.mint_lp.supply != 0,
+            ErrorCode::InsufficientLiquidity
+        );
+
+        let clock = Clock::get()?;
+        ctx.accounts.liquidity_state.set_inner(LiquidityState {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset,
+            is_deposit: true,
+            status: LiquidityStatus::Initiated,
+            created_at: clock.unix_timestamp,
+            deadline,
+            bump: ctx.bumps.liquidity_state,
+        });
+
+        // A native SOL side is wrapped from the user's lamports as needed
+        if ctx.accounts.mint_x.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                ctx.accounts.token_program_x.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.user_x,
+                max_x_in,
+            )?;
+        }
+        if ctx.accounts.mint_y.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                ctx.accounts.token_program_y.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.user_y,
+                max_y_in,
+            )?;
+        }
+
+        // Over-fund the escrows so the deposit size stays hidden until settlement
+        transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program_x.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_x.to_account_info(),
+                    mint: ctx.accounts.mint_x.to_account_info(),
+                    to: ctx.accounts.escrow_x.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            max_x_in,
+            ctx.accounts.mint_x.decimals,
+        )?;
+
+        transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program_y.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_y.to_account_info(),
+                    mint: ctx.accounts.mint_y.to_account_info(),
+                    to: ctx.accounts.escrow_y.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            max_y_in,
+            ctx.accounts.mint_y.decimals,
+        )?;
+
+        // What the escrows actually hold, after any Token-2022 transfer fee
+        ctx.accounts.escrow_x.reload()?;
+        ctx.accounts.escrow_y.reload()?;
+        let fee_x = MintTransferFee::of(&ctx.accounts.mint_x)?;
+        let fee_y = MintTransferFee::of(&ctx.accounts.mint_y)?;
+
+        let args = vec![
+            Argument::ArcisPubkey(pub_key),
+            Argument::PlaintextU128(nonce),
+            Argument::EncryptedU64(encrypted_lp_amount),
+            Argument::EncryptedU64(encrypted_max_x),
+            Argument::EncryptedU64(encrypted_max_y),
+            Argument::PlaintextU64(ctx.accounts.vault_x.amount),
+            Argument::PlaintextU64(ctx.accounts.vault_y.amount),
+            Argument::PlaintextU64(ctx.accounts.mint_lp.supply),
+            Argument::PlaintextU64(ctx.accounts.escrow_x.amount),
+            Argument::PlaintextU64(ctx.accounts.escrow_y.amount),
+            Argument::PlaintextU16(fee_x.basis_points),
+            Argument::PlaintextU64(fee_x.maximum_fee),
+            Argument::PlaintextU16(fee_y.basis_points),
+            Argument::PlaintextU64(fee_y.maximum_fee),
+        ];
+
+        let callback_accounts = ctx.accounts.callback_accounts();
+        queue_computation(
+            ctx.accounts,
+            computation_offset,
+            args,
+            callback_accounts,
+            None,
+        )?;
+
+        ctx.accounts.liquidity_state.status = LiquidityStatus::Computing;
+
+        emit!(ConfidentialLiquidityInitiatedEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset,
+            is_deposit: true,
+        });
+        Ok(())
+    }
+
+    #[arcium_callback(encrypted_ix = "compute_deposit")]
+    pub fn compute_deposit_callback(
+        ctx: Context<ComputeDepositCallback>,
+        output: ComputationOutputs<ComputeDepositOutput>,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.liquidity_state.status == LiquidityStatus::Computing,
+            ErrorCode::InvalidLiquidityStatus
+        );
+
+        let (result, lp_amount, x_amount, y_amount) = match output {
+            ComputationOutputs::Success(ComputeDepositOutput {
+                field_0:
+                    ComputeDepositTupleStruct0 {
+                        field_0: result,
+                        field_1: lp_amount,
+                        field_2: x_amount,
+                        field_3: y_amount,
+                    },
+            }) => (result, lp_amount, x_amount, y_amount),
+            _ => {
+                ctx.accounts
+                    .reject(LiquidityStatus::Failed, ErrorCode::AbortedComputation)?;
+                return Ok(());
+            }
+        };
+
+        if check_deadline(ctx.accounts.liquidity_state.deadline).is_err() {
+            ctx.accounts
+                .reject(LiquidityStatus::Rejected, ErrorCode::DeadlineExceeded)?;
+            return Ok(());
+        }
+
+        if ctx.accounts.config.dark {
+            ctx.accounts
+                .reject(LiquidityStatus::Rejected, ErrorCode::DarkPoolActive)?;
+            return Ok(());
+        }
+
+        // A zero settlement means the LP's encrypted limits were not met
+        if lp_amount == 0 {
+            ctx.accounts
+                .reject(LiquidityStatus::Rejected, ErrorCode::SlippageExceded)?;
+            return Ok(());
+        }
+
+        // The amounts were computed against the reserves at queue time
+        if !ctx.accounts.covers_lp(lp_amount, x_amount, y_amount)? {
+            ctx.accounts
+                .reject(LiquidityStatus::Rejected, ErrorCode::StaleReserves)?;
+            return Ok(());
+        }
+
+        // Settled straight from the circuit output and not stored. The amounts
+        // still show in this transaction's token balance changes and LP supply.
+        ctx.accounts.settle(lp_amount, x_amount, y_amount)?;
+        ctx.accounts.release_escrows(x_amount, y_amount)?;
+
+        ctx.accounts.liquidity_state.status = LiquidityStatus::Executed;
+
+        emit!(ConfidentialLiquidityExecutedEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset: ctx.accounts.liquidity_state.computation_offset,
+            lp_amount: result.ciphertexts[0],
+            x_amount: result.ciphertexts[1],
+            y_amount: result.ciphertexts[2],
+            nonce: result.nonce,
+        });
+        Ok(())
+    }
+
+    /// Queues a withdrawal whose LP amount and limits are encrypted. As with
+    /// `compute_deposit`, the settled amounts are public in the callback's
+    /// burn and transfers, only the limits stay confidential.
+    pub fn compute_withdraw(
+        ctx: Context<ComputeWithdraw>,
+        computation_offset: u64,
+        pub_key: [u8; 32],
+        nonce: u128,
+        encrypted_lp_amount: [u8; 32], // Encrypted u64
+        encrypted_min_x: [u8; 32],     // Encrypted u64
+        encrypted_min_y: [u8; 32],     // Encrypted u64
+        max_lp_in: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        require!(ctx.accounts.config.locked == false, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.config.dark, ErrorCode::DarkPoolActive);
+        require!(max_lp_in != 0, ErrorCode::InvalidAmount);
+        check_deadline(deadline)?;
+
+        let clock = Clock::get()?;
+        ctx.accounts.liquidity_state.set_inner(LiquidityState {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset,
+            is_deposit: false,
+            status: LiquidityStatus::Initiated,
+            created_at: clock.unix_timestamp,
+            deadline,
+            bump: ctx.bumps.liquidity_state,
+        });
+
+        // Over-fund the LP escrow so the burn size stays hidden until settlement
+        transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_lp.to_account_info(),
+                    mint: ctx.accounts.mint_lp.to_account_info(),
+                    to: ctx.accounts.escrow_lp.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            max_lp_in,
+            ctx.accounts.mint_lp.decimals,
+        )?;
+
+        let fee_x = MintTransferFee::of(&ctx.accounts.mint_x)?;
+        let fee_y = MintTransferFee::of(&ctx.accounts.mint_y)?;
+
+        let args = vec![
+            Argument::ArcisPubkey(pub_key),
+            Argument::PlaintextU128(nonce),
+            Argument::EncryptedU64(encrypted_lp_amount),
+            Argument::EncryptedU64(encrypted_min_x),
+            Argument::EncryptedU64(encrypted_min_y),
+            Argument::PlaintextU64(ctx.accounts.vault_x.amount),
+            Argument::PlaintextU64(ctx.accounts.vault_y.amount),
+            Argument::PlaintextU64(ctx.accounts.mint_lp.supply),
+            Argument::PlaintextU64(max_lp_in),
+            Argument::PlaintextU16(fee_x.basis_points),
+            Argument::PlaintextU64(fee_x.maximum_fee),
+            Argument::PlaintextU16(fee_y.basis_points),
+            Argument::PlaintextU64(fee_y.maximum_fee),
+        ];
+
+        let callback_accounts = ctx.accounts.callback_accounts();
+        queue_computation(
+            ctx.accounts,
+            computation_offset,
+            args,
+            callback_accounts,
+            None,
+        )?;
+
+        ctx.accounts.liquidity_state.status = LiquidityStatus::Computing;
+
+        emit!(ConfidentialLiquidityInitiatedEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset,
+            is_deposit: false,
+        });
+        Ok(())
+    }
+
+    #[arcium_callback(encrypted_ix = "compute_withdraw")]
+    pub fn compute_withdraw_callback(
+        ctx: Context<ComputeWithdrawCallback>,
+        output: ComputationOutputs<ComputeWithdrawOutput>,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.liquidity_state.status == LiquidityStatus::Computing,
+            ErrorCode::InvalidLiquidityStatus
+        );
+
+        let (result, lp_amount, x_amount, y_amount) = match output {
+            ComputationOutputs::Success(ComputeWithdrawOutput {
+                field_0:
+                    ComputeWithdrawTupleStruct0 {
+                        field_0: result,
+                        field_1: lp_amount,
+                        field_2: x_amount,
+                        field_3: y_amount,
+                    },
+            }) => (result, lp_amount, x_amount, y_amount),
+            _ => {
+                ctx.accounts
+                    .reject(LiquidityStatus::Failed, ErrorCode::AbortedComputation)?;
+                return Ok(());
+            }
+        };
+
+        if check_deadline(ctx.accounts.liquidity_state.deadline).is_err() {
+            ctx.accounts
+                .reject(LiquidityStatus::Rejected, ErrorCode::DeadlineExceeded)?;
+            return Ok(());
+        }
+
+        if ctx.accounts.config.dark {
+            ctx.accounts
+                .reject(LiquidityStatus::Rejected, ErrorCode::DarkPoolActive)?;
+            return Ok(());
+        }
+
+        if lp_amount == 0 {
+            ctx.accounts
+                .reject(LiquidityStatus::Rejected, ErrorCode::SlippageExceded)?;
+            return Ok(());
+        }
+
+        // The payout must not exceed the LP's share of the current reserves
+        if !ctx.accounts.within_share(lp_amount, x_amount, y_amount) {
+            ctx.accounts
+                .reject(LiquidityStatus::Rejected, ErrorCode::StaleReserves)?;
+            return Ok(());
+        }
+
+        // Settled straight from the circuit output and not stored, see
+        // compute_deposit_callback
+        ctx.accounts.settle(lp_amount, x_amount, y_amount)?;
+        ctx.accounts.release_lp_escrow(lp_amount)?;
+
+        ctx.accounts.liquidity_state.status = LiquidityStatus::Executed;
+
+        emit!(ConfidentialLiquidityExecutedEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset: ctx.accounts.liquidity_state.computation_offset,
+            lp_amount: result.ciphertexts[0],
+            x_amount: result.ciphertexts[1],
+            y_amount: result.ciphertexts[2],
+            nonce: result.nonce,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_deposit(ctx: Context<CancelDeposit>) -> Result<()> {
+        require!(
+            matches!(
+                ctx.accounts.liquidity_state.status,
+                LiquidityStatus::Initiated | LiquidityStatus::Computing
+            ),
+            ErrorCode::InvalidLiquidityStatus
+        );
+
+        // Anyone can crank a stale deposit once the pool timeout has passed
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp
+                >= ctx
+                    .accounts
+                    .liquidity_state
+                    .created_at
+                    .saturating_add(ctx.accounts.config.swap_timeout),
+            ErrorCode::SwapNotExpired
+        );
+
+        let seeds = &[
+            &b"config"[..],
+            &ctx.accounts.config.seed.to_le_bytes(),
+            &[ctx.accounts.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        release_escrow(
+            ctx.accounts.token_program_x.to_account_info(),
+            &ctx.accounts.mint_x,
+            &ctx.accounts.escrow_x,
+            0,
+            ctx.accounts.user_x.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.accounts.config.to_account_info(),
+            signer_seeds,
+        )?;
+        release_escrow(
+            ctx.accounts.token_program_y.to_account_info(),
+            &ctx.accounts.mint_y,
+            &ctx.accounts.escrow_y,
+            0,
+            ctx.accounts.user_y.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.accounts.config.to_account_info(),
+            signer_seeds,
+        )?;
+
+        emit!(ConfidentialLiquidityCancelledEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset: ctx.accounts.liquidity_state.computation_offset,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_withdraw(ctx: Context<CancelWithdraw>) -> Result<()> {
+        require!(
+            matches!(
+                ctx.accounts.liquidity_state.status,
+                LiquidityStatus::Initiated | LiquidityStatus::Computing
+            ),
+            ErrorCode::InvalidLiquidityStatus
+        );
+
+        // Anyone can crank a stale withdrawal once the pool timeout has passed
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp
+                >= ctx
+                    .accounts
+                    .liquidity_state
+                    .created_at
+                    .saturating_add(ctx.accounts.config.swap_timeout),
+            ErrorCode::SwapNotExpired
+        );
+
+        let seeds = &[
+            &b"config"[..],
+            &ctx.accounts.config.seed.to_le_bytes(),
+            &[ctx.accounts.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        release_escrow(
+            ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.mint_lp,
+            &ctx.accounts.escrow_lp,
+            0,
+            ctx.accounts.user_lp.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.accounts.config.to_account_info(),
+            signer_seeds,
+        )?;
+
+        emit!(ConfidentialLiquidityCancelledEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            computation_offset: ctx.accounts.liquidity_state.computation_offset,
+        });
+        Ok(())
+    }
+
+    pub fn close_liquidity(ctx: Context<CloseLiquidity>) -> Result<()> {
+        require!(
+            matches!(
+                ctx.accounts.liquidity_state.status,
+                LiquidityStatus::Executed | LiquidityStatus::Failed | LiquidityStatus::Rejected
+            ),
+            ErrorCode::InvalidLiquidityStatus
+        );
+        Ok(())
+    }
+
+    // ========================= DARK POOL =========================
+    pub fn init_seal_reserves_comp_def(ctx: Context<InitSealReservesCompDef>) -> Result<()> {
+        init_comp_def(ctx.accounts, true, 0, None, None)?;
+        Ok(())
+    }
+
+    pub fn init_reveal_reserves_comp_def(ctx: Context<InitRevealReservesCompDef>) -> Result<()> {
+        init_comp_def(ctx.accounts, true, 0, None, None)?;
+        Ok(())
+    }
+
+    pub fn init_dark_fund_comp_def(ctx: Context<InitDarkFundCompDef>) -> Result<()> {
+        init_comp_def(ctx.accounts, true, 0, None, None)?;
+        Ok(())
+    }
+
+    pub fn init_dark_swap_comp_def(ctx: Context<InitDarkSwapCompDef>) -> Result<()> {
+        init_comp_def(ctx.accounts, true, 0, None, None)?;
+        Ok(())
+    }
+
+    pub fn init_dark_withdraw_comp_def(ctx: Context<InitDarkWithdrawCompDef>) -> Result<()> {
+        init_comp_def(ctx.accounts, true, 0, None, None)?;
+        Ok(())
+    }
+
+    pub fn enable_dark_pool(
+        ctx: Context<EnableDarkPool>,
+        computation_offset: u64,
+        nonce: u128,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.config.authority == Some(ctx.accounts.payer.key()),
+            ErrorCode::InvalidAuthority
+        );
+        require!(!ctx.accounts.dark_pool.sealed, ErrorCode::DarkPoolActive);
+        require!(
+            ctx.accounts.vault_x.amount != 0 && ctx.accounts.vault_y.amount != 0,
+            ErrorCode::InsufficientLiquidity
+        );
+        // LPs get a window to withdraw between two dark periods
+        let public_since = ctx.accounts.dark_pool.toggled_at;
+        require!(
+            Clock::get()?.unix_timestamp >= public_since.saturating_add(DARK_COOLDOWN),
+            ErrorCode::DarkPoolCooldown
+        );
+
+        let timeout = ctx.accounts.config.swap_timeout;
+        claim_dark_slot(
+            &mut ctx.accounts.dark_pool.pending,
+            computation_offset,
+            timeout,
+        )?;
+        ctx.accounts.dark_pool.config = ctx.accounts.config.key();
+        ctx.accounts.dark_pool.bump = ctx.bumps.dark_pool;
+
+        // Public trading stops now, the reserves only move once they are sealed
+        ctx.accounts.config.dark = true;
+
+        // Sealed net of any Token-2022 transfer fee on the way into the dark vaults
+        let vault_x_amount = ctx.accounts.vault_x.amount;
+        let vault_y_amount = ctx.accounts.vault_y.amount;
+        let reserve_x =
+            vault_x_amount - MintTransferFee::of(&ctx.accounts.mint_x)?.on(vault_x_amount);
+        let reserve_y =
+            vault_y_amount - MintTransferFee::of(&ctx.accounts.mint_y)?.on(vault_y_amount);
+
+        let args = vec![
+            Argument::PlaintextU128(nonce),
+            Argument::PlaintextU64(reserve_x),
+            Argument::PlaintextU64(reserve_y),
+        ];
+
+        let callback_accounts = ctx.accounts.callback_accounts();
+        queue_computation(
+            ctx.accounts,
+            computation_offset,
+            args,
+            callback_accounts,
+            None,
+        )?;
+        Ok(())
+    }
+
+    #[arcium_callback(encrypted_ix = "seal_reserves")]
+    pub fn seal_reserves_callback(
+        ctx: Context<SealReservesCallback>,
+        output: ComputationOutputs<SealReservesOutput>,
+    ) -> Result<()> {
+        if !release_dark_slot(
+            &mut ctx.accounts.dark_pool.pending,
+            &ctx.accounts.computation_account.key(),
+        ) {
+            // The slot was claimed again, the newer computation owns the state
+            emit!(DarkPoolFailedEvent {
+                config: ctx.accounts.config.key(),
+                user: None,
+                reason: ErrorCode::StaleComputation.to_string(),
+            });
+            return Ok(());
+        }
+
+        let reserves = match output {
+            ComputationOutputs::Success(SealReservesOutput { field_0: reserves }) => reserves,
+            _ => {
+                // Nothing has moved yet, the pool simply stays public
+                ctx.accounts.config.dark = false;
+                emit!(DarkPoolFailedEvent {
+                    config: ctx.accounts.config.key(),
+                    user: None,
+                    reason: ErrorCode::AbortedComputation.to_string(),
+                });
+                return Ok(());
+            }
+        };
+
+        ctx.accounts.move_reserves()?;
+        ctx.accounts.dark_pool.reserves = reserves.ciphertexts;
+        ctx.accounts.dark_pool.nonce = reserves.nonce;
+        ctx.accounts.dark_pool.sealed = true;
+        ctx.accounts.dark_pool.toggled_at = Clock::get()?.unix_timestamp;
+
+        emit!(DarkPoolEnabledEvent {
+            config: ctx.accounts.config.key(),
+        });
+        Ok(())
+    }
+
+    /// Reopens a pool whose `seal_reserves` callback never landed. Public
+    /// trading and LP withdrawals stop when the seal is queued, so anyone can
+    /// undo it once the computation is older than the pool's swap timeout.
+    pub fn cancel_enable_dark_pool(ctx: Context<CancelEnableDarkPool>) -> Result<()> {
+        require!(ctx.accounts.config.dark, ErrorCode::DarkPoolInactive);
+        require!(!ctx.accounts.dark_pool.sealed, ErrorCode::DarkPoolActive);
+        if let Some(held) = ctx.accounts.dark_pool.pending {
+            require!(
+                Clock::get()?.unix_timestamp
+                    >= held.since.saturating_add(ctx.accounts.config.swap_timeout),
+                ErrorCode::DarkStateBusy
+            );
+        }
+
+        // A seal landing after this finds its slot gone and changes nothing
+        ctx.accounts.dark_pool.pending = None;
+        ctx.accounts.config.dark = false;
+
+        emit!(DarkPoolFailedEvent {
+            config: ctx.accounts.config.key(),
+            user: None,
+            reason: ErrorCode::DarkSealExpired.to_string(),
+        });
+        Ok(())
+    }
+
+    pub fn disable_dark_pool(ctx: Context<DisableDarkPool>, computation_offset: u64) -> Result<()> {
+        // LP withdrawals are closed while dark, so once the pool has been dark
+        // for MAX_DARK_PERIOD, or has no authority, anyone can bring it back
+        // to public for LPs to exit
+        let dark_until = ctx
+            .accounts
+            .dark_pool
+            .toggled_at
+            .saturating_add(MAX_DARK_PERIOD);
+        require!(
+            ctx.accounts.config.authority.is_none()
+                || ctx.accounts.config.authority == Some(ctx.accounts.payer.key())
+                || Clock::get()?.unix_timestamp >= dark_until,
+            ErrorCode::InvalidAuthority
+        );
+        require!(ctx.accounts.dark_pool.sealed, ErrorCode::DarkPoolInactive);
+
+        let timeout = ctx.accounts.config.swap_timeout;
+        claim_dark_slot(
+            &mut ctx.accounts.dark_pool.pending,
+            computation_offset,
+            timeout,
+        )?;
+
+        let args = vec![
+            Argument::PlaintextU128(ctx.accounts.dark_pool.nonce),
+            Argument::Account(
+                ctx.accounts.dark_pool.key(),
+                DARK_CIPHERTEXT_OFFSET,
+                DARK_RESERVES_LEN,
+            ),
+        ];
+
+        let callback_accounts = ctx.accounts.callback_accounts();
+        queue_computation(
+            ctx.accounts,
+            computation_offset,
+            args,
+            callback_accounts,
+            None,
+        )?;
+        Ok(())
+    }
+
+    #[arcium_callback(encrypted_ix = "reveal_reserves")]
+    pub fn reveal_reserves_callback(
+        ctx: Context<RevealReservesCallback>,
+        output: ComputationOutputs<RevealReservesOutput>,
+    ) -> Result<()> {
+        if !release_dark_slot(
+            &mut ctx.accounts.dark_pool.pending,
+            &ctx.accounts.computation_account.key(),
+        ) {
+            // The slot was claimed again, the newer computation owns the state
+            emit!(DarkPoolFailedEvent {
+                config: ctx.accounts.config.key(),
+                user: None,
+                reason: ErrorCode::StaleComputation.to_string(),
+            });
+            return Ok(());
+        }
+
+        let (x_amount, y_amount, protocol_x, protocol_y) = match output {
+            ComputationOutputs::Success(RevealReservesOutput {
+                field_0:
+                    RevealReservesTupleStruct0 {
+                        field_0: x_amount,
+                        field_1: y_amount,
+                        field_2: protocol_x,
+                        field_3: protocol_y,
+                    },
+            }) => (x_amount, y_amount, protocol_x, protocol_y),
+            _ => {
+                // The pool stays dark, disabling can be retried
+                emit!(DarkPoolFailedEvent {
+                    config: ctx.accounts.config.key(),
+                    user: None,
+                    reason: ErrorCode::AbortedComputation.to_string(),
+                });
+                return Ok(());
+            }
+        };
+
+        // Only the aggregate reserves become public, traders' balances stay
+        // behind in the dark vaults until they withdraw them
+        let accounts = &ctx.accounts;
+        accounts.move_out(
+            accounts.vault_x.to_account_info(),
+            accounts.vault_y.to_account_info(),
+            x_amount,
+            y_amount,
+        )?;
+        // Protocol fees accrued by dark swaps were kept out of the reserves
+        accounts.move_out(
+            accounts.protocol_x.to_account_info(),
+            accounts.protocol_y.to_account_info(),
+            protocol_x,
+            protocol_y,
+        )?;
+        ctx.accounts.dark_pool.sealed = false;
+        ctx.accounts.dark_pool.toggled_at = Clock::get()?.unix_timestamp;
+        ctx.accounts.config.dark = false;
+
+        emit!(DarkPoolDisabledEvent {
+            config: ctx.accounts.config.key(),
+            x_amount,
+            y_amount,
+        });
+        Ok(())
+    }
+
+    pub fn dark_fund(
+        ctx: Context<DarkFund>,
+        computation_offset: u64,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Result<()> {
+        // Once the pool is public again, funding only goes through to credit
+        // a funding whose computation was abandoned
+        let uncredited =
+            ctx.accounts.dark_balance.pending_x != 0 || ctx.accounts.dark_balance.pending_y != 0;
+        require!(
+            ctx.accounts.dark_pool.sealed || (amount_x == 0 && amount_y == 0),
+            ErrorCode::DarkPoolInactive
+        );
+        require!(
+            amount_x != 0 || amount_y != 0 || uncredited,
+            ErrorCode::InvalidAmount
+        );
+
+        let timeout = ctx.accounts.config.swap_timeout;
+        claim_dark_slot(
+            &mut ctx.accounts.dark_balance.pending,
+            computation_offset,
+            timeout,
+        )?;
+        let is_new = !ctx.accounts.dark_balance.initialized;
+        if is_new {
+            ctx.accounts.dark_balance.user = ctx.accounts.user.key();
+            ctx.accounts.dark_balance.config = ctx.accounts.config.key();
+            ctx.accounts.dark_balance.bump = ctx.bumps.dark_balance;
+        }
+
+        // A native SOL side is wrapped from the user's lamports as needed
+        if ctx.accounts.mint_x.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                ctx.accounts.token_program_x.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.user_x,
+                amount_x,
+            )?;
+        }
+        if ctx.accounts.mint_y.key() == spl_token::native_mint::ID {
+            wrap_sol(
+                ctx.accounts.token_program_y.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.user_y,
+                amount_y,
+            )?;
+        }
+
+        let dark_x_before = ctx.accounts.dark_x.amount;
+        let dark_y_before = ctx.accounts.dark_y.amount;
+
+        if amount_x > 0 {
+            transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program_x.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.user_x.to_account_info(),
+                        mint: ctx.accounts.mint_x.to_account_info(),
+                        to: ctx.accounts.dark_x.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                amount_x,
+                ctx.accounts.mint_x.decimals,
+            )?;
+        }
+
+        if amount_y > 0 {
+            transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program_y.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.user_y.to_account_info(),
+                        mint: ctx.accounts.mint_y.to_account_info(),
+                        to: ctx.accounts.dark_y.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                amount_y,
+                ctx.accounts.mint_y.decimals,
+            )?;
+        }
+
+        // Credit what the aggregated vaults actually received, after any
+        // Token-2022 transfer fee
+        ctx.accounts.dark_x.reload()?;
+        ctx.accounts.dark_y.reload()?;
+        let received_x = ctx.accounts.dark_x.amount - dark_x_before;
+        let received_y = ctx.accounts.dark_y.amount - dark_y_before;
+
+        // A funding whose computation was abandoned was never credited, it is
+        // credited along with this one
+        let credit_x = ctx.accounts.dark_balance.pending_x + received_x;
+        let credit_y = ctx.accounts.dark_balance.pending_y + received_y;
+        ctx.accounts.dark_balance.pending_x = credit_x;
+        ctx.accounts.dark_balance.pending_y = credit_y;
+
+        let args = vec![
+            Argument::PlaintextU128(ctx.accounts.dark_balance.nonce),
+            Argument::Account(
+                ctx.accounts.dark_balance.key(),
+                DARK_CIPHERTEXT_OFFSET,
+                DARK_BALANCE_LEN,
+            ),
+            Argument::PlaintextBool(is_new),
+            Argument::PlaintextU64(credit_x),
+            Argument::PlaintextU64(credit_y),
+        ];
+
+        let callback_accounts = ctx.accounts.callback_accounts();
+        queue_computation(
+            ctx.accounts,
+            computation_offset,
+            args,
+            callback_accounts,
+            None,
+        )?;
+        Ok(())
+    }
+
+    #[arcium_callback(encrypted_ix = "dark_fund")]
+    pub fn dark_fund_callback(
+        ctx: Context<DarkFundCallback>,
+        output: ComputationOutputs<DarkFundOutput>,
+    ) -> Result<()> {
+        if !release_dark_slot(
+            &mut ctx.accounts.dark_balance.pending,
+            &ctx.accounts.computation_account.key(),
+        ) {
+            // The slot was claimed again, the newer computation owns the state
+            emit!(DarkPoolFailedEvent {
+                config: ctx.accounts.config.key(),
+                user: Some(ctx.accounts.user.key()),
+                reason: ErrorCode::StaleComputation.to_string(),
+            });
+            return Ok(());
+        }
+
+        // Only the funding callback takes the uncredited amounts, whether it
+        // credits or refunds them
+        let x_amount = std::mem::take(&mut ctx.accounts.dark_balance.pending_x);
+        let y_amount = std::mem::take(&mut ctx.accounts.dark_balance.pending_y);
+
+        let balance = match output {
+            ComputationOutputs::Success(DarkFundOutput { field_0: balance }) => balance,
+            _ => {
+                // The deposit was never credited, hand it back
+                ctx.accounts.pay_out(x_amount, y_amount)?;
+                emit!(DarkPoolFailedEvent {
+                    config: ctx.accounts.config.key(),
+                    user: Some(ctx.accounts.user.key()),
+                    reason: ErrorCode::AbortedComputation.to_string(),
+                });
+                return Ok(());
+            }
+        };
+
+        ctx.accounts.dark_balance.balance = balance.ciphertexts;
+        ctx.accounts.dark_balance.nonce = balance.nonce;
+        ctx.accounts.dark_balance.initialized = true;
+
+        emit!(DarkFundEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            x_amount,
+            y_amount,
+        });
+        Ok(())
+    }
+
+    pub fn dark_swap(
+        ctx: Context<DarkSwap>,
+        computation_offset: u64,
+        pub_key: [u8; 32],
+        nonce: u128,
+        encrypted_is_x: [u8; 32],       // Encrypted bool
+        encrypted_amount: [u8; 32],     // Encrypted u64
+        encrypted_min_output: [u8; 32], // Encrypted u64
+    ) -> Result<()> {
+        require!(ctx.accounts.dark_pool.sealed, ErrorCode::DarkPoolInactive);
+        require!(
+            ctx.accounts.dark_balance.initialized,
+            ErrorCode::InvalidAmount
+        );
+
+        // Reserves and balance are rewritten by the callback, so only one
+        // computation may read each of them at a time
+        let timeout = ctx.accounts.config.swap_timeout;
+        claim_dark_slot(
+            &mut ctx.accounts.dark_pool.pending,
+            computation_offset,
+            timeout,
+        )?;
+        claim_dark_slot(
+            &mut ctx.accounts.dark_balance.pending,
+            computation_offset,
+            timeout,
+        )?;
+
+        let args = vec![
+            Argument::ArcisPubkey(pub_key),
+            Argument::PlaintextU128(nonce),
+            Argument::EncryptedBool(encrypted_is_x),
+            Argument::EncryptedU64(encrypted_amount),
+            Argument::EncryptedU64(encrypted_min_output),
+            Argument::PlaintextU128(ctx.accounts.dark_pool.nonce),
+            Argument::Account(
+                ctx.accounts.dark_pool.key(),
+                DARK_CIPHERTEXT_OFFSET,
+                DARK_RESERVES_LEN,
+            ),
+            Argument::PlaintextU128(ctx.accounts.dark_balance.nonce),
+            Argument::Account(
+                ctx.accounts.dark_balance.key(),
+                DARK_CIPHERTEXT_OFFSET,
+                DARK_BALANCE_LEN,
+            ),
+            Argument::PlaintextU16(ctx.accounts.config.fee),
+            Argument::PlaintextU16(ctx.accounts.config.protocol_fee),
+        ];
+
+        let callback_accounts = ctx.accounts.callback_accounts();
+        queue_computation(
+            ctx.accounts,
+            computation_offset,
+            args,
+            callback_accounts,
+            None,
+        )?;
+        Ok(())
+    }
+
+    #[arcium_callback(encrypted_ix = "dark_swap")]
+    pub fn dark_swap_callback(
+        ctx: Context<DarkSwapCallback>,
+        output: ComputationOutputs<DarkSwapOutput>,
+    ) -> Result<()> {
+        // Each slot is released if this computation still holds it, but the
+        // state is only written if it held both
+        let computation_account = ctx.accounts.computation_account.key();
+        let held_pool =
+            release_dark_slot(&mut ctx.accounts.dark_pool.pending, &computation_account);
+        let held_balance =
+            release_dark_slot(&mut ctx.accounts.dark_balance.pending, &computation_account);
+        if !(held_pool && held_balance) {
+            emit!(DarkPoolFailedEvent {
+                config: ctx.accounts.config.key(),
+                user: Some(ctx.accounts.user.key()),
+                reason: ErrorCode::StaleComputation.to_string(),
+            });
+            return Ok(());
+        }
+
+        let (reserves, balance, result) = match output {
+            ComputationOutputs::Success(DarkSwapOutput {
+                field_0:
+                    DarkSwapTupleStruct0 {
+                        field_0: reserves,
+                        field_1: balance,
+                        field_2: result,
+                    },
+            }) => (reserves, balance, result),
+            _ => {
+                emit!(DarkPoolFailedEvent {
+                    config: ctx.accounts.config.key(),
+                    user: Some(ctx.accounts.user.key()),
+                    reason: ErrorCode::AbortedComputation.to_string(),
+                });
+                return Ok(());
+            }
+        };
+
+        // No tokens move, a rejected trade re-encrypts the same values
+        ctx.accounts.dark_pool.reserves = reserves.ciphertexts;
+        ctx.accounts.dark_pool.nonce = reserves.nonce;
+        ctx.accounts.dark_balance.balance = balance.ciphertexts;
+        ctx.accounts.dark_balance.nonce = balance.nonce;
+
+        emit!(DarkSwapEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            deposit_amount: result.ciphertexts[0],
+            withdraw_amount: result.ciphertexts[1],
+            balance_x: result.ciphertexts[2],
+            balance_y: result.ciphertexts[3],
+            nonce: result.nonce,
+        });
+        Ok(())
+    }
+
+    pub fn dark_withdraw(
+        ctx: Context<DarkWithdraw>,
+        computation_offset: u64,
+        pub_key: [u8; 32],
+        nonce: u128,
+        encrypted_x_amount: [u8; 32], // Encrypted u64
+        encrypted_y_amount: [u8; 32], // Encrypted u64
+    ) -> Result<()> {
+        // Balances stay withdrawable after the pool goes back to public
+        require!(
+            ctx.accounts.dark_balance.initialized,
+            ErrorCode::InvalidAmount
+        );
+
+        let timeout = ctx.accounts.config.swap_timeout;
+        claim_dark_slot(
+            &mut ctx.accounts.dark_balance.pending,
+            computation_offset,
+            timeout,
+        )?;
+
+        let args = vec![
+            Argument::ArcisPubkey(pub_key),
+            Argument::PlaintextU128(nonce),
+            Argument::EncryptedU64(encrypted_x_amount),
+            Argument::EncryptedU64(encrypted_y_amount),
+            Argument::PlaintextU128(ctx.accounts.dark_balance.nonce),
+            Argument::Account(
+                ctx.accounts.dark_balance.key(),
+                DARK_CIPHERTEXT_OFFSET,
+                DARK_BALANCE_LEN,
+            ),
+        ];
+
+        let callback_accounts = ctx.accounts.callback_accounts();
+        queue_computation(
+            ctx.accounts,
+            computation_offset,
+            args,
+            callback_accounts,
+            None,
+        )?;
+        Ok(())
+    }
+
+    #[arcium_callback(encrypted_ix = "dark_withdraw")]
+    pub fn dark_withdraw_callback(
+        ctx: Context<DarkWithdrawCallback>,
+        output: ComputationOutputs<DarkWithdrawOutput>,
+    ) -> Result<()> {
+        if !release_dark_slot(
+            &mut ctx.accounts.dark_balance.pending,
+            &ctx.accounts.computation_account.key(),
+        ) {
+            // The slot was claimed again, the newer computation owns the state
+            emit!(DarkPoolFailedEvent {
+                config: ctx.accounts.config.key(),
+                user: Some(ctx.accounts.user.key()),
+                reason: ErrorCode::StaleComputation.to_string(),
+            });
+            return Ok(());
+        }
+
+        let (balance, x_amount, y_amount) = match output {
+            ComputationOutputs::Success(DarkWithdrawOutput {
+                field_0:
+                    DarkWithdrawTupleStruct0 {
+                        field_0: balance,
+                        field_1: x_amount,
+                        field_2: y_amount,
+                    },
+            }) => (balance, x_amount, y_amount),
+            _ => {
+                emit!(DarkPoolFailedEvent {
+                    config: ctx.accounts.config.key(),
+                    user: Some(ctx.accounts.user.key()),
+                    reason: ErrorCode::AbortedComputation.to_string(),
+                });
+                return Ok(());
+            }
+        };
+
+        ctx.accounts.dark_balance.balance = balance.ciphertexts;
+        ctx.accounts.dark_balance.nonce = balance.nonce;
+
+        // A zero payout means the balance did not cover the request
+        if x_amount == 0 && y_amount == 0 {
+            emit!(DarkPoolFailedEvent {
+                config: ctx.accounts.config.key(),
+                user: Some(ctx.accounts.user.key()),
+                reason: ErrorCode::InvalidAmount.to_string(),
+            });
+            return Ok(());
+        }
+
+        ctx.accounts.pay_out(x_amount, y_amount)?;
+
+        emit!(DarkWithdrawEvent {
+            user: ctx.accounts.user.key(),
+            config: ctx.accounts.config.key(),
+            x_amount,
+            y_amount,
+        });
+        Ok(())
+    }
+}
+
+/// Integer square root, rounded down.
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+
+    // Newton's method from a power of two at or above the root
+    let mut x = 1u128 << ((128 - n.leading_zeros() + 1) / 2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Part of a single-sided deposit of `amount` to swap so that the rest matches
+/// the pool ratio after the swap. This is the positive root of
+/// `c*s^2 + b*R*s - a*R*F = 0` with `b = 2F - fee` and `c = F - fee`, rearranged
+/// to `s = 2aF / (b + sqrt(b^2 + 4caF/R))` so it fits in u128 at a fixed point scale.
+fn zap_swap_amount(reserve: u64, amount: u64, fee: u16) -> Result<u64> {
+    const F: u128 = 10_000;
+    const SCALE: u128 = 100_000;
+
+    let (r, a) = (reserve as u128, amount as u128);
+    require!(r != 0, ErrorCode::InsufficientLiquidity);
+    let b = 2 * F - fee as u128;
+    let c = F - fee as u128;
+
+    let radicand = (b * b)
+        .checked_mul(r)
+        .and_then(|bbr| (4 * c * F).checked_mul(a)?.checked_add(bbr))
+        .and_then(|sum| sum.checked_mul(SCALE * SCALE))
+        .ok_or(ErrorCode::MathOverflow)?
+        / r;
+    let swap_amount = 2 * a * F * SCALE / (b * SCALE + isqrt(radicand));
+    u64::try_from(swap_amount).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// The protocol's cut of the pool fee charged on a swap input. The fee rounds
+/// up in the pool's favour and the cut rounds down, like in `compute_swap`.
+fn protocol_fee_amount(config: &Config, deposit_amount: u64) -> u64 {
+    let fee_amount = (deposit_amount as u128 * config.fee as u128 + 9_999) / 10_000;
+    (fee_amount * config.protocol_fee as u128 / 10_000) as u64
+}
+
+/// Fails once the caller's optional unix timestamp deadline has passed.
+fn check_deadline(deadline: Option<i64>) -> Result<()> {
+    if let Some(deadline) = deadline {
+        require!(
+            Clock::get()?.unix_timestamp <= deadline,
+            ErrorCode::DeadlineExceeded
+        );
+    }
+    Ok(())
+}
+
+/// Claims the single in-flight computation slot of a piece of dark pool state
+/// for `computation_offset`, since its callback overwrites the ciphertexts the
+/// computation read. A slot held past the pool's swap timeout is treated as
+/// abandoned, claiming it again invalidates the abandoned computation.
+fn claim_dark_slot(
+    pending: &mut Option<PendingComputation>,
+    computation_offset: u64,
+    timeout: i64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    if let Some(held) = pending {
+        require!(
+            now >= held.since.saturating_add(timeout),
+            ErrorCode::DarkStateBusy
+        );
+    }
+    *pending = Some(PendingComputation {
+        computation_offset,
+        since: now,
+    });
+    Ok(())
+}
+
+/// Releases a dark pool state slot if `computation_account` belongs to the
+/// computation that holds it. Returns false for a computation whose slot was
+/// claimed again, its callback must leave the state alone.
+fn release_dark_slot(
+    pending: &mut Option<PendingComputation>,
+    computation_account: &Pubkey,
+) -> bool {
+    match pending {
+        Some(held) if derive_comp_pda!(held.computation_offset) == *computation_account => {
+            *pending = None;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returns what is left in a swap escrow to the user and closes it, rent included.
+fn release_escrow<'info>(
+    token_program: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    spent: u64,
+    user_token: AccountInfo<'info>,
+    user: AccountInfo<'info>,
+    config: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let remaining = escrow
+        .amount
+        .checked_sub(spent)
+        .ok_or(ErrorCode::InvalidAmount)?;
+
+    // Closing a native escrow hands all of its lamports to the user, which
+    // unwraps whatever is left instead of refunding wSOL
+    if remaining > 0 && mint.key() != spl_token::native_mint::ID {
+        transfer_checked(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                TransferChecked {
+                    from: escrow.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: user_token,
+                    authority: config.clone(),
+                },
+                signer_seeds,
+            ),
+            remaining,
+            mint.decimals,
+        )?;
+    }
+
+    // Token-2022 refuses to close an account holding withheld transfer fees
+    if has_withheld_fees(&escrow.to_account_info())? {
+        harvest_withheld_tokens_to_mint(
+            CpiContext::new(
+                token_program.clone(),
+                HarvestWithheldTokensToMint {
+                    token_program_id: token_program.clone(),
+                    mint: mint.to_account_info(),
+                },
+            ),
+            vec![escrow.to_account_info()],
+        )?;
+    }
+
+    close_account(CpiContext::new_with_signer(
+        token_program,
+        CloseAccount {
+            account: escrow.to_account_info(),
+            destination: user,
+            authority: config,
+        },
+        signer_seeds,
+    ))
+}
+
+/// Tops the user's wSOL account up from their lamports so it holds at least `amount`.
+/// The program never creates user token accounts, clients create the wSOL
+/// account idempotently for a native side since `unwrap_sol` may close it.
+fn wrap_sol<'info>(
+    token_program: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    user: AccountInfo<'info>,
+    user_token: &InterfaceAccount<'info, TokenAccount>,
+    amount: u64,
+) -> Result<()> {
+    let shortfall = amount.saturating_sub(user_token.amount);
+    if shortfall == 0 {
+        return Ok(());
+    }
+
+    system_program::transfer(
+        CpiContext::new(
+            system_program,
+            system_program::Transfer {
+                from: user,
+                to: user_token.to_account_info(),
+            },
+        ),
+        shortfall,
+    )?;
+    sync_native(CpiContext::new(
+        token_program,
+        SyncNative {
+            account: user_token.to_account_info(),
+        },
+    ))
+}
+
+/// Unwraps the user's wSOL account into lamports by closing it, but only if
+/// it held no wSOL when the instruction was loaded. wSOL the user already held
+/// is never unwrapped, the output then simply stays wrapped. `user_token` must
+/// not have been reloaded since, so its amount is still the pre-balance.
+fn unwrap_sol<'info>(
+    token_program: AccountInfo<'info>,
+    user_token: &InterfaceAccount<'info, TokenAccount>,
+    user: AccountInfo<'info>,
+) -> Result<()> {
+    if user_token.amount > 0 {
+        return Ok(());
+    }
+
+    close_account(CpiContext::new(
+        token_program,
+        CloseAccount {
+            account: user_token.to_account_info(),
+            destination: user.clone(),
+            authority: user,
+        },
+    ))
+}
+
+/// Rejects Token-2022 mints with extensions the pool cannot hold safely, such
+/// as transfer hooks, permanent delegates or non-transferable tokens.
+fn check_mint_extensions(mint: &InterfaceAccount<Mint>) -> Result<()> {
+    let info = mint.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let data = info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    for extension in state.get_extension_types()? {
+        require!(
+            matches!(
+                extension,
+                ExtensionType::TransferFeeConfig
+                    | ExtensionType::MintCloseAuthority
+                    | ExtensionType::InterestBearingConfig
+                    | ExtensionType::MetadataPointer
+                    | ExtensionType::TokenMetadata
+            ),
+            ErrorCode::UnsupportedMintExtension
+        );
+    }
+    Ok(())
+}
+
+/// Whether a Token-2022 account holds transfer fees withheld from incoming transfers.
+fn has_withheld_fees(account: &AccountInfo) -> Result<bool> {
+    if *account.owner != spl_token_2022::ID {
+        return Ok(false);
+    }
+
+    let data = account.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(state
+        .get_extension::<TransferFeeAmount>()
+        .map_or(false, |fees| u64::from(fees.withheld_amount) > 0))
+}
+
+/// The current epoch's Token-2022 transfer fee of a mint, zero for SPL Token mints.
+#[derive(Clone, Copy, Default)]
+struct MintTransferFee {
+    basis_points: u16,
+    maximum_fee: u64,
+}
+
+impl MintTransferFee {
+    /// Reads the transfer fee config extension, if the mint has one.
+    fn of(mint: &InterfaceAccount<Mint>) -> Result<Self> {
+        let info = mint.to_account_info();
+        if *info.owner != spl_token_2022::ID {
+            return Ok(Self::default());
+        }
+
+        let data = info.try_borrow_data()?;
+        let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+        let Ok(config) = state.get_extension::<TransferFeeConfig>() else {
+            return Ok(Self::default());
+        };
+        let fee = config.get_epoch_fee(Clock::get()?.epoch);
+        Ok(Self {
+            basis_points: u16::from(fee.transfer_fee_basis_points),
+            maximum_fee: u64::from(fee.maximum_fee),
+        })
+    }
+
+    /// Fee withheld when `amount` is sent, rounded up like Token-2022 does.
+    fn on(&self, amount: u64) -> u64 {
+        if self.basis_points == 0 || amount == 0 {
+            return 0;
+        }
+        let fee = (amount as u128 * self.basis_points as u128 + 9_999) / 10_000;
+        fee.min(self.maximum_fee as u128) as u64
+    }
+
+    /// Amount to send so that at least `amount` arrives after the fee.
+    fn gross_up(&self, amount: u64) -> Result<u64> {
+        if self.basis_points == 0 || amount == 0 {
+            return Ok(amount);
+        }
+        let capped = amount as u128 + self.maximum_fee as u128;
+        let gross = match self.basis_points >= 10_000 {
+            true => capped,
+            false => {
+                let divisor = (10_000 - self.basis_points) as u128;
+                ((amount as u128 * 10_000 + divisor - 1) / divisor).min(capped)
+            }
+        };
+        u64::try_from(gross).map_err(|_| ErrorCode::InvalidAmount.into())
+    }
+}
+
+// ========================= STATE =========================
+
+#[account]
+pub struct Config {
+    pub seed: u64,
+    pub authority: Option<Pubkey>,
+    pub pending_authority: Option<Pubkey>,
+    pub mint_x: Pubkey,
+    pub mint_y: Pubkey,
+    pub fee: u16,
+    pub protocol_fee: u16,
+    pub locked: bool,
+    pub swap_timeout: i64,
+    // LP mint decimals, the larger of the two pool mints' decimals
+    pub lp_decimals: u8,
+    // Fixed point precision passed to the curve's LP amount math
+    pub precision: u8,
+    pub config_bump: u8,
+    pub lp_bump: u8,
+    // Set while the reserves live encrypted in the dark pool vaults
+    pub dark: bool,
+}
+
+impl Space for Config {
+    const INIT_SPACE: usize = 8 + 8 + (32 + 1) * 2 + 32 * 2 + 2 * 2 + 1 + 8 + 1 * 4 + 1;
+}
+
+#[account]
+pub struct SwapState {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub computation_offset: u64,
+    pub deposit_amount: u64,
+    pub withdraw_amount: u64,
+    pub status: SwapStatus,
+    pub created_at: i64,
+    pub deadline: Option<i64>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum SwapStatus {
+    Initiated,
+    Computing,
+    Executed,
+    Failed,
+    Rejected,
+}
+
+impl Space for SwapState {
+    const INIT_SPACE: usize = 8 + 32 + 32 + 8 + 8 + 8 + 1 + 8 + (1 + 8) + 1;
+}
+
+#[account]
+pub struct LiquidityState {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub computation_offset: u64,
+    pub is_deposit: bool,
+    pub status: LiquidityStatus,
+    pub created_at: i64,
+    pub deadline: Option<i64>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum LiquidityStatus {
+    Initiated,
+    Computing,
+    Executed,
+    Failed,
+    Rejected,
+}
+
+impl Space for LiquidityState {
+    const INIT_SPACE: usize = 8 + 32 + 32 + 8 + 1 + 1 + 8 + (1 + 8) + 1;
+}
+
+#[account]
+pub struct DarkPool {
+    pub bump: u8,
+    // Reserves and accrued protocol fees encrypted under the MXE, must stay
+    // at DARK_CIPHERTEXT_OFFSET
+    pub reserves: [[u8; 32]; 4],
+    pub nonce: u128,
+    pub config: Pubkey,
+    // Set while the encrypted reserves are the pool's canonical reserves
+    pub sealed: bool,
+    pub pending: Option<PendingComputation>,
+    // When the pool last went dark or back to public
+    pub toggled_at: i64,
+}
+
+impl Space for DarkPool {
+    const INIT_SPACE: usize = 8 + 1 + 32 * 4 + 16 + 32 + 1 + (1 + 8 + 8) + 8;
+}
+
+#[account]
+pub struct DarkBalance {
+    pub bump: u8,
+    // Balance encrypted under the MXE, must stay at DARK_CIPHERTEXT_OFFSET
+    pub balance: [[u8; 32]; 2],
+    pub nonce: u128,
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub initialized: bool,
+    pub pending: Option<PendingComputation>,
+    // Funding not credited yet, refunded if its computation aborts and
+    // carried into the next funding if its computation is abandoned
+    pub pending_x: u64,
+    pub pending_y: u64,
+}
+
+impl Space for DarkBalance {
+    const INIT_SPACE: usize = 8 + 1 + 32 * 2 + 16 + 32 + 32 + 1 + (1 + 8 + 8) + 8 + 8;
+}
+
+/// The one computation allowed to rewrite a piece of dark pool state.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct PendingComputation {
+    pub computation_offset: u64,
+    pub since: i64,
+}
+
+// ========================= AMM ACCOUNTS =========================
+
+#[derive(Accounts)]
+#[instruction(seed: u64)]
+pub struct InitializeAmm<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"lp", config.key().as_ref()],
+        bump,
+        mint::decimals = mint_x.decimals.max(mint_y.decimals),
+        mint::authority = config
+    )]
+    pub mint_lp: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = mint_lp,
+        associated_token::authority = config,
+    )]
+    pub locked_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"protocol_x", config.key().as_ref()],
+        bump,
+        token::mint = mint_x,
+        token::authority = config,
+        token::token_program = token_program_x,
+    )]
+    pub protocol_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"protocol_y", config.key().as_ref()],
+        bump,
+        token::mint = mint_y,
+        token::authority = config,
+        token::token_program = token_program_y,
+    )]
+    pub protocol_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"config", seed.to_le_bytes().as_ref()],
+        bump,
+        space = Config::INIT_SPACE,
+    )]
+    pub config: Account<'info, Config>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = config,
+    )]
+    pub locked_lp: InterfaceAccount<'info, TokenAccount>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump
+    )]
+    pub mint_lp: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user
+    )]
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"protocol_x", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"protocol_y", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Zap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"protocol_x", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"protocol_y", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimProtocolFees<'info> {
+    pub user: Signer<'info>,
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [b"protocol_x", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"protocol_y", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint_x,
+        token::token_program = token_program_x,
+    )]
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint_y,
+        token::token_program = token_program_y,
+    )]
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Update<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+}
+
+// ========================= CONFIDENTIAL SWAP ACCOUNTS =========================
+
+#[queue_computation_accounts("compute_swap", payer)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct ComputeSwap<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"protocol_x", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"protocol_y", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = user,
+        space = SwapState::INIT_SPACE,
+        seeds = [
+            b"swap_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub swap_state: Box<Account<'info, SwapState>>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"escrow_x", swap_state.key().as_ref()],
+        bump,
+        token::mint = mint_x,
+        token::authority = config,
+        token::token_program = token_program_x,
+    )]
+    pub escrow_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"escrow_y", swap_state.key().as_ref()],
+        bump,
+        token::mint = mint_y,
+        token::authority = config,
+        token::token_program = token_program_y,
+    )]
+    pub escrow_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Arcium required accounts
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut, address = derive_mempool_pda!())]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+    #[account(mut, address = derive_execpool_pda!())]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+    #[account(mut, address = derive_comp_pda!(computation_offset))]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_COMPUTE_SWAP))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(mut, address = derive_cluster_pda!(mxe_account))]
+    pub cluster_account: Box<Account<'info, Cluster>>,
+    #[account(mut, address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS)]
+    pub pool_account: Box<Account<'info, FeePool>>,
+    #[account(address = ARCIUM_CLOCK_ACCOUNT_ADDRESS)]
+    pub clock_account: Box<Account<'info, ClockAccount>>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+impl<'info> ComputeSwap<'info> {
+    /// Accounts forwarded to `compute_swap_callback`, in `ComputeSwapCallback` field order.
+    fn callback_accounts(&self) -> Vec<CallbackAccount> {
+        let account = |pubkey: Pubkey, is_writable: bool| CallbackAccount {
+            pubkey,
+            is_writable,
+        };
+        vec![
+            account(self.user.key(), true),
+            account(self.mint_x.key(), true),
+            account(self.mint_y.key(), true),
+            account(self.config.key(), false),
+            account(self.swap_state.key(), true),
+            account(self.escrow_x.key(), true),
+            account(self.escrow_y.key(), true),
+            account(self.vault_x.key(), true),
+            account(self.vault_y.key(), true),
+            account(self.protocol_x.key(), true),
+            account(self.protocol_y.key(), true),
+            account(self.user_x.key(), true),
+            account(self.user_y.key(), true),
+            account(self.token_program_x.key(), false),
+            account(self.token_program_y.key(), false),
+            account(self.associated_token_program.key(), false),
+        ]
+    }
+}
+
+#[callback_accounts("compute_swap", payer)]
+#[derive(Accounts)]
+pub struct ComputeSwapCallback<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_COMPUTE_SWAP))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    ///CHECK: swap owner, checked by has_one on swap_state
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    // Writable so withheld transfer fees can be harvested before closing escrows
+    #[account(mut)]
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [
+            b"swap_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            swap_state.computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump = swap_state.bump,
+        has_one = user,
+        has_one = config,
+    )]
+    pub swap_state: Box<Account<'info, SwapState>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_x", swap_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_y", swap_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"protocol_x", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"protocol_y", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> ComputeSwapCallback<'info> {
+    /// Settles one mint's leg of the swap: moves its input from escrow into
+    /// the pool, sends the protocol's cut on to the protocol vault and pays
+    /// its output to the user. Every transfer is made even when its amount is
+    /// zero, so both legs look the same.
+    fn settle(&self, x: bool, amount_in: u64, amount_out: u64, protocol_amount: u64) -> Result<()> {
+        let (mint, token_program, escrow, vault, protocol, user_token) = match x {
+            true => (
+                &self.mint_x,
+                &self.token_program_x,
+                &self.escrow_x,
+                &self.vault_x,
+                &self.protocol_x,
+                &self.user_x,
+            ),
+            false => (
+                &self.mint_y,
+                &self.token_program_y,
+                &self.escrow_y,
+                &self.vault_y,
+                &self.protocol_y,
+                &self.user_y,
+            ),
+        };
+
+        // The user cannot sign here, so native SOL output is paid into the
+        // escrow, which unwraps to the user when it is closed
+        let user_out = match mint.key() == spl_token::native_mint::ID {
+            true => escrow.to_account_info(),
+            false => user_token.to_account_info(),
+        };
+
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        for (from, to, amount) in [
+            (escrow.to_account_info(), vault.to_account_info(), amount_in),
+            (
+                vault.to_account_info(),
+                protocol.to_account_info(),
+                protocol_amount,
+            ),
+            (vault.to_account_info(), user_out, amount_out),
+        ] {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    TransferChecked {
+                        from,
+                        mint: mint.to_account_info(),
+                        to,
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                amount,
+                mint.decimals,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Refunds what is left in both escrows to the user and closes them.
+    fn release_escrows(&self, spent_x: u64, spent_y: u64) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        release_escrow(
+            self.token_program_x.to_account_info(),
+            &self.mint_x,
+            &self.escrow_x,
+            spent_x,
+            self.user_x.to_account_info(),
+            self.user.to_account_info(),
+            self.config.to_account_info(),
+            signer_seeds,
+        )?;
+        release_escrow(
+            self.token_program_y.to_account_info(),
+            &self.mint_y,
+            &self.escrow_y,
+            spent_y,
+            self.user_y.to_account_info(),
+            self.user.to_account_info(),
+            self.config.to_account_info(),
+            signer_seeds,
+        )
+    }
+
+    /// Refunds the whole escrow and records why the swap did not settle.
+    fn reject(&mut self, status: SwapStatus, reason: ErrorCode) -> Result<()> {
+        self.release_escrows(0, 0)?;
+        self.swap_state.status = status;
+
+        emit!(ConfidentialSwapFailedEvent {
+            user: self.user.key(),
+            config: self.config.key(),
+            computation_offset: self.swap_state.computation_offset,
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    /// The settlement must not shrink the constant product of the current reserves.
+    fn preserves_k(&self, in_x: u64, in_y: u64, out_x: u64, out_y: u64) -> bool {
+        let (reserve_x, reserve_y) = (self.vault_x.amount, self.vault_y.amount);
+        if out_x >= reserve_x || out_y >= reserve_y {
+            return false;
+        }
+
+        let k = reserve_x as u128 * reserve_y as u128;
+        (reserve_x as u128 + in_x as u128 - out_x as u128)
+            .checked_mul(reserve_y as u128 + in_y as u128 - out_y as u128)
+            .map_or(true, |new_k| new_k >= k)
+    }
+}
+
+#[init_computation_definition_accounts("compute_swap", payer)]
+#[derive(Accounts)]
+pub struct InitComputeSwapCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut)]
+    /// CHECK: comp_def_account, checked by arcium program.
+    pub comp_def_account: UncheckedAccount<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSwap<'info> {
+    pub caller: Signer<'info>,
+    ///CHECK: rent receiver, checked by has_one on swap_state
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    // Writable so withheld transfer fees can be harvested before closing escrows
+    #[account(mut)]
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            b"swap_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            swap_state.computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump = swap_state.bump,
+        has_one = user,
+        has_one = config,
+        close = user,
+    )]
+    pub swap_state: Account<'info, SwapState>,
+    #[account(
+        mut,
+        seeds = [b"escrow_x", swap_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"escrow_y", swap_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSwap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            b"swap_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            swap_state.computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump = swap_state.bump,
+        has_one = user,
+        has_one = config,
+        close = user,
+    )]
+    pub swap_state: Account<'info, SwapState>,
+}
+
+// ========================= CONFIDENTIAL LIQUIDITY ACCOUNTS =========================
+
+#[queue_computation_accounts("compute_deposit", payer)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct ComputeDeposit<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = user,
+        space = LiquidityState::INIT_SPACE,
+        seeds = [
+            b"liquidity_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub liquidity_state: Box<Account<'info, LiquidityState>>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"escrow_x", liquidity_state.key().as_ref()],
+        bump,
+        token::mint = mint_x,
+        token::authority = config,
+        token::token_program = token_program_x,
+    )]
+    pub escrow_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"escrow_y", liquidity_state.key().as_ref()],
+        bump,
+        token::mint = mint_y,
+        token::authority = config,
+        token::token_program = token_program_y,
+    )]
+    pub escrow_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Arcium required accounts
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut, address = derive_mempool_pda!())]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+    #[account(mut, address = derive_execpool_pda!())]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+    #[account(mut, address = derive_comp_pda!(computation_offset))]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_COMPUTE_DEPOSIT))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(mut, address = derive_cluster_pda!(mxe_account))]
+    pub cluster_account: Box<Account<'info, Cluster>>,
+    #[account(mut, address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS)]
+    pub pool_account: Box<Account<'info, FeePool>>,
+    #[account(address = ARCIUM_CLOCK_ACCOUNT_ADDRESS)]
+    pub clock_account: Box<Account<'info, ClockAccount>>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+impl<'info> ComputeDeposit<'info> {
+    /// Accounts forwarded to `compute_deposit_callback`, in `ComputeDepositCallback` field order.
+    fn callback_accounts(&self) -> Vec<CallbackAccount> {
+        let account = |pubkey: Pubkey, is_writable: bool| CallbackAccount {
+            pubkey,
+            is_writable,
+        };
+        vec![
+            account(self.user.key(), true),
+            account(self.mint_x.key(), true),
+            account(self.mint_y.key(), true),
+            account(self.mint_lp.key(), true),
+            account(self.config.key(), false),
+            account(self.liquidity_state.key(), true),
+            account(self.escrow_x.key(), true),
+            account(self.escrow_y.key(), true),
+            account(self.vault_x.key(), true),
+            account(self.vault_y.key(), true),
+            account(self.user_x.key(), true),
+            account(self.user_y.key(), true),
+            account(self.user_lp.key(), true),
+            account(self.token_program_x.key(), false),
+            account(self.token_program_y.key(), false),
+            account(self.token_program.key(), false),
+        ]
+    }
+}
+
+#[callback_accounts("compute_deposit", payer)]
+#[derive(Accounts)]
+pub struct ComputeDepositCallback<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_COMPUTE_DEPOSIT))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    ///CHECK: LP owner, checked by has_one on liquidity_state
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    // Writable so withheld transfer fees can be harvested before closing escrows
+    #[account(mut)]
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [
+            b"liquidity_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            liquidity_state.computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump = liquidity_state.bump,
+        has_one = user,
+        has_one = config,
+    )]
+    pub liquidity_state: Box<Account<'info, LiquidityState>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_x", liquidity_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_y", liquidity_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ComputeDepositCallback<'info> {
+    /// Moves the deposit from escrow into the vaults and mints the LP.
+    fn settle(&self, lp_amount: u64, x_amount: u64, y_amount: u64) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program_x.to_account_info(),
+                TransferChecked {
+                    from: self.escrow_x.to_account_info(),
+                    mint: self.mint_x.to_account_info(),
+                    to: self.vault_x.to_account_info(),
+                    authority: self.config.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            x_amount,
+            self.mint_x.decimals,
+        )?;
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program_y.to_account_info(),
+                TransferChecked {
+                    from: self.escrow_y.to_account_info(),
+                    mint: self.mint_y.to_account_info(),
+                    to: self.vault_y.to_account_info(),
+                    authority: self.config.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            y_amount,
+            self.mint_y.decimals,
+        )?;
+
+        mint_to(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                MintTo {
+                    mint: self.mint_lp.to_account_info(),
+                    to: self.user_lp.to_account_info(),
+                    authority: self.config.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            lp_amount,
+        )
+    }
+
+    /// Refunds what is left in both escrows to the user and closes them.
+    fn release_escrows(&self, spent_x: u64, spent_y: u64) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        release_escrow(
+            self.token_program_x.to_account_info(),
+            &self.mint_x,
+            &self.escrow_x,
+            spent_x,
+            self.user_x.to_account_info(),
+            self.user.to_account_info(),
+            self.config.to_account_info(),
+            signer_seeds,
+        )?;
+        release_escrow(
+            self.token_program_y.to_account_info(),
+            &self.mint_y,
+            &self.escrow_y,
+            spent_y,
+            self.user_y.to_account_info(),
+            self.user.to_account_info(),
+            self.config.to_account_info(),
+            signer_seeds,
+        )
+    }
+
+    /// Refunds the whole escrow and records why the deposit did not settle.
+    fn reject(&mut self, status: LiquidityStatus, reason: ErrorCode) -> Result<()> {
+        self.release_escrows(0, 0)?;
+        self.liquidity_state.status = status;
+
+        emit!(ConfidentialLiquidityFailedEvent {
+            user: self.user.key(),
+            config: self.config.key(),
+            computation_offset: self.liquidity_state.computation_offset,
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    /// What reaches the vaults must still pay for the LP at the current reserves.
+    fn covers_lp(&self, lp_amount: u64, x_amount: u64, y_amount: u64) -> Result<bool> {
+        let received_x = x_amount - MintTransferFee::of(&self.mint_x)?.on(x_amount);
+        let received_y = y_amount - MintTransferFee::of(&self.mint_y)?.on(y_amount);
+        let supply = self.mint_lp.supply as u128;
+        Ok(
+            received_x as u128 * supply >= lp_amount as u128 * self.vault_x.amount as u128
+                && received_y as u128 * supply >= lp_amount as u128 * self.vault_y.amount as u128,
+        )
+    }
+}
+
+#[init_computation_definition_accounts("compute_deposit", payer)]
+#[derive(Accounts)]
+pub struct InitComputeDepositCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut)]
+    /// CHECK: comp_def_account, checked by arcium program.
+    pub comp_def_account: UncheckedAccount<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+#[queue_computation_accounts("compute_withdraw", payer)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct ComputeWithdraw<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = user,
+        space = LiquidityState::INIT_SPACE,
+        seeds = [
+            b"liquidity_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub liquidity_state: Box<Account<'info, LiquidityState>>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"escrow_lp", liquidity_state.key().as_ref()],
+        bump,
+        token::mint = mint_lp,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub escrow_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Arcium required accounts
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut, address = derive_mempool_pda!())]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+    #[account(mut, address = derive_execpool_pda!())]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+    #[account(mut, address = derive_comp_pda!(computation_offset))]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_COMPUTE_WITHDRAW))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(mut, address = derive_cluster_pda!(mxe_account))]
+    pub cluster_account: Box<Account<'info, Cluster>>,
+    #[account(mut, address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS)]
+    pub pool_account: Box<Account<'info, FeePool>>,
+    #[account(address = ARCIUM_CLOCK_ACCOUNT_ADDRESS)]
+    pub clock_account: Box<Account<'info, ClockAccount>>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+impl<'info> ComputeWithdraw<'info> {
+    /// Accounts forwarded to `compute_withdraw_callback`, in `ComputeWithdrawCallback` field order.
+    fn callback_accounts(&self) -> Vec<CallbackAccount> {
+        let account = |pubkey: Pubkey, is_writable: bool| CallbackAccount {
+            pubkey,
+            is_writable,
+        };
+        vec![
+            account(self.user.key(), true),
+            account(self.mint_x.key(), false),
+            account(self.mint_y.key(), false),
+            account(self.mint_lp.key(), true),
+            account(self.config.key(), false),
+            account(self.liquidity_state.key(), true),
+            account(self.escrow_lp.key(), true),
+            account(self.vault_x.key(), true),
+            account(self.vault_y.key(), true),
+            account(self.user_x.key(), true),
+            account(self.user_y.key(), true),
+            account(self.user_lp.key(), true),
+            account(self.token_program_x.key(), false),
+            account(self.token_program_y.key(), false),
+            account(self.token_program.key(), false),
+        ]
+    }
+}
+
+#[callback_accounts("compute_withdraw", payer)]
+#[derive(Accounts)]
+pub struct ComputeWithdrawCallback<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_COMPUTE_WITHDRAW))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    ///CHECK: LP owner, checked by has_one on liquidity_state
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [
+            b"liquidity_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            liquidity_state.computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump = liquidity_state.bump,
+        has_one = user,
+        has_one = config,
+    )]
+    pub liquidity_state: Box<Account<'info, LiquidityState>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_lp", liquidity_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ComputeWithdrawCallback<'info> {
+    /// Burns the LP from escrow and pays both sides out of the vaults.
+    fn settle(&self, lp_amount: u64, x_amount: u64, y_amount: u64) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        burn(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                Burn {
+                    mint: self.mint_lp.to_account_info(),
+                    from: self.escrow_lp.to_account_info(),
+                    authority: self.config.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            lp_amount,
+        )?;
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program_x.to_account_info(),
+                TransferChecked {
+                    from: self.vault_x.to_account_info(),
+                    mint: self.mint_x.to_account_info(),
+                    to: self.user_x.to_account_info(),
+                    authority: self.config.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            x_amount,
+            self.mint_x.decimals,
+        )?;
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program_y.to_account_info(),
+                TransferChecked {
+                    from: self.vault_y.to_account_info(),
+                    mint: self.mint_y.to_account_info(),
+                    to: self.user_y.to_account_info(),
+                    authority: self.config.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            y_amount,
+            self.mint_y.decimals,
+        )
+    }
+
+    /// Refunds the unburnt LP to the user and closes the escrow.
+    fn release_lp_escrow(&self, spent: u64) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        release_escrow(
+            self.token_program.to_account_info(),
+            &self.mint_lp,
+            &self.escrow_lp,
+            spent,
+            self.user_lp.to_account_info(),
+            self.user.to_account_info(),
+            self.config.to_account_info(),
+            signer_seeds,
+        )
+    }
+
+    /// Refunds the whole escrow and records why the withdrawal did not settle.
+    fn reject(&mut self, status: LiquidityStatus, reason: ErrorCode) -> Result<()> {
+        self.release_lp_escrow(0)?;
+        self.liquidity_state.status = status;
+
+        emit!(ConfidentialLiquidityFailedEvent {
+            user: self.user.key(),
+            config: self.config.key(),
+            computation_offset: self.liquidity_state.computation_offset,
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    /// The payout must not exceed the LP's share of the current reserves.
+    fn within_share(&self, lp_amount: u64, x_amount: u64, y_amount: u64) -> bool {
+        let supply = self.mint_lp.supply as u128;
+        x_amount as u128 * supply <= lp_amount as u128 * self.vault_x.amount as u128
+            && y_amount as u128 * supply <= lp_amount as u128 * self.vault_y.amount as u128
+    }
+}
+
+#[init_computation_definition_accounts("compute_withdraw", payer)]
+#[derive(Accounts)]
+pub struct InitComputeWithdrawCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut)]
+    /// CHECK: comp_def_account, checked by arcium program.
+    pub comp_def_account: UncheckedAccount<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelDeposit<'info> {
+    pub caller: Signer<'info>,
+    ///CHECK: rent receiver, checked by has_one on liquidity_state
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    // Writable so withheld transfer fees can be harvested before closing escrows
+    #[account(mut)]
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            b"liquidity_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            liquidity_state.computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump = liquidity_state.bump,
+        has_one = user,
+        has_one = config,
+        constraint = liquidity_state.is_deposit @ ErrorCode::InvalidLiquidityStatus,
+        close = user,
+    )]
+    pub liquidity_state: Account<'info, LiquidityState>,
+    #[account(
+        mut,
+        seeds = [b"escrow_x", liquidity_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"escrow_y", liquidity_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelWithdraw<'info> {
+    pub caller: Signer<'info>,
+    ///CHECK: rent receiver, checked by has_one on liquidity_state
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [
+            b"liquidity_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            liquidity_state.computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump = liquidity_state.bump,
+        has_one = user,
+        has_one = config,
+        constraint = !liquidity_state.is_deposit @ ErrorCode::InvalidLiquidityStatus,
+        close = user,
+    )]
+    pub liquidity_state: Account<'info, LiquidityState>,
+    #[account(
+        mut,
+        seeds = [b"escrow_lp", liquidity_state.key().as_ref()],
+        bump,
+    )]
+    pub escrow_lp: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            b"liquidity_state",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            liquidity_state.computation_offset.to_le_bytes().as_ref(),
+        ],
+        bump = liquidity_state.bump,
+        has_one = user,
+        has_one = config,
+        close = user,
+    )]
+    pub liquidity_state: Account<'info, LiquidityState>,
+}
+
+// ========================= DARK POOL ACCOUNTS =========================
+
+#[queue_computation_accounts("seal_reserves", payer)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct EnableDarkPool<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = DarkPool::INIT_SPACE,
+        seeds = [b"dark_pool", config.key().as_ref()],
+        bump
+    )]
+    pub dark_pool: Box<Account<'info, DarkPool>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [b"dark_x", config.key().as_ref()],
+        bump,
+        token::mint = mint_x,
+        token::authority = config,
+        token::token_program = token_program_x,
+    )]
+    pub dark_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [b"dark_y", config.key().as_ref()],
+        bump,
+        token::mint = mint_y,
+        token::authority = config,
+        token::token_program = token_program_y,
+    )]
+    pub dark_y: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Arcium required accounts
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut, address = derive_mempool_pda!())]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+    #[account(mut, address = derive_execpool_pda!())]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+    #[account(mut, address = derive_comp_pda!(computation_offset))]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_SEAL_RESERVES))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(mut, address = derive_cluster_pda!(mxe_account))]
+    pub cluster_account: Box<Account<'info, Cluster>>,
+    #[account(mut, address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS)]
+    pub pool_account: Box<Account<'info, FeePool>>,
+    #[account(address = ARCIUM_CLOCK_ACCOUNT_ADDRESS)]
+    pub clock_account: Box<Account<'info, ClockAccount>>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+impl<'info> EnableDarkPool<'info> {
+    /// Accounts forwarded to `seal_reserves_callback`, in `SealReservesCallback` field order.
+    fn callback_accounts(&self) -> Vec<CallbackAccount> {
+        let account = |pubkey: Pubkey, is_writable: bool| CallbackAccount {
+            pubkey,
+            is_writable,
+        };
+        vec![
+            account(self.mint_x.key(), false),
+            account(self.mint_y.key(), false),
+            account(self.config.key(), true),
+            account(self.dark_pool.key(), true),
+            account(self.vault_x.key(), true),
+            account(self.vault_y.key(), true),
+            account(self.dark_x.key(), true),
+            account(self.dark_y.key(), true),
+            account(self.token_program_x.key(), false),
+            account(self.token_program_y.key(), false),
+            account(self.computation_account.key(), false),
+        ]
+    }
+}
+
+#[callback_accounts("seal_reserves", payer)]
+#[derive(Accounts)]
+pub struct SealReservesCallback<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_SEAL_RESERVES))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"dark_pool", config.key().as_ref()],
+        bump = dark_pool.bump,
+    )]
+    pub dark_pool: Box<Account<'info, DarkPool>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_x,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_y,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"dark_x", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"dark_y", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    /// CHECK: computation_account, matched against the dark state's pending
+    /// computation by the callback
+    pub computation_account: UncheckedAccount<'info>,
+}
+
+impl<'info> SealReservesCallback<'info> {
+    /// Empties the public vaults into the aggregated dark vaults.
+    fn move_reserves(&self) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        if self.vault_x.amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program_x.to_account_info(),
+                    TransferChecked {
+                        from: self.vault_x.to_account_info(),
+                        mint: self.mint_x.to_account_info(),
+                        to: self.dark_x.to_account_info(),
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                self.vault_x.amount,
+                self.mint_x.decimals,
+            )?;
+        }
+
+        if self.vault_y.amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program_y.to_account_info(),
+                    TransferChecked {
+                        from: self.vault_y.to_account_info(),
+                        mint: self.mint_y.to_account_info(),
+                        to: self.dark_y.to_account_info(),
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                self.vault_y.amount,
+                self.mint_y.decimals,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[init_computation_definition_accounts("seal_reserves", payer)]
+#[derive(Accounts)]
+pub struct InitSealReservesCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut)]
+    /// CHECK: comp_def_account, checked by arcium program.
+    pub comp_def_account: UncheckedAccount<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelEnableDarkPool<'info> {
+    pub caller: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"dark_pool", config.key().as_ref()],
+        bump = dark_pool.bump,
+        has_one = config,
+    )]
+    pub dark_pool: Box<Account<'info, DarkPool>>,
+}
+
+#[queue_computation_accounts("reveal_reserves", payer)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct DisableDarkPool<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"dark_pool", config.key().as_ref()],
+        bump = dark_pool.bump,
+    )]
+    pub dark_pool: Box<Account<'info, DarkPool>>,
+    #[account(
+        associated_token::mint = mint_x,
         associated_token::authority = config,
+        associated_token::token_program = token_program_x,
     )]
-    pub vault_x: Account<'info, TokenAccount>,
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
-        init,
-        payer = admin,
         associated_token::mint = mint_y,
         associated_token::authority = config,
+        associated_token::token_program = token_program_y,
     )]
-    pub vault_y: Account<'info, TokenAccount>,
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
-        init,
-        payer = admin,
-        seeds = [b"config", seed.to_le_bytes().as_ref()],
+        seeds = [b"dark_x", config.key().as_ref()],
         bump,
-        space = Config::INIT_SPACE,
     )]
-    pub config: Account<'info, Config>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub dark_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"dark_y", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"protocol_x", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"protocol_y", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_y: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Arcium required accounts
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut, address = derive_mempool_pda!())]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+    #[account(mut, address = derive_execpool_pda!())]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+    #[account(mut, address = derive_comp_pda!(computation_offset))]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_REVEAL_RESERVES))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(mut, address = derive_cluster_pda!(mxe_account))]
+    pub cluster_account: Box<Account<'info, Cluster>>,
+    #[account(mut, address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS)]
+    pub pool_account: Box<Account<'info, FeePool>>,
+    #[account(address = ARCIUM_CLOCK_ACCOUNT_ADDRESS)]
+    pub clock_account: Box<Account<'info, ClockAccount>>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+impl<'info> DisableDarkPool<'info> {
+    /// Accounts forwarded to `reveal_reserves_callback`, in `RevealReservesCallback` field order.
+    fn callback_accounts(&self) -> Vec<CallbackAccount> {
+        let account = |pubkey: Pubkey, is_writable: bool| CallbackAccount {
+            pubkey,
+            is_writable,
+        };
+        vec![
+            account(self.mint_x.key(), false),
+            account(self.mint_y.key(), false),
+            account(self.config.key(), true),
+            account(self.dark_pool.key(), true),
+            account(self.vault_x.key(), true),
+            account(self.vault_y.key(), true),
+            account(self.dark_x.key(), true),
+            account(self.dark_y.key(), true),
+            account(self.protocol_x.key(), true),
+            account(self.protocol_y.key(), true),
+            account(self.token_program_x.key(), false),
+            account(self.token_program_y.key(), false),
+            account(self.computation_account.key(), false),
+        ]
+    }
 }
 
+#[callback_accounts("reveal_reserves", payer)]
 #[derive(Accounts)]
-pub struct Deposit<'info> {
+pub struct RevealReservesCallback<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-    pub mint_y: Account<'info, Mint>,
+    pub payer: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_REVEAL_RESERVES))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
     #[account(
+        mut,
         seeds = [b"config", config.seed.to_le_bytes().as_ref()],
         bump = config.config_bump,
         has_one = mint_x,
         has_one = mint_y,
     )]
-    pub config: Account<'info, Config>,
+    pub config: Box<Account<'info, Config>>,
     #[account(
         mut,
-        seeds = [b"lp", config.key().as_ref()],
-        bump = config.lp_bump,
+        seeds = [b"dark_pool", config.key().as_ref()],
+        bump = dark_pool.bump,
     )]
-    pub mint_lp: Account<'info, Mint>,
+    pub dark_pool: Box<Account<'info, DarkPool>>,
     #[account(
         mut,
         associated_token::mint = mint_x,
         associated_token::authority = config,
+        associated_token::token_program = token_program_x,
     )]
-    pub vault_x: Account<'info, TokenAccount>,
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         associated_token::mint = mint_y,
         associated_token::authority = config,
+        associated_token::token_program = token_program_y,
     )]
-    pub vault_y: Account<'info, TokenAccount>,
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
-        associated_token::mint = mint_x,
-        associated_token::authority = user,
+        seeds = [b"dark_x", config.key().as_ref()],
+        bump,
     )]
-    pub user_x: Account<'info, TokenAccount>,
+    pub dark_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
-        associated_token::mint = mint_y,
+        seeds = [b"dark_y", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"protocol_x", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"protocol_y", config.key().as_ref()],
+        bump,
+    )]
+    pub protocol_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    /// CHECK: computation_account, matched against the dark state's pending
+    /// computation by the callback
+    pub computation_account: UncheckedAccount<'info>,
+}
+
+impl<'info> RevealReservesCallback<'info> {
+    /// Moves revealed amounts out of the aggregated dark vaults, to the public
+    /// vaults for the reserves and to the protocol vaults for accrued fees.
+    fn move_out(
+        &self,
+        to_x: AccountInfo<'info>,
+        to_y: AccountInfo<'info>,
+        x_amount: u64,
+        y_amount: u64,
+    ) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        if x_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program_x.to_account_info(),
+                    TransferChecked {
+                        from: self.dark_x.to_account_info(),
+                        mint: self.mint_x.to_account_info(),
+                        to: to_x,
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                x_amount,
+                self.mint_x.decimals,
+            )?;
+        }
+
+        if y_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program_y.to_account_info(),
+                    TransferChecked {
+                        from: self.dark_y.to_account_info(),
+                        mint: self.mint_y.to_account_info(),
+                        to: to_y,
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                y_amount,
+                self.mint_y.decimals,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[init_computation_definition_accounts("reveal_reserves", payer)]
+#[derive(Accounts)]
+pub struct InitRevealReservesCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut)]
+    /// CHECK: comp_def_account, checked by arcium program.
+    pub comp_def_account: UncheckedAccount<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+#[queue_computation_accounts("dark_fund", payer)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct DarkFund<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+        has_one = mint_x,
+        has_one = mint_y,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"dark_pool", config.key().as_ref()],
+        bump = dark_pool.bump,
+    )]
+    pub dark_pool: Box<Account<'info, DarkPool>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = DarkBalance::INIT_SPACE,
+        seeds = [b"dark_balance", config.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub dark_balance: Box<Account<'info, DarkBalance>>,
+    #[account(
+        mut,
+        seeds = [b"dark_x", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"dark_y", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
         associated_token::authority = user,
+        associated_token::token_program = token_program_x,
     )]
-    pub user_y: Account<'info, TokenAccount>,
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
-        associated_token::mint = mint_lp,
+        associated_token::mint = mint_y,
         associated_token::authority = user,
+        associated_token::token_program = token_program_y,
     )]
-    pub user_lp: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Arcium required accounts
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut, address = derive_mempool_pda!())]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+    #[account(mut, address = derive_execpool_pda!())]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+    #[account(mut, address = derive_comp_pda!(computation_offset))]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DARK_FUND))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(mut, address = derive_cluster_pda!(mxe_account))]
+    pub cluster_account: Box<Account<'info, Cluster>>,
+    #[account(mut, address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS)]
+    pub pool_account: Box<Account<'info, FeePool>>,
+    #[account(address = ARCIUM_CLOCK_ACCOUNT_ADDRESS)]
+    pub clock_account: Box<Account<'info, ClockAccount>>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+impl<'info> DarkFund<'info> {
+    /// Accounts forwarded to `dark_fund_callback`, in `DarkFundCallback` field order.
+    fn callback_accounts(&self) -> Vec<CallbackAccount> {
+        let account = |pubkey: Pubkey, is_writable: bool| CallbackAccount {
+            pubkey,
+            is_writable,
+        };
+        vec![
+            account(self.user.key(), false),
+            account(self.mint_x.key(), false),
+            account(self.mint_y.key(), false),
+            account(self.config.key(), false),
+            account(self.dark_balance.key(), true),
+            account(self.dark_x.key(), true),
+            account(self.dark_y.key(), true),
+            account(self.user_x.key(), true),
+            account(self.user_y.key(), true),
+            account(self.token_program_x.key(), false),
+            account(self.token_program_y.key(), false),
+            account(self.computation_account.key(), false),
+        ]
+    }
 }
 
+#[callback_accounts("dark_fund", payer)]
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
+pub struct DarkFundCallback<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-    pub mint_y: Account<'info, Mint>,
+    pub payer: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DARK_FUND))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    ///CHECK: balance owner, checked by has_one on dark_balance
+    pub user: AccountInfo<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
     #[account(
         seeds = [b"config", config.seed.to_le_bytes().as_ref()],
         bump = config.config_bump,
         has_one = mint_x,
-        has_one = mint_y
+        has_one = mint_y,
     )]
-    pub config: Account<'info, Config>,
+    pub config: Box<Account<'info, Config>>,
     #[account(
         mut,
-        seeds = [b"lp", config.key().as_ref()],
-        bump = config.lp_bump
+        seeds = [b"dark_balance", config.key().as_ref(), user.key().as_ref()],
+        bump = dark_balance.bump,
+        has_one = user,
+        has_one = config,
+    )]
+    pub dark_balance: Box<Account<'info, DarkBalance>>,
+    #[account(
+        mut,
+        seeds = [b"dark_x", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"dark_y", config.key().as_ref()],
+        bump,
     )]
-    pub mint_lp: Account<'info, Mint>,
+    pub dark_y: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         associated_token::mint = mint_x,
-        associated_token::authority = config
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
     )]
-    pub vault_x: Account<'info, TokenAccount>,
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         associated_token::mint = mint_y,
-        associated_token::authority = config
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
     )]
-    pub vault_y: Account<'info, TokenAccount>,
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    /// CHECK: computation_account, matched against the dark state's pending
+    /// computation by the callback
+    pub computation_account: UncheckedAccount<'info>,
+}
+
+impl<'info> DarkFundCallback<'info> {
+    /// Pays the user out of the aggregated dark vaults.
+    fn pay_out(&self, x_amount: u64, y_amount: u64) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        if x_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program_x.to_account_info(),
+                    TransferChecked {
+                        from: self.dark_x.to_account_info(),
+                        mint: self.mint_x.to_account_info(),
+                        to: self.user_x.to_account_info(),
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                x_amount,
+                self.mint_x.decimals,
+            )?;
+        }
+
+        if y_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program_y.to_account_info(),
+                    TransferChecked {
+                        from: self.dark_y.to_account_info(),
+                        mint: self.mint_y.to_account_info(),
+                        to: self.user_y.to_account_info(),
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                y_amount,
+                self.mint_y.decimals,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[init_computation_definition_accounts("dark_fund", payer)]
+#[derive(Accounts)]
+pub struct InitDarkFundCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut)]
+    /// CHECK: comp_def_account, checked by arcium program.
+    pub comp_def_account: UncheckedAccount<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+#[queue_computation_accounts("dark_swap", payer)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct DarkSwap<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"dark_pool", config.key().as_ref()],
+        bump = dark_pool.bump,
+    )]
+    pub dark_pool: Box<Account<'info, DarkPool>>,
+    #[account(
+        mut,
+        seeds = [b"dark_balance", config.key().as_ref(), user.key().as_ref()],
+        bump = dark_balance.bump,
+        has_one = user,
+        has_one = config,
+    )]
+    pub dark_balance: Box<Account<'info, DarkBalance>>,
+
+    // Arcium required accounts
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut, address = derive_mempool_pda!())]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+    #[account(mut, address = derive_execpool_pda!())]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+    #[account(mut, address = derive_comp_pda!(computation_offset))]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DARK_SWAP))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(mut, address = derive_cluster_pda!(mxe_account))]
+    pub cluster_account: Box<Account<'info, Cluster>>,
+    #[account(mut, address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS)]
+    pub pool_account: Box<Account<'info, FeePool>>,
+    #[account(address = ARCIUM_CLOCK_ACCOUNT_ADDRESS)]
+    pub clock_account: Box<Account<'info, ClockAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+impl<'info> DarkSwap<'info> {
+    /// Accounts forwarded to `dark_swap_callback`, in `DarkSwapCallback` field order.
+    fn callback_accounts(&self) -> Vec<CallbackAccount> {
+        let account = |pubkey: Pubkey, is_writable: bool| CallbackAccount {
+            pubkey,
+            is_writable,
+        };
+        vec![
+            account(self.user.key(), false),
+            account(self.config.key(), false),
+            account(self.dark_pool.key(), true),
+            account(self.dark_balance.key(), true),
+            account(self.computation_account.key(), false),
+        ]
+    }
+}
+
+#[callback_accounts("dark_swap", payer)]
+#[derive(Accounts)]
+pub struct DarkSwapCallback<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DARK_SWAP))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    ///CHECK: balance owner, checked by has_one on dark_balance
+    pub user: AccountInfo<'info>,
     #[account(
-        mut,
-        associated_token::mint = mint_x,
-        associated_token::authority = user
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
     )]
-    pub user_x: Account<'info, TokenAccount>,
+    pub config: Box<Account<'info, Config>>,
     #[account(
         mut,
-        associated_token::mint = mint_y,
-        associated_token::authority = user
+        seeds = [b"dark_pool", config.key().as_ref()],
+        bump = dark_pool.bump,
     )]
-    pub user_y: Account<'info, TokenAccount>,
+    pub dark_pool: Box<Account<'info, DarkPool>>,
     #[account(
         mut,
-        associated_token::mint = mint_lp,
-        associated_token::authority = user
+        seeds = [b"dark_balance", config.key().as_ref(), user.key().as_ref()],
+        bump = dark_balance.bump,
+        has_one = user,
+        has_one = config,
     )]
-    pub user_lp: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
+    pub dark_balance: Box<Account<'info, DarkBalance>>,
+    /// CHECK: computation_account, matched against the dark state's pending
+    /// computation by the callback
+    pub computation_account: UncheckedAccount<'info>,
 }
 
+#[init_computation_definition_accounts("dark_swap", payer)]
 #[derive(Accounts)]
-pub struct Update<'info> {
+pub struct InitDarkSwapCompDef<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
-        bump = config.config_bump
-    )]
-    pub config: Account<'info, Config>,
+    pub payer: Signer<'info>,
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut)]
+    /// CHECK: comp_def_account, checked by arcium program.
+    pub comp_def_account: UncheckedAccount<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
 }
 
-// ========================= CONFIDENTIAL SWAP ACCOUNTS =========================
-
-#[queue_computation_accounts("compute_swap", payer)]
+#[queue_computation_accounts("dark_withdraw", payer)]
 #[derive(Accounts)]
 #[instruction(computation_offset: u64)]
-pub struct ComputeSwap<'info> {
+pub struct DarkWithdraw<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
     #[account(mut)]
     pub user: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-    pub mint_y: Account<'info, Mint>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
     #[account(
         seeds = [b"config", config.seed.to_le_bytes().as_ref()],
         bump = config.config_bump,
@@ -656,42 +5449,36 @@ pub struct ComputeSwap<'info> {
     pub config: Box<Account<'info, Config>>,
     #[account(
         mut,
-        seeds = [b"lp", config.key().as_ref()],
-        bump = config.lp_bump,
-    )]
-    pub mint_lp: Box<Account<'info, Mint>>,
-    #[account(
-        mut,
-       associated_token::mint = mint_x,
-       associated_token::authority = config,
+        seeds = [b"dark_balance", config.key().as_ref(), user.key().as_ref()],
+        bump = dark_balance.bump,
+        has_one = user,
+        has_one = config,
     )]
-    pub vault_x: Box<Account<'info, TokenAccount>>,
+    pub dark_balance: Box<Account<'info, DarkBalance>>,
     #[account(
-        mut,
-       associated_token::mint = mint_y,
-       associated_token::authority = config,
+        seeds = [b"dark_x", config.key().as_ref()],
+        bump,
     )]
-    pub vault_y: Box<Account<'info, TokenAccount>>,
+    pub dark_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
-        init,
-        payer = user,
-        space = SwapState::INIT_SPACE,
-        seeds = [b"swap_state"],
-        bump
+        seeds = [b"dark_y", config.key().as_ref()],
+        bump,
     )]
-    pub swap_state: Box<Account<'info, SwapState>>,
+    pub dark_y: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         associated_token::mint = mint_x,
         associated_token::authority = user,
+        associated_token::token_program = token_program_x,
     )]
-    pub user_x: Box<Account<'info, TokenAccount>>,
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         associated_token::mint = mint_y,
         associated_token::authority = user,
+        associated_token::token_program = token_program_y,
     )]
-    pub user_y: Box<Account<'info, TokenAccount>>,
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
 
     // Arcium required accounts
     #[account(address = derive_mxe_pda!())]
@@ -705,7 +5492,7 @@ pub struct ComputeSwap<'info> {
     #[account(mut, address = derive_comp_pda!(computation_offset))]
     /// CHECK: computation_account, checked by the arcium program.
     pub computation_account: UncheckedAccount<'info>,
-    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_COMPUTE_SWAP))]
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DARK_WITHDRAW))]
     pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
     #[account(mut, address = derive_cluster_pda!(mxe_account))]
     pub cluster_account: Box<Account<'info, Cluster>>,
@@ -714,63 +5501,150 @@ pub struct ComputeSwap<'info> {
     #[account(address = ARCIUM_CLOCK_ACCOUNT_ADDRESS)]
     pub clock_account: Box<Account<'info, ClockAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
     pub arcium_program: Program<'info, Arcium>,
 }
 
-#[callback_accounts("compute_swap", payer)]
+impl<'info> DarkWithdraw<'info> {
+    /// Accounts forwarded to `dark_withdraw_callback`, in `DarkWithdrawCallback` field order.
+    fn callback_accounts(&self) -> Vec<CallbackAccount> {
+        let account = |pubkey: Pubkey, is_writable: bool| CallbackAccount {
+            pubkey,
+            is_writable,
+        };
+        vec![
+            account(self.user.key(), false),
+            account(self.mint_x.key(), false),
+            account(self.mint_y.key(), false),
+            account(self.config.key(), false),
+            account(self.dark_balance.key(), true),
+            account(self.dark_x.key(), true),
+            account(self.dark_y.key(), true),
+            account(self.user_x.key(), true),
+            account(self.user_y.key(), true),
+            account(self.token_program_x.key(), false),
+            account(self.token_program_y.key(), false),
+            account(self.computation_account.key(), false),
+        ]
+    }
+}
+
+#[callback_accounts("dark_withdraw", payer)]
 #[derive(Accounts)]
-pub struct ComputeSwapCallback<'info> {
+pub struct DarkWithdrawCallback<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
     pub arcium_program: Program<'info, Arcium>,
-    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_COMPUTE_SWAP))]
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DARK_WITHDRAW))]
     pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
     #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
     /// CHECK: instructions_sysvar, checked by the account constraint
     pub instructions_sysvar: AccountInfo<'info>,
-    ///CHECK:doc
-    #[account(mut)]
+    ///CHECK: balance owner, checked by has_one on dark_balance
     pub user: AccountInfo<'info>,
-    pub mint_x: Account<'info, Mint>,
-
-    pub mint_y: Account<'info, Mint>,
-    #[account(
-        mut,
-        seeds = [b"lp", config.key().as_ref()],
-        bump = config.lp_bump,
-    )]
-    pub mint_lp: Account<'info, Mint>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
     #[account(
         seeds = [b"config", config.seed.to_le_bytes().as_ref()],
         bump = config.config_bump,
         has_one = mint_x,
         has_one = mint_y,
     )]
-    pub config: Account<'info, Config>,
+    pub config: Box<Account<'info, Config>>,
     #[account(
         mut,
-        seeds = [b"swap_state"],
-        bump
+        seeds = [b"dark_balance", config.key().as_ref(), user.key().as_ref()],
+        bump = dark_balance.bump,
+        has_one = user,
+        has_one = config,
     )]
-    pub swap_state: Account<'info, SwapState>,
-    #[account(mut)]
-    pub vault_x: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub vault_y: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_x: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_y: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub dark_balance: Box<Account<'info, DarkBalance>>,
+    #[account(
+        mut,
+        seeds = [b"dark_x", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"dark_y", config.key().as_ref()],
+        bump,
+    )]
+    pub dark_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    /// CHECK: computation_account, matched against the dark state's pending
+    /// computation by the callback
+    pub computation_account: UncheckedAccount<'info>,
 }
 
-#[init_computation_definition_accounts("compute_swap", payer)]
+impl<'info> DarkWithdrawCallback<'info> {
+    /// Pays the user out of the aggregated dark vaults.
+    fn pay_out(&self, x_amount: u64, y_amount: u64) -> Result<()> {
+        let seeds = &[
+            &b"config"[..],
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        if x_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program_x.to_account_info(),
+                    TransferChecked {
+                        from: self.dark_x.to_account_info(),
+                        mint: self.mint_x.to_account_info(),
+                        to: self.user_x.to_account_info(),
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                x_amount,
+                self.mint_x.decimals,
+            )?;
+        }
+
+        if y_amount > 0 {
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program_y.to_account_info(),
+                    TransferChecked {
+                        from: self.dark_y.to_account_info(),
+                        mint: self.mint_y.to_account_info(),
+                        to: self.user_y.to_account_info(),
+                        authority: self.config.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                y_amount,
+                self.mint_y.decimals,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[init_computation_definition_accounts("dark_withdraw", payer)]
 #[derive(Accounts)]
-pub struct InitComputeSwapCompDef<'info> {
+pub struct InitDarkWithdrawCompDef<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
     #[account(mut, address = derive_mxe_pda!())]
@@ -782,45 +5656,6 @@ pub struct InitComputeSwapCompDef<'info> {
     pub system_program: Program<'info, System>,
 }
 
-#[derive(Accounts)]
-pub struct ExecuteSwap<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-
-    pub mint_y: Account<'info, Mint>,
-    #[account(
-        mut,
-        seeds = [b"lp", config.key().as_ref()],
-        bump = config.lp_bump,
-    )]
-    pub mint_lp: Account<'info, Mint>,
-    #[account(
-        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
-        bump = config.config_bump,
-        has_one = mint_x,
-        has_one = mint_y,
-    )]
-    pub config: Account<'info, Config>,
-    #[account(
-        mut,
-        seeds = [b"swap_state"],
-        bump
-    )]
-    pub swap_state: Account<'info, SwapState>,
-    #[account(mut)]
-    pub vault_x: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub vault_y: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_x: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_y: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-}
-
 // ========================= EVENTS =========================
 
 #[event]
@@ -851,6 +5686,15 @@ pub struct WithdrawEvent {
     pub y_amount: u64,
 }
 
+#[event]
+pub struct SwapEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub is_x: bool,
+    pub deposit_amount: u64,
+    pub withdraw_amount: u64,
+}
+
 #[event]
 pub struct LockEvent {
     pub user: Pubkey,
@@ -863,6 +5707,47 @@ pub struct UnlockEvent {
     pub config: Pubkey,
 }
 
+#[event]
+pub struct SetFeeEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub fee: u16,
+}
+
+#[event]
+pub struct SetProtocolFeeEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub protocol_fee: u16,
+}
+
+#[event]
+pub struct ClaimProtocolFeesEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub x_amount: u64,
+    pub y_amount: u64,
+}
+
+#[event]
+pub struct ProposeAuthorityEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct AcceptAuthorityEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+}
+
+#[event]
+pub struct RenounceAuthorityEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+}
+
 #[event]
 pub struct ConfidentialSwapInitiatedEvent {
     pub user: Pubkey,
@@ -870,6 +5755,9 @@ pub struct ConfidentialSwapInitiatedEvent {
     pub computation_offset: u64,
 }
 
+/// The settled amounts encrypted for the trader. The same amounts are public in
+/// the callback's transfers, the ciphertexts only spare the trader from reading
+/// them back out of the transaction.
 #[event]
 pub struct ConfidentialSwapExecutedEvent {
     pub user: Pubkey,
@@ -881,6 +5769,13 @@ pub struct ConfidentialSwapExecutedEvent {
     // pub is_x: bool,
 }
 
+#[event]
+pub struct ConfidentialSwapCancelledEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub computation_offset: u64,
+}
+
 #[event]
 pub struct ConfidentialSwapFailedEvent {
     pub user: Pubkey,
@@ -889,6 +5784,88 @@ pub struct ConfidentialSwapFailedEvent {
     pub reason: String,
 }
 
+#[event]
+pub struct ConfidentialLiquidityInitiatedEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub computation_offset: u64,
+    pub is_deposit: bool,
+}
+
+/// The settled amounts encrypted for the LP. They are not secret: the
+/// callback's mint or burn and transfers show the same amounts on chain.
+#[event]
+pub struct ConfidentialLiquidityExecutedEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub computation_offset: u64,
+    pub lp_amount: [u8; 32],
+    pub x_amount: [u8; 32],
+    pub y_amount: [u8; 32],
+    pub nonce: u128,
+}
+
+#[event]
+pub struct ConfidentialLiquidityCancelledEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub computation_offset: u64,
+}
+
+#[event]
+pub struct ConfidentialLiquidityFailedEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub computation_offset: u64,
+    pub reason: String,
+}
+
+#[event]
+pub struct DarkPoolEnabledEvent {
+    pub config: Pubkey,
+}
+
+#[event]
+pub struct DarkPoolDisabledEvent {
+    pub config: Pubkey,
+    pub x_amount: u64,
+    pub y_amount: u64,
+}
+
+#[event]
+pub struct DarkFundEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub x_amount: u64,
+    pub y_amount: u64,
+}
+
+#[event]
+pub struct DarkSwapEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub deposit_amount: [u8; 32],
+    pub withdraw_amount: [u8; 32],
+    pub balance_x: [u8; 32],
+    pub balance_y: [u8; 32],
+    pub nonce: u128,
+}
+
+#[event]
+pub struct DarkWithdrawEvent {
+    pub user: Pubkey,
+    pub config: Pubkey,
+    pub x_amount: u64,
+    pub y_amount: u64,
+}
+
+#[event]
+pub struct DarkPoolFailedEvent {
+    pub config: Pubkey,
+    pub user: Option<Pubkey>,
+    pub reason: String,
+}
+
 // ========================= ERRORS =========================
 
 #[error_code]
@@ -905,4 +5882,40 @@ pub enum ErrorCode {
     InvalidAmount,
     #[msg("Invalid update authority")]
     InvalidAuthority,
+    #[msg("Swap is not in a valid state for this action")]
+    InvalidSwapStatus,
+    #[msg("Swap has not expired yet")]
+    SwapNotExpired,
+    #[msg("Invalid swap timeout")]
+    InvalidSwapTimeout,
+    #[msg("Pool reserves moved since the swap was queued")]
+    StaleReserves,
+    #[msg("Deadline exceeded")]
+    DeadlineExceeded,
+    #[msg("Initial deposit is below the minimum liquidity")]
+    InsufficientLiquidity,
+    #[msg("Fee exceeds the maximum")]
+    InvalidFee,
+    #[msg("Mint has a Token-2022 extension the pool does not support")]
+    UnsupportedMintExtension,
+    #[msg("Invalid curve precision")]
+    InvalidPrecision,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Pool reserves are in dark mode")]
+    DarkPoolActive,
+    #[msg("Pool reserves are not in dark mode")]
+    DarkPoolInactive,
+    #[msg("A computation on this dark pool state is still in flight")]
+    DarkStateBusy,
+    #[msg("Protocol fee must be zero before renouncing the authority")]
+    ProtocolFeeActive,
+    #[msg("Liquidity change is not in a valid state for this action")]
+    InvalidLiquidityStatus,
+    #[msg("The computation no longer holds this dark pool state")]
+    StaleComputation,
+    #[msg("The pool went public too recently to go dark again")]
+    DarkPoolCooldown,
+    #[msg("The dark pool seal never landed")]
+    DarkSealExpired,
 }
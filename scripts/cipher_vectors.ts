@@ -0,0 +1,61 @@
+// Writes the Rescue cipher vectors that whispr-client's `cipher_vectors` test
+// checks its RescueCipher against. They come from @arcium-hq/client, the
+// implementation the MXE interoperates with, so the Rust port is pinned to it
+// rather than to its own output.
+//
+//   yarn cipher-vectors
+
+import { RescueCipher, x25519 } from "@arcium-hq/client";
+import * as fs from "fs";
+import * as path from "path";
+
+const hex = (bytes: Uint8Array | number[]) =>
+  Buffer.from(bytes).toString("hex");
+
+// RFC 7748, section 6.1
+const alicePrivate = Buffer.from(
+  "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a",
+  "hex"
+);
+const bobPublic = Buffer.from(
+  "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f",
+  "hex"
+);
+const sharedSecret = x25519.getSharedSecret(alicePrivate, bobPublic);
+const cipher = new RescueCipher(sharedSecret);
+
+const u64Max = BigInt("18446744073709551615");
+const cases: { nonce: Buffer; plaintext: bigint[] }[] = [
+  // SwapData { is_x: true, amount: 10_000_000_000, min_output: 8_000_000_000 }
+  {
+    nonce: Buffer.from("000102030405060708090a0b0c0d0e0f", "hex"),
+    plaintext: [BigInt(1), BigInt(10_000_000_000), BigInt(8_000_000_000)],
+  },
+  // More than one block of keystream, and values at the u64 bound
+  {
+    nonce: Buffer.alloc(16, 0xff),
+    plaintext: Array.from({ length: 12 }, (_, i) => u64Max - BigInt(i)),
+  },
+  {
+    nonce: Buffer.alloc(16),
+    plaintext: [BigInt(0)],
+  },
+];
+
+const fixture = {
+  shared_secret: hex(sharedSecret),
+  cases: cases.map(({ nonce, plaintext }) => ({
+    // Little-endian, as the program takes it as a u128
+    nonce: hex(nonce),
+    plaintext: plaintext.map((value) => value.toString()),
+    ciphertexts: cipher.encrypt(plaintext, nonce).map(hex),
+  })),
+};
+
+const out = path.join(
+  __dirname,
+  "../crates/whispr-client/fixtures/cipher_vectors.json"
+);
+fs.mkdirSync(path.dirname(out), { recursive: true });
+fs.writeFileSync(out, JSON.stringify(fixture, null, 2) + "\n");
+console.log(`wrote ${out}`);
@@ -26,14 +26,27 @@ import * as os from "os";
 import {
   ASSOCIATED_TOKEN_PROGRAM_ID as associatedTokenProgram,
   TOKEN_PROGRAM_ID as tokenProgram,
+  TOKEN_2022_PROGRAM_ID,
+  NATIVE_MINT,
+  ExtensionType,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  getMintLen,
   createMint,
   mintTo,
   getAssociatedTokenAddress,
   getOrCreateAssociatedTokenAccount,
   getAccount,
+  createAssociatedTokenAccountIdempotentInstruction,
+  createSyncNativeInstruction,
 } from "@solana/spl-token";
 import { assert, expect } from "chai";
-import { SystemProgram, Keypair } from "@solana/web3.js";
+import {
+  SystemProgram,
+  Keypair,
+  Transaction,
+  sendAndConfirmTransaction,
+} from "@solana/web3.js";
 import { BN } from "@coral-xyz/anchor";
 import { associatedAddress } from "@coral-xyz/anchor/dist/cjs/utils/token";
 
@@ -61,7 +74,9 @@ describe("Whispr", () => {
   const [admin, user] = [new Keypair(), new Keypair()];
   const seed = new BN(randomBytes(8));
   const fee = 300;
+  const swapTimeout = new BN(600);
   const DECIMALS = 6;
+  const PRECISION = 6;
   const config = PublicKey.findProgramAddressSync(
     [Buffer.from("config"), seed.toArrayLike(Buffer, "le", 8)],
     program.programId
@@ -77,6 +92,14 @@ describe("Whispr", () => {
   let user_x: PublicKey;
   let user_y: PublicKey;
   let user_lp: PublicKey;
+  let locked_lp: PublicKey;
+  const [protocol_x, protocol_y] = ["protocol_x", "protocol_y"].map(
+    (prefix) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from(prefix), config.toBuffer()],
+        program.programId
+      )[0]
+  );
 
   before("Setup AMM", async () => {
     // Airdrop
@@ -115,6 +138,7 @@ describe("Whispr", () => {
     // Get vault addresses
     vault_x = await getAssociatedTokenAddress(mint_x, config, true);
     vault_y = await getAssociatedTokenAddress(mint_y, config, true);
+    locked_lp = await getAssociatedTokenAddress(mint_lp, config, true);
 
     // Create user accounts and mint tokens
     user_x = (
@@ -155,15 +179,20 @@ describe("Whispr", () => {
 
     // Initialize AMM
     await program.methods
-      .initializeAmm(seed, fee, admin.publicKey)
+      .initializeAmm(seed, fee, admin.publicKey, swapTimeout, PRECISION)
       .accountsStrict({
         admin: admin.publicKey,
         mintX: mint_x,
         mintY: mint_y,
         mintLp: mint_lp,
+        lockedLp: locked_lp,
         vaultX: vault_x,
         vaultY: vault_y,
+        protocolX: protocol_x,
+        protocolY: protocol_y,
         config: config,
+        tokenProgramX: tokenProgram,
+        tokenProgramY: tokenProgram,
         tokenProgram,
         associatedTokenProgram,
         systemProgram: SystemProgram.programId,
@@ -184,9 +213,10 @@ describe("Whispr", () => {
 
     await program.methods
       .deposit(
-        new BN(1000 * Math.pow(10, DECIMALS)),
+        new BN(100 * Math.pow(10, DECIMALS)),
         new BN(200 * Math.pow(10, DECIMALS)),
-        new BN(200 * Math.pow(10, DECIMALS))
+        new BN(200 * Math.pow(10, DECIMALS)),
+        null
       )
       .accountsStrict({
         user: user.publicKey,
@@ -199,6 +229,9 @@ describe("Whispr", () => {
         userX: user_x,
         userY: user_y,
         userLp: user_lp,
+        lockedLp: locked_lp,
+        tokenProgramX: tokenProgram,
+        tokenProgramY: tokenProgram,
         tokenProgram,
         associatedTokenProgram,
         systemProgram: SystemProgram.programId,
@@ -220,6 +253,48 @@ describe("Whispr", () => {
     console.log("User LP:", Number(userLpAcc.amount) / 10 ** DECIMALS);
   });
 
+  it("swap publicly", async () => {
+    const swapAccounts = {
+      user: user.publicKey,
+      mintX: mint_x,
+      mintY: mint_y,
+      config: config,
+      mintLp: mint_lp,
+      vaultX: vault_x,
+      vaultY: vault_y,
+      protocolX: protocol_x,
+      protocolY: protocol_y,
+      userX: user_x,
+      userY: user_y,
+      tokenProgramX: tokenProgram,
+      tokenProgramY: tokenProgram,
+      associatedTokenProgram,
+      systemProgram: SystemProgram.programId,
+    };
+    const amount = new BN(1 * Math.pow(10, DECIMALS));
+
+    // A transaction landing after its deadline must not execute
+    try {
+      await program.methods
+        .swap(true, amount, new BN(0), new BN(1))
+        .accountsStrict(swapAccounts)
+        .signers([user])
+        .rpc();
+      assert.fail("swap executed after its deadline");
+    } catch (err) {
+      expect(err.toString()).to.include("DeadlineExceeded");
+    }
+
+    const userYBefore = await getAccount(provider.connection, user_y);
+    await program.methods
+      .swap(true, amount, new BN(1), null)
+      .accountsStrict(swapAccounts)
+      .signers([user])
+      .rpc();
+    const userYAfter = await getAccount(provider.connection, user_y);
+    expect(userYAfter.amount > userYBefore.amount).to.be.true;
+  });
+
   it("execute confidential swap", async () => {
     const owner = readKpJson(`${os.homedir()}/.config/solana/id.json`);
 
@@ -231,7 +306,12 @@ describe("Whispr", () => {
     console.log("MXE x25519 pubkey is", mxePublicKey);
 
     console.log("Initializing compute swap computation definition");
-    const initSwapSig = await initComputeSwapCompDef(program, owner, false);
+    const initSwapSig = await initCompDef(
+      program,
+      owner,
+      "compute_swap",
+      false
+    );
     console.log(
       "Compute swap computation definition initialized with signature",
       initSwapSig
@@ -247,9 +327,8 @@ describe("Whispr", () => {
     const minOutput = BigInt(8 * Math.pow(10, DECIMALS));
 
     const nonce = randomBytes(16);
-    const ciphertextIsX = cipher.encrypt([isX], nonce);
-    const ciphertextAmount = cipher.encrypt([swapAmount], nonce);
-    const ciphertextMinOutput = cipher.encrypt([minOutput], nonce);
+    // SwapData fields are encrypted together, in struct order
+    const ciphertext = cipher.encrypt([isX, swapAmount, minOutput], nonce);
 
     const swapExecutedEventPromise = awaitEvent(
       "confidentialSwapExecutedEvent"
@@ -260,28 +339,50 @@ describe("Whispr", () => {
     const swapStatePda = PublicKey.findProgramAddressSync(
       [
         Buffer.from("swap_state"),
-        // user.publicKey.toBuffer()
+        config.toBuffer(),
+        user.publicKey.toBuffer(),
+        computationOffset.toArrayLike(Buffer, "le", 8),
       ],
       program.programId
     )[0];
+    const [escrowX, escrowY] = ["escrow_x", "escrow_y"].map(
+      (prefix) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(prefix), swapStatePda.toBuffer()],
+          program.programId
+        )[0]
+    );
+
+    // Lock up to 20 of each side so the escrow does not reveal the direction
+    // while the swap is queued
+    const maxIn = new BN(20 * Math.pow(10, DECIMALS));
+    const userXBefore = await getAccount(provider.connection, user_x);
+    const userYBefore = await getAccount(provider.connection, user_y);
 
     const queueSig = await program.methods
       .computeSwap(
         computationOffset,
         Array.from(publicKey),
         new anchor.BN(deserializeLE(nonce).toString()),
-        Array.from(ciphertextAmount[0])
-        //  Array.from(ciphertextMinOutput[0]),
+        Array.from(ciphertext[0]),
+        Array.from(ciphertext[1]),
+        Array.from(ciphertext[2]),
+        maxIn,
+        maxIn,
+        new BN(Math.floor(Date.now() / 1000) + 600)
       )
       .accountsPartial({
         user: user.publicKey,
         mintX: mint_x,
         mintY: mint_y,
         config: config,
-        mintLp: mint_lp,
         vaultX: vault_x,
         vaultY: vault_y,
+        protocolX: protocol_x,
+        protocolY: protocol_y,
         swapState: swapStatePda,
+        escrowX,
+        escrowY,
         userX: user_x,
         userY: user_y,
         computationAccount: getComputationAccAddress(
@@ -296,7 +397,8 @@ describe("Whispr", () => {
           program.programId,
           Buffer.from(getCompDefAccOffset("compute_swap")).readUInt32LE()
         ),
-        tokenProgram,
+        tokenProgramX: tokenProgram,
+        tokenProgramY: tokenProgram,
         associatedTokenProgram,
         systemProgram: SystemProgram.programId,
       })
@@ -322,42 +424,970 @@ describe("Whispr", () => {
     );
     console.log(`deposit amount is ${output[0]}`);
     console.log(`withdraw amount is ${output[1]}`);
-    const depositBN = new BN(output[0].toString());
-    const withdrawBN = new BN(output[1].toString());
-    const userXBefore = await getAccount(provider.connection, user_x);
-    const userYBefore = await getAccount(provider.connection, user_y);
 
-    const executeTx = await program.methods
-      .executeSwap(depositBN, withdrawBN)
+    // The callback settled atomically and refunded the unused escrow
+    const userXAfter = await getAccount(provider.connection, user_x);
+    const userYAfter = await getAccount(provider.connection, user_y);
+    expect(userXBefore.amount - userXAfter.amount).to.equal(output[0]);
+    expect(userYAfter.amount - userYBefore.amount).to.equal(output[1]);
+    expect(await provider.connection.getAccountInfo(escrowX)).to.be.null;
+    expect(await provider.connection.getAccountInfo(escrowY)).to.be.null;
+
+    const swapState = await program.account.swapState.fetch(swapStatePda);
+    expect(swapState.status).to.have.property("executed");
+
+    // Settled swaps can be closed to reclaim rent
+    await program.methods
+      .closeSwap()
       .accountsPartial({
+        user: user.publicKey,
+        config: config,
+        swapState: swapStatePda,
+      })
+      .signers([user])
+      .rpc();
+    expect(await provider.connection.getAccountInfo(swapStatePda)).to.be.null;
+  });
+
+  it("accrue and claim protocol fees", async () => {
+    await program.methods
+      .setProtocolFee(2_000)
+      .accountsStrict({ user: admin.publicKey, config: config })
+      .signers([admin])
+      .rpc();
+
+    await program.methods
+      .swap(false, new BN(5 * Math.pow(10, DECIMALS)), new BN(1), null)
+      .accountsStrict({
         user: user.publicKey,
         mintX: mint_x,
         mintY: mint_y,
         config: config,
-        swapState: swapStatePda,
+        mintLp: mint_lp,
         vaultX: vault_x,
         vaultY: vault_y,
+        protocolX: protocol_x,
+        protocolY: protocol_y,
         userX: user_x,
         userY: user_y,
+        tokenProgramX: tokenProgram,
+        tokenProgramY: tokenProgram,
+        associatedTokenProgram,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+    const accrued = (await getAccount(provider.connection, protocol_y)).amount;
+    expect(accrued > BigInt(0)).to.be.true;
+
+    const [admin_x, admin_y] = await Promise.all(
+      [mint_x, mint_y].map(
+        async (mint) =>
+          (
+            await getOrCreateAssociatedTokenAccount(
+              provider.connection,
+              admin,
+              mint,
+              admin.publicKey
+            )
+          ).address
+      )
+    );
+    await program.methods
+      .claimProtocolFees()
+      .accountsStrict({
+        user: admin.publicKey,
+        mintX: mint_x,
+        mintY: mint_y,
+        config: config,
+        protocolX: protocol_x,
+        protocolY: protocol_y,
+        userX: admin_x,
+        userY: admin_y,
+        tokenProgramX: tokenProgram,
+        tokenProgramY: tokenProgram,
+      })
+      .signers([admin])
+      .rpc();
+    expect((await getAccount(provider.connection, admin_y)).amount).to.equal(
+      accrued
+    );
+    expect((await getAccount(provider.connection, protocol_y)).amount).to.equal(
+      BigInt(0)
+    );
+  });
+
+  it("swap a token-2022 transfer fee mint", async () => {
+    const feeSeed = new BN(randomBytes(8));
+    const feeConfig = PublicKey.findProgramAddressSync(
+      [Buffer.from("config"), feeSeed.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+    const feeMintLp = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp"), feeConfig.toBuffer()],
+      program.programId
+    )[0];
+    const [feeProtocolX, feeProtocolY] = ["protocol_x", "protocol_y"].map(
+      (prefix) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(prefix), feeConfig.toBuffer()],
+          program.programId
+        )[0]
+    );
+
+    // 1% transfer fee on X, Y stays a plain SPL Token mint
+    const feeMintX = Keypair.generate();
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: admin.publicKey,
+          newAccountPubkey: feeMintX.publicKey,
+          space: mintLen,
+          lamports:
+            await provider.connection.getMinimumBalanceForRentExemption(
+              mintLen
+            ),
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          feeMintX.publicKey,
+          admin.publicKey,
+          admin.publicKey,
+          100,
+          BigInt(Math.pow(10, DECIMALS)),
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(
+          feeMintX.publicKey,
+          DECIMALS,
+          admin.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ),
+      [admin, feeMintX]
+    );
+
+    const feeUserX = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        user,
+        feeMintX.publicKey,
+        user.publicKey,
+        true,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      )
+    ).address;
+    await mintTo(
+      provider.connection,
+      admin,
+      feeMintX.publicKey,
+      feeUserX,
+      admin.publicKey,
+      1000 * Math.pow(10, DECIMALS),
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const feeVaultX = await getAssociatedTokenAddress(
+      feeMintX.publicKey,
+      feeConfig,
+      true,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const feeVaultY = await getAssociatedTokenAddress(mint_y, feeConfig, true);
+    const feeLockedLp = await getAssociatedTokenAddress(
+      feeMintLp,
+      feeConfig,
+      true
+    );
+    const poolAccounts = {
+      mintX: feeMintX.publicKey,
+      mintY: mint_y,
+      mintLp: feeMintLp,
+      vaultX: feeVaultX,
+      vaultY: feeVaultY,
+      config: feeConfig,
+      tokenProgramX: TOKEN_2022_PROGRAM_ID,
+      tokenProgramY: tokenProgram,
+      associatedTokenProgram,
+      systemProgram: SystemProgram.programId,
+    };
+
+    await program.methods
+      .initializeAmm(feeSeed, fee, admin.publicKey, swapTimeout, PRECISION)
+      .accountsStrict({
+        ...poolAccounts,
+        admin: admin.publicKey,
+        lockedLp: feeLockedLp,
+        protocolX: feeProtocolX,
+        protocolY: feeProtocolY,
+        tokenProgram,
+      })
+      .signers([admin])
+      .rpc();
+    const feeConfigAcc = await program.account.config.fetch(feeConfig);
+    expect(feeConfigAcc.lpDecimals).to.equal(DECIMALS);
+    expect(feeConfigAcc.precision).to.equal(PRECISION);
+
+    const feeUserLp = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        user,
+        feeMintLp,
+        user.publicKey,
+        true
+      )
+    ).address;
+    await program.methods
+      .deposit(
+        new BN(1),
+        new BN(100 * Math.pow(10, DECIMALS)),
+        new BN(100 * Math.pow(10, DECIMALS)),
+        null
+      )
+      .accountsStrict({
+        ...poolAccounts,
+        user: user.publicKey,
+        userX: feeUserX,
+        userY: user_y,
+        userLp: feeUserLp,
+        lockedLp: feeLockedLp,
+        tokenProgram,
+      })
+      .signers([user])
+      .rpc();
+
+    // The vault only receives the deposit net of the 1% transfer fee
+    const vaultBefore = (
+      await getAccount(
+        provider.connection,
+        feeVaultX,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      )
+    ).amount;
+    expect(vaultBefore).to.equal(BigInt(99 * Math.pow(10, DECIMALS)));
+
+    const amount = 10 * Math.pow(10, DECIMALS);
+    await program.methods
+      .swap(true, new BN(amount), new BN(1), null)
+      .accountsStrict({
+        ...poolAccounts,
+        user: user.publicKey,
+        protocolX: feeProtocolX,
+        protocolY: feeProtocolY,
+        userX: feeUserX,
+        userY: user_y,
+      })
+      .signers([user])
+      .rpc();
+
+    const vaultAfter = (
+      await getAccount(
+        provider.connection,
+        feeVaultX,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      )
+    ).amount;
+    expect(vaultAfter - vaultBefore).to.equal(BigInt((amount * 99) / 100));
+  });
+
+  it("wrap and unwrap native SOL", async () => {
+    const solSeed = new BN(randomBytes(8));
+    const solConfig = PublicKey.findProgramAddressSync(
+      [Buffer.from("config"), solSeed.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+    const solMintLp = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp"), solConfig.toBuffer()],
+      program.programId
+    )[0];
+    const [solProtocolX, solProtocolY] = ["protocol_x", "protocol_y"].map(
+      (prefix) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(prefix), solConfig.toBuffer()],
+          program.programId
+        )[0]
+    );
+    const solLockedLp = await getAssociatedTokenAddress(
+      solMintLp,
+      solConfig,
+      true
+    );
+    const solUserX = await getAssociatedTokenAddress(
+      NATIVE_MINT,
+      user.publicKey
+    );
+    const poolAccounts = {
+      mintX: NATIVE_MINT,
+      mintY: mint_y,
+      mintLp: solMintLp,
+      vaultX: await getAssociatedTokenAddress(NATIVE_MINT, solConfig, true),
+      vaultY: await getAssociatedTokenAddress(mint_y, solConfig, true),
+      config: solConfig,
+      tokenProgramX: tokenProgram,
+      tokenProgramY: tokenProgram,
+      associatedTokenProgram,
+      systemProgram: SystemProgram.programId,
+    };
+
+    await program.methods
+      .initializeAmm(solSeed, fee, admin.publicKey, swapTimeout, PRECISION)
+      .accountsStrict({
+        ...poolAccounts,
+        admin: admin.publicKey,
+        lockedLp: solLockedLp,
+        protocolX: solProtocolX,
+        protocolY: solProtocolY,
+        tokenProgram,
+      })
+      .signers([admin])
+      .rpc();
+
+    // The program never creates the wSOL account, the client does when the
+    // pool has a native side
+    const createSolUserX = () =>
+      createAssociatedTokenAccountIdempotentInstruction(
+        user.publicKey,
+        solUserX,
+        user.publicKey,
+        NATIVE_MINT
+      );
+
+    // The user holds no wSOL, the deposit wraps it from their lamports
+    const solUserLp = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        user,
+        solMintLp,
+        user.publicKey,
+        true
+      )
+    ).address;
+    const sol = anchor.web3.LAMPORTS_PER_SOL;
+    await program.methods
+      .deposit(new BN(1), new BN(10 * sol), new BN(100 * Math.pow(10, DECIMALS)), null)
+      .accountsStrict({
+        ...poolAccounts,
+        user: user.publicKey,
+        userX: solUserX,
+        userY: user_y,
+        userLp: solUserLp,
+        lockedLp: solLockedLp,
         tokenProgram,
+      })
+      .preInstructions([createSolUserX()])
+      .signers([user])
+      .rpc();
+
+    const swapYForSol = () =>
+      program.methods
+        .swap(false, new BN(10 * Math.pow(10, DECIMALS)), new BN(1), null)
+        .accountsStrict({
+          ...poolAccounts,
+          user: user.publicKey,
+          protocolX: solProtocolX,
+          protocolY: solProtocolY,
+          userX: solUserX,
+          userY: user_y,
+        })
+        .preInstructions([createSolUserX()])
+        .signers([user])
+        .rpc();
+
+    // Swapping Y into the pool pays out lamports and closes the empty wSOL account
+    const lamportsBefore = await provider.connection.getBalance(user.publicKey);
+    await swapYForSol();
+    const lamportsAfter = await provider.connection.getBalance(user.publicKey);
+    expect(lamportsAfter).to.be.greaterThan(lamportsBefore);
+    expect(await provider.connection.getAccountInfo(solUserX)).to.be.null;
+
+    // wSOL the user already held is left wrapped, along with the output
+    const held = BigInt(sol);
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        createSolUserX(),
+        SystemProgram.transfer({
+          fromPubkey: user.publicKey,
+          toPubkey: solUserX,
+          lamports: sol,
+        }),
+        createSyncNativeInstruction(solUserX)
+      ),
+      [user]
+    );
+    await swapYForSol();
+    expect((await getAccount(provider.connection, solUserX)).amount > held).to
+      .be.true;
+  });
+
+  it("zap in and out of one side", async () => {
+    const zapAccounts = {
+      user: user.publicKey,
+      mintX: mint_x,
+      mintY: mint_y,
+      config: config,
+      mintLp: mint_lp,
+      vaultX: vault_x,
+      vaultY: vault_y,
+      protocolX: protocol_x,
+      protocolY: protocol_y,
+      userX: user_x,
+      userY: user_y,
+      userLp: user_lp,
+      tokenProgramX: tokenProgram,
+      tokenProgramY: tokenProgram,
+      tokenProgram,
+      associatedTokenProgram,
+      systemProgram: SystemProgram.programId,
+    };
+
+    // Deposit X only, part of it is swapped to Y inside the program
+    const lpBefore = (await getAccount(provider.connection, user_lp)).amount;
+    const yBefore = (await getAccount(provider.connection, user_y)).amount;
+    await program.methods
+      .depositSingle(true, new BN(10 * Math.pow(10, DECIMALS)), new BN(1), null)
+      .accountsStrict(zapAccounts)
+      .signers([user])
+      .rpc();
+    const lpAfter = (await getAccount(provider.connection, user_lp)).amount;
+    expect(lpAfter > lpBefore).to.be.true;
+    expect((await getAccount(provider.connection, user_y)).amount).to.equal(
+      yBefore
+    );
+
+    // Withdraw the new LP as Y only
+    await program.methods
+      .withdrawSingle(
+        false,
+        new BN((lpAfter - lpBefore).toString()),
+        new BN(1),
+        null
+      )
+      .accountsStrict(zapAccounts)
+      .signers([user])
+      .rpc();
+    expect((await getAccount(provider.connection, user_lp)).amount).to.equal(
+      lpBefore
+    );
+    expect((await getAccount(provider.connection, user_y)).amount > yBefore).to
+      .be.true;
+  });
+
+  it("deposit and withdraw liquidity confidentially", async () => {
+    const owner = readKpJson(`${os.homedir()}/.config/solana/id.json`);
+    await initCompDef(program, owner, "compute_deposit", false);
+    await initCompDef(program, owner, "compute_withdraw", false);
+
+    const mxePublicKey = await getMXEPublicKeyWithRetry(
+      provider as anchor.AnchorProvider,
+      program.programId
+    );
+    const privateKey = x25519.utils.randomPrivateKey();
+    const publicKey = x25519.getPublicKey(privateKey);
+    const sharedSecret = x25519.getSharedSecret(privateKey, mxePublicKey);
+    const cipher = new RescueCipher(sharedSecret);
+
+    const liquidityAccounts = (circuit: string, computationOffset: BN) => {
+      const liquidityState = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("liquidity_state"),
+          config.toBuffer(),
+          user.publicKey.toBuffer(),
+          computationOffset.toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      )[0];
+      const [escrowX, escrowY, escrowLp] = [
+        "escrow_x",
+        "escrow_y",
+        "escrow_lp",
+      ].map(
+        (prefix) =>
+          PublicKey.findProgramAddressSync(
+            [Buffer.from(prefix), liquidityState.toBuffer()],
+            program.programId
+          )[0]
+      );
+      return {
+        liquidityState,
+        escrowX,
+        escrowY,
+        escrowLp,
+        accounts: {
+          user: user.publicKey,
+          mintX: mint_x,
+          mintY: mint_y,
+          config: config,
+          mintLp: mint_lp,
+          vaultX: vault_x,
+          vaultY: vault_y,
+          liquidityState,
+          userX: user_x,
+          userY: user_y,
+          userLp: user_lp,
+          computationAccount: getComputationAccAddress(
+            program.programId,
+            computationOffset
+          ),
+          clusterAccount: arciumEnv.arciumClusterPubkey,
+          mxeAccount: getMXEAccAddress(program.programId),
+          mempoolAccount: getMempoolAccAddress(program.programId),
+          executingPool: getExecutingPoolAccAddress(program.programId),
+          compDefAccount: getCompDefAccAddress(
+            program.programId,
+            Buffer.from(getCompDefAccOffset(circuit)).readUInt32LE()
+          ),
+          tokenProgramX: tokenProgram,
+          tokenProgramY: tokenProgram,
+          tokenProgram,
+          associatedTokenProgram,
+          systemProgram: SystemProgram.programId,
+        },
+      };
+    };
+
+    // Deposit for 5 LP, the escrows are over-funded to hide the size
+    const lpAmount = BigInt(5 * Math.pow(10, DECIMALS));
+    const maxIn = BigInt(50 * Math.pow(10, DECIMALS));
+    let nonce = randomBytes(16);
+    let ciphertext = cipher.encrypt([lpAmount, maxIn, maxIn], nonce);
+    let computationOffset = new anchor.BN(randomBytes(8), "hex");
+    const deposit = liquidityAccounts("compute_deposit", computationOffset);
+
+    const lpBefore = (await getAccount(provider.connection, user_lp)).amount;
+    let executedEventPromise = awaitEvent("confidentialLiquidityExecutedEvent");
+    await program.methods
+      .computeDeposit(
+        computationOffset,
+        Array.from(publicKey),
+        new anchor.BN(deserializeLE(nonce).toString()),
+        Array.from(ciphertext[0]),
+        Array.from(ciphertext[1]),
+        Array.from(ciphertext[2]),
+        new BN(maxIn.toString()),
+        new BN(maxIn.toString()),
+        null
+      )
+      .accountsPartial({
+        ...deposit.accounts,
+        escrowX: deposit.escrowX,
+        escrowY: deposit.escrowY,
+      })
+      .signers([user])
+      .rpc({ commitment: "confirmed" });
+    await awaitComputationFinalization(
+      provider as anchor.AnchorProvider,
+      computationOffset,
+      program.programId,
+      "confirmed"
+    );
+    let executedEvent = await executedEventPromise;
+    let output = cipher.decrypt(
+      [executedEvent.lpAmount],
+      executedEvent.nonce.toArrayLike(Buffer, "le", 16)
+    );
+    expect(output[0]).to.equal(lpAmount);
+    expect((await getAccount(provider.connection, user_lp)).amount).to.equal(
+      lpBefore + lpAmount
+    );
+    expect(await provider.connection.getAccountInfo(deposit.escrowX)).to.be
+      .null;
+    expect(await provider.connection.getAccountInfo(deposit.escrowY)).to.be
+      .null;
+
+    await program.methods
+      .closeLiquidity()
+      .accountsPartial({
+        user: user.publicKey,
+        config: config,
+        liquidityState: deposit.liquidityState,
+      })
+      .signers([user])
+      .rpc();
+
+    // Withdraw the same LP, with only the escrowed upper bound public
+    nonce = randomBytes(16);
+    ciphertext = cipher.encrypt([lpAmount, BigInt(1), BigInt(1)], nonce);
+    computationOffset = new anchor.BN(randomBytes(8), "hex");
+    const withdraw = liquidityAccounts("compute_withdraw", computationOffset);
+
+    const yBefore = (await getAccount(provider.connection, user_y)).amount;
+    executedEventPromise = awaitEvent("confidentialLiquidityExecutedEvent");
+    await program.methods
+      .computeWithdraw(
+        computationOffset,
+        Array.from(publicKey),
+        new anchor.BN(deserializeLE(nonce).toString()),
+        Array.from(ciphertext[0]),
+        Array.from(ciphertext[1]),
+        Array.from(ciphertext[2]),
+        new BN((lpAmount * BigInt(2)).toString()),
+        null
+      )
+      .accountsPartial({ ...withdraw.accounts, escrowLp: withdraw.escrowLp })
+      .signers([user])
+      .rpc({ commitment: "confirmed" });
+    await awaitComputationFinalization(
+      provider as anchor.AnchorProvider,
+      computationOffset,
+      program.programId,
+      "confirmed"
+    );
+    executedEvent = await executedEventPromise;
+    output = cipher.decrypt(
+      [executedEvent.yAmount],
+      executedEvent.nonce.toArrayLike(Buffer, "le", 16)
+    );
+    expect((await getAccount(provider.connection, user_lp)).amount).to.equal(
+      lpBefore
+    );
+    expect((await getAccount(provider.connection, user_y)).amount).to.equal(
+      yBefore + output[0]
+    );
+    expect(await provider.connection.getAccountInfo(withdraw.escrowLp)).to.be
+      .null;
+  });
+
+  it("trade in dark pool mode", async () => {
+    const owner = readKpJson(`${os.homedir()}/.config/solana/id.json`);
+    for (const circuit of [
+      "seal_reserves",
+      "reveal_reserves",
+      "dark_fund",
+      "dark_swap",
+      "dark_withdraw",
+    ] as const) {
+      await initCompDef(program, owner, circuit, false);
+    }
+
+    const mxePublicKey = await getMXEPublicKeyWithRetry(
+      provider as anchor.AnchorProvider,
+      program.programId
+    );
+    const privateKey = x25519.utils.randomPrivateKey();
+    const publicKey = x25519.getPublicKey(privateKey);
+    const sharedSecret = x25519.getSharedSecret(privateKey, mxePublicKey);
+    const cipher = new RescueCipher(sharedSecret);
+
+    const [darkPool, darkX, darkY] = ["dark_pool", "dark_x", "dark_y"].map(
+      (prefix) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(prefix), config.toBuffer()],
+          program.programId
+        )[0]
+    );
+    const darkBalance = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("dark_balance"),
+        config.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    )[0];
+    const poolAccounts = {
+      mintX: mint_x,
+      mintY: mint_y,
+      config: config,
+      darkPool,
+      darkX,
+      darkY,
+      tokenProgramX: tokenProgram,
+      tokenProgramY: tokenProgram,
+      systemProgram: SystemProgram.programId,
+    };
+    const arciumAccounts = (circuit: string, computationOffset: BN) => ({
+      computationAccount: getComputationAccAddress(
+        program.programId,
+        computationOffset
+      ),
+      clusterAccount: arciumEnv.arciumClusterPubkey,
+      mxeAccount: getMXEAccAddress(program.programId),
+      mempoolAccount: getMempoolAccAddress(program.programId),
+      executingPool: getExecutingPoolAccAddress(program.programId),
+      compDefAccount: getCompDefAccAddress(
+        program.programId,
+        Buffer.from(getCompDefAccOffset(circuit)).readUInt32LE()
+      ),
+    });
+    const finalize = (computationOffset: BN) =>
+      awaitComputationFinalization(
+        provider as anchor.AnchorProvider,
+        computationOffset,
+        program.programId,
+        "confirmed"
+      );
+
+    // Seal the current reserves, the public vaults are emptied into the
+    // aggregated dark vaults
+    let computationOffset = new anchor.BN(randomBytes(8), "hex");
+    await program.methods
+      .enableDarkPool(
+        computationOffset,
+        new anchor.BN(deserializeLE(randomBytes(16)).toString())
+      )
+      .accountsPartial({
+        payer: admin.publicKey,
+        ...poolAccounts,
+        vaultX: vault_x,
+        vaultY: vault_y,
+        ...arciumAccounts("seal_reserves", computationOffset),
+      })
+      .signers([admin])
+      .rpc({ commitment: "confirmed" });
+    await finalize(computationOffset);
+    expect((await program.account.config.fetch(config)).dark).to.be.true;
+    expect((await program.account.darkPool.fetch(darkPool)).sealed).to.be.true;
+    expect((await getAccount(provider.connection, vault_x)).amount).to.equal(
+      BigInt(0)
+    );
+
+    // Public trading is closed while dark
+    try {
+      await program.methods
+        .swap(true, new BN(1), new BN(0), null)
+        .accountsPartial({
+          user: user.publicKey,
+          mintX: mint_x,
+          mintY: mint_y,
+          config: config,
+          mintLp: mint_lp,
+          vaultX: vault_x,
+          vaultY: vault_y,
+          protocolX: protocol_x,
+          protocolY: protocol_y,
+          userX: user_x,
+          userY: user_y,
+          tokenProgramX: tokenProgram,
+          tokenProgramY: tokenProgram,
+          associatedTokenProgram,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("public swap executed in dark mode");
+    } catch (err) {
+      expect(err.toString()).to.include("DarkPoolActive");
+    }
+
+    // Fund an encrypted balance with 10 X
+    const fundAmount = BigInt(10 * Math.pow(10, DECIMALS));
+    const xBefore = (await getAccount(provider.connection, user_x)).amount;
+    const yBefore = (await getAccount(provider.connection, user_y)).amount;
+    computationOffset = new anchor.BN(randomBytes(8), "hex");
+    await program.methods
+      .darkFund(computationOffset, new BN(fundAmount.toString()), new BN(0))
+      .accountsPartial({
+        user: user.publicKey,
+        ...poolAccounts,
+        darkBalance,
+        userX: user_x,
+        userY: user_y,
         associatedTokenProgram,
+        ...arciumAccounts("dark_fund", computationOffset),
+      })
+      .signers([user])
+      .rpc({ commitment: "confirmed" });
+    await finalize(computationOffset);
+    expect((await program.account.darkBalance.fetch(darkBalance)).initialized)
+      .to.be.true;
+
+    // Swap 5 X for Y, nothing moves between token accounts
+    const swapAmount = BigInt(5 * Math.pow(10, DECIMALS));
+    let nonce = randomBytes(16);
+    let ciphertext = cipher.encrypt([BigInt(1), swapAmount, BigInt(1)], nonce);
+    const darkXBefore = (await getAccount(provider.connection, darkX)).amount;
+    const darkSwapEventPromise = awaitEvent("darkSwapEvent");
+    computationOffset = new anchor.BN(randomBytes(8), "hex");
+    await program.methods
+      .darkSwap(
+        computationOffset,
+        Array.from(publicKey),
+        new anchor.BN(deserializeLE(nonce).toString()),
+        Array.from(ciphertext[0]),
+        Array.from(ciphertext[1]),
+        Array.from(ciphertext[2])
+      )
+      .accountsPartial({
+        user: user.publicKey,
+        config: config,
+        darkPool,
+        darkBalance,
         systemProgram: SystemProgram.programId,
+        ...arciumAccounts("dark_swap", computationOffset),
+      })
+      .signers([user])
+      .rpc({ commitment: "confirmed" });
+    await finalize(computationOffset);
+    const darkSwapEvent = await darkSwapEventPromise;
+    const [deposited, withdrawn, balanceX, balanceY] = cipher.decrypt(
+      [
+        darkSwapEvent.depositAmount,
+        darkSwapEvent.withdrawAmount,
+        darkSwapEvent.balanceX,
+        darkSwapEvent.balanceY,
+      ],
+      darkSwapEvent.nonce.toArrayLike(Buffer, "le", 16)
+    );
+    expect(deposited).to.equal(swapAmount);
+    expect(withdrawn > BigInt(0)).to.be.true;
+    expect(balanceX).to.equal(fundAmount - swapAmount);
+    expect(balanceY).to.equal(withdrawn);
+    expect((await getAccount(provider.connection, darkX)).amount).to.equal(
+      darkXBefore
+    );
+
+    // Take the whole balance back out
+    nonce = randomBytes(16);
+    ciphertext = cipher.encrypt([balanceX, balanceY], nonce);
+    computationOffset = new anchor.BN(randomBytes(8), "hex");
+    await program.methods
+      .darkWithdraw(
+        computationOffset,
+        Array.from(publicKey),
+        new anchor.BN(deserializeLE(nonce).toString()),
+        Array.from(ciphertext[0]),
+        Array.from(ciphertext[1])
+      )
+      .accountsPartial({
+        user: user.publicKey,
+        ...poolAccounts,
+        darkBalance,
+        userX: user_x,
+        userY: user_y,
+        associatedTokenProgram,
+        ...arciumAccounts("dark_withdraw", computationOffset),
+      })
+      .signers([user])
+      .rpc({ commitment: "confirmed" });
+    await finalize(computationOffset);
+    expect((await getAccount(provider.connection, user_x)).amount).to.equal(
+      xBefore - swapAmount
+    );
+    expect((await getAccount(provider.connection, user_y)).amount).to.equal(
+      yBefore + withdrawn
+    );
+
+    // Reveal the aggregate reserves and go back to public trading, the
+    // protocol's cut of the dark swap fee is paid out with them
+    const protocolXBefore = (await getAccount(provider.connection, protocol_x))
+      .amount;
+    computationOffset = new anchor.BN(randomBytes(8), "hex");
+    await program.methods
+      .disableDarkPool(computationOffset)
+      .accountsPartial({
+        payer: admin.publicKey,
+        ...poolAccounts,
+        vaultX: vault_x,
+        vaultY: vault_y,
+        protocolX: protocol_x,
+        protocolY: protocol_y,
+        ...arciumAccounts("reveal_reserves", computationOffset),
       })
+      .signers([admin])
+      .rpc({ commitment: "confirmed" });
+    await finalize(computationOffset);
+    expect((await program.account.config.fetch(config)).dark).to.be.false;
+    expect(
+      (await getAccount(provider.connection, protocol_x)).amount >
+        protocolXBefore
+    ).to.be.true;
+    expect((await getAccount(provider.connection, darkX)).amount).to.equal(
+      BigInt(0)
+    );
+  });
+
+  it("update fee and hand over authority", async () => {
+    const updateAccounts = (k: Keypair) => ({
+      user: k.publicKey,
+      config: config,
+    });
+
+    try {
+      await program.methods
+        .setFee(10_001)
+        .accountsStrict(updateAccounts(admin))
+        .signers([admin])
+        .rpc();
+      assert.fail("set_fee accepted a fee above the cap");
+    } catch (err) {
+      expect(err.toString()).to.include("InvalidFee");
+    }
+
+    await program.methods
+      .setFee(250)
+      .accountsStrict(updateAccounts(admin))
+      .signers([admin])
+      .rpc();
+    expect((await program.account.config.fetch(config)).fee).to.equal(250);
+
+    // Two-step transfer: the proposed authority has to accept
+    await program.methods
+      .proposeAuthority(user.publicKey)
+      .accountsStrict(updateAccounts(admin))
+      .signers([admin])
+      .rpc();
+    await program.methods
+      .acceptAuthority()
+      .accountsStrict(updateAccounts(user))
       .signers([user])
       .rpc();
+    let configAcc = await program.account.config.fetch(config);
+    expect(configAcc.authority.equals(user.publicKey)).to.be.true;
+    expect(configAcc.pendingAuthority).to.be.null;
 
-    console.log("✓ Swap executed:", executeTx);
+    // Protocol fees accrued without an authority could never be claimed
+    try {
+      await program.methods
+        .renounceAuthority()
+        .accountsStrict(updateAccounts(user))
+        .signers([user])
+        .rpc();
+      assert.fail("renounce_authority accepted a nonzero protocol fee");
+    } catch (err) {
+      expect(err.toString()).to.include("ProtocolFeeActive");
+    }
+    await program.methods
+      .setProtocolFee(0)
+      .accountsStrict(updateAccounts(user))
+      .signers([user])
+      .rpc();
+
+    await program.methods
+      .renounceAuthority()
+      .accountsStrict(updateAccounts(user))
+      .signers([user])
+      .rpc();
+    configAcc = await program.account.config.fetch(config);
+    expect(configAcc.authority).to.be.null;
   });
 
-  async function initComputeSwapCompDef(
+  async function initCompDef(
     program: Program<Whispr>,
     owner: anchor.web3.Keypair,
+    circuit:
+      | "compute_swap"
+      | "compute_deposit"
+      | "compute_withdraw"
+      | "seal_reserves"
+      | "reveal_reserves"
+      | "dark_fund"
+      | "dark_swap"
+      | "dark_withdraw",
     uploadRawCircuit: boolean
   ): Promise<string> {
     const baseSeedCompDefAcc = getArciumAccountBaseSeed(
       "ComputationDefinitionAccount"
     );
-    const offset = getCompDefAccOffset("compute_swap");
+    const offset = getCompDefAccOffset(circuit);
 
     const compDefPDA = PublicKey.findProgramAddressSync(
       [baseSeedCompDefAcc, program.programId.toBuffer(), offset],
@@ -366,8 +1396,17 @@ describe("Whispr", () => {
 
     console.log("Comp def pda is ", compDefPDA.toBase58());
 
-    const sig = await program.methods
-      .initComputeSwapCompDef()
+    const initMethod = {
+      compute_swap: program.methods.initComputeSwapCompDef,
+      compute_deposit: program.methods.initComputeDepositCompDef,
+      compute_withdraw: program.methods.initComputeWithdrawCompDef,
+      seal_reserves: program.methods.initSealReservesCompDef,
+      reveal_reserves: program.methods.initRevealReservesCompDef,
+      dark_fund: program.methods.initDarkFundCompDef,
+      dark_swap: program.methods.initDarkSwapCompDef,
+      dark_withdraw: program.methods.initDarkWithdrawCompDef,
+    }[circuit];
+    const sig = await initMethod()
       .accounts({
         compDefAccount: compDefPDA,
         payer: owner.publicKey,
@@ -377,14 +1416,14 @@ describe("Whispr", () => {
       .rpc({
         commitment: "confirmed",
       });
-    console.log("Init compute swap computation definition transaction", sig);
+    console.log(`Init ${circuit} computation definition transaction`, sig);
 
     if (uploadRawCircuit) {
-      const rawCircuit = fs.readFileSync("build/compute_swap.arcis");
+      const rawCircuit = fs.readFileSync(`build/${circuit}.arcis`);
 
       await uploadCircuit(
         provider as anchor.AnchorProvider,
-        "compute_swap",
+        circuit,
         program.programId,
         rawCircuit,
         true